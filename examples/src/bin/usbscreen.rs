@@ -0,0 +1,425 @@
+//USB屏幕命令行工具
+//  usbscreen info                 读取设备信息
+//  usbscreen update firmware.uf2  升级固件(也支持objcopy生成的bin文件)
+//  usbscreen calibrate            校准触摸屏(只支持USB Raw)
+//  usbscreen events               打印触摸和按键事件(只支持USB Raw)
+//  usbscreen stats [watch|reset]  读取性能计数器，watch每秒刷新一次，reset读取后清零
+//  usbscreen replay capture.cap [320x240 out.png]  按原来的时间间隔回放录制文件到设备，或者回放到模拟器并保存为PNG
+//  usbscreen panel [320x240]      显示测试图案，调整反色、Gamma曲线、显存偏移和颜色顺序后保存到设备
+//  usbscreen tearing on|off       开关TE同步(只支持320x240)
+//  usbscreen tearing pin 11|none  设置屏幕TE输出接的引脚，重启后生效
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use usb_screen_client::emulator::Emulator;
+use usb_screen_client::usb_screen::UsbScreen;
+use usb_screen_client::{capture, firmware, usb_screen};
+use usb_screen_core::frame::FrameError;
+use usb_screen_core::panel::{ColorOrder, Inversion, PanelConfig, GAMMA_LEN};
+use usb_screen_core::settings::{CONFIG_TE_PIN, CONFIG_TE_SYNC};
+use usb_screen_core::stats::{DeviceStats, Timing};
+
+const USAGE: &str = "usage:
+  usbscreen info
+  usbscreen update <firmware.uf2|firmware.bin>
+  usbscreen calibrate
+  usbscreen events
+  usbscreen stats [watch|reset]
+  usbscreen replay <capture> [<width>x<height> <out.png>]
+  usbscreen panel [<width>x<height>]
+  usbscreen tearing on|off
+  usbscreen tearing pin <gpio|none>";
+
+fn main() -> Result<()>{
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(|s| s.as_str()).collect::<Vec<_>>().as_slice(){
+        ["info"] => info(),
+        ["update", path] => update(path),
+        ["calibrate"] => calibrate(),
+        ["events"] => events(),
+        ["stats"] => stats(false, false),
+        ["stats", "watch"] => stats(true, false),
+        ["stats", "reset"] => stats(false, true),
+        ["replay", path] => replay(path),
+        ["replay", path, size, out] => replay_emulator(path, size, out),
+        ["panel"] => panel(None),
+        ["panel", size] => panel(Some(size)),
+        ["tearing", "on"] => set_config(CONFIG_TE_SYNC, 1),
+        ["tearing", "off"] => set_config(CONFIG_TE_SYNC, 0),
+        ["tearing", "pin", "none"] => set_config(CONFIG_TE_PIN, 0),
+        ["tearing", "pin", pin] => {
+            let pin: u32 = pin.parse().map_err(|_| anyhow!("invalid pin: {pin}"))?;
+            set_config(CONFIG_TE_PIN, pin + 1)?;
+            println!("reconnect the device to use the new TE pin");
+            Ok(())
+        }
+        _ => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "usb-raw")]
+fn info() -> Result<()>{
+    let interface = usb_screen::open_usb_screen()?.ok_or(anyhow!("usb screen not found"))?;
+    let info = usb_screen::read_info(&interface)?;
+    println!("{info}");
+    warn_panel_mismatch(&info);
+    Ok(())
+}
+
+#[cfg(feature = "usb-serial")]
+fn info() -> Result<()>{
+    let mut port = open_serial()?;
+    let info = usb_screen::read_info_serial(port.as_mut())?;
+    println!("{info}");
+    warn_panel_mismatch(&info);
+    Ok(())
+}
+
+//识别出的控制器与固件不符(panel_mismatch=1)时，固件仍按编译时选择的屏幕初始化，需要换用对应屏幕的固件
+fn warn_panel_mismatch(info: &str){
+    if info.split(';').any(|item| item == "panel_mismatch=1"){
+        let panel = info.split(';').find_map(|item| item.strip_prefix("panel=")).unwrap_or("unknown");
+        eprintln!("warning: detected {panel} panel, but the firmware is built for another controller, flash the firmware for this panel");
+    }
+}
+
+#[cfg(feature = "usb-raw")]
+fn update(path: &str) -> Result<()>{
+    let image = firmware::load_firmware(path)?;
+    let interface = usb_screen::open_usb_screen()?.ok_or(anyhow!("usb screen not found"))?;
+    println!("uploading {} bytes...", image.len());
+    usb_screen::update_firmware(&image, &interface)?;
+    println!("firmware verified, device is rebooting");
+    Ok(())
+}
+
+#[cfg(feature = "usb-serial")]
+fn update(path: &str) -> Result<()>{
+    let image = firmware::load_firmware(path)?;
+    let mut port = open_serial()?;
+    println!("uploading {} bytes...", image.len());
+    usb_screen::update_firmware_serial(&image, port.as_mut())?;
+    println!("firmware verified, device is rebooting");
+    Ok(())
+}
+
+#[cfg(feature = "usb-hid")]
+fn info() -> Result<()>{
+    let mut screen = usb_screen_client::hid::HidScreen::open()?;
+    let info = screen.read_info()?;
+    println!("{info}");
+    warn_panel_mismatch(&info);
+    Ok(())
+}
+
+#[cfg(feature = "usb-hid")]
+fn update(path: &str) -> Result<()>{
+    let image = firmware::load_firmware(path)?;
+    let mut screen = usb_screen_client::hid::HidScreen::open()?;
+    println!("uploading {} bytes...", image.len());
+    screen.update_firmware(&image)?;
+    println!("firmware verified, device is rebooting");
+    Ok(())
+}
+
+//依次在3个位置显示十字，点击后计算校准参数写入设备
+#[cfg(feature = "usb-raw")]
+fn calibrate() -> Result<()>{
+    use image::{Rgb, RgbImage};
+    use usb_screen_core::touch::Calibration;
+
+    let mut interface = usb_screen::open_usb_screen()?.ok_or(anyhow!("usb screen not found"))?;
+    let info = usb_screen::read_info(&interface)?;
+    let (width, height) = screen_size(&info).ok_or(anyhow!("unknown screen size: {info}"))?;
+    let targets = [(20, 20), (width - 20, height / 2), (width / 2, height - 20)];
+    let mut raw = vec![];
+    usb_screen::set_events(true, &interface)?;
+    for (x, y) in targets{
+        usb_screen::clear_screen(Rgb([0, 0, 0]), &interface, width, height)?;
+        let mut cross = RgbImage::new(21, 21);
+        for i in 0..21{
+            cross.put_pixel(i, 10, Rgb([255, 255, 255]));
+            cross.put_pixel(10, i, Rgb([255, 255, 255]));
+        }
+        usb_screen::draw_rgb_image(x - 10, y - 10, &cross, &interface)?;
+        println!("touch the cross at {x},{y}");
+        //使用抬起之前的最后一个点
+        let mut last = None;
+        loop{
+            let event = usb_screen::read_touch_event(&interface)?;
+            if event.pressed{
+                last = Some((event.raw_x as f32, event.raw_y as f32));
+            }else if let Some(point) = last{
+                raw.push(point);
+                break;
+            }
+        }
+    }
+    usb_screen::set_events(false, &interface)?;
+    usb_screen::clear_screen(Rgb([0, 0, 0]), &interface, width, height)?;
+    let screen = targets.map(|(x, y)| (x as f32, y as f32));
+    let calibration = Calibration::from_points([raw[0], raw[1], raw[2]], screen).ok_or(anyhow!("invalid touch points"))?;
+    interface.set_touch_calibration(&calibration)?;
+    println!("calibration saved: {:?}", calibration.matrix);
+    Ok(())
+}
+
+#[cfg(not(feature = "usb-raw"))]
+fn calibrate() -> Result<()>{
+    Err(anyhow!("touch calibration requires the usb-raw feature"))
+}
+
+#[cfg(feature = "usb-raw")]
+fn events() -> Result<()>{
+    let interface = usb_screen::open_usb_screen()?.ok_or(anyhow!("usb screen not found"))?;
+    for event in usb_screen::events(&interface)?{
+        println!("{:?}", event?);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "usb-raw"))]
+fn events() -> Result<()>{
+    Err(anyhow!("device events require the usb-raw feature"))
+}
+
+fn stats(watch: bool, reset: bool) -> Result<()>{
+    let mut screen = open_screen()?;
+    let mut last: Option<DeviceStats> = None;
+    loop{
+        let stats = screen.read_stats(reset)?;
+        print_stats(&stats, last.as_ref());
+        if !watch{
+            return Ok(());
+        }
+        last = Some(stats);
+        std::thread::sleep(Duration::from_secs(1));
+        println!();
+    }
+}
+
+fn set_config(key: u16, value: u32) -> Result<()>{
+    open_screen()?.set_config(key, value)
+}
+
+fn replay(path: &str) -> Result<()>{
+    let capture = std::fs::read(path)?;
+    let mut screen = open_screen()?;
+    let mismatches = capture::replay(&capture, &mut screen, true)?;
+    println!("replayed {path}, {mismatches} responses differ from the capture");
+    Ok(())
+}
+
+fn replay_emulator(path: &str, size: &str, out: &str) -> Result<()>{
+    let capture = std::fs::read(path)?;
+    let (width, height) = parse_size(size)?;
+    let mut screen = Emulator::new(width, height);
+    let mismatches = capture::replay(&capture, &mut screen, false)?;
+    screen.save_png(out)?;
+    println!("replayed {path} into {out}, {mismatches} responses differ from the capture");
+    Ok(())
+}
+
+//Gamma曲线预设：(名称, 正极性, 负极性)，全0表示屏幕控制器的默认曲线
+type GammaPreset = (&'static str, [u8; GAMMA_LEN], [u8; GAMMA_LEN]);
+
+//ST7789的曲线只有14字节，后面补0
+const ST7789_GAMMA: [GammaPreset; 2] = [
+    ("controller default", [0; GAMMA_LEN], [0; GAMMA_LEN]),
+    ("TFT_eSPI",
+        [0xD0, 0x04, 0x0D, 0x11, 0x13, 0x2B, 0x3F, 0x54, 0x4C, 0x18, 0x0D, 0x0B, 0x1F, 0x23, 0, 0],
+        [0xD0, 0x04, 0x0C, 0x11, 0x13, 0x2C, 0x3F, 0x44, 0x51, 0x2F, 0x1F, 0x1F, 0x20, 0x23, 0, 0]),
+];
+
+//ILI9341的曲线15字节
+const ILI9341_GAMMA: [GammaPreset; 2] = [
+    ("controller default", [0; GAMMA_LEN], [0; GAMMA_LEN]),
+    ("Adafruit ILI9341",
+        [0x0F, 0x31, 0x2B, 0x0C, 0x0E, 0x08, 0x4E, 0xF1, 0x37, 0x07, 0x10, 0x03, 0x0E, 0x09, 0x00, 0],
+        [0x00, 0x0E, 0x14, 0x03, 0x11, 0x07, 0x31, 0xC1, 0x48, 0x08, 0x0F, 0x0C, 0x31, 0x36, 0x0F, 0]),
+];
+
+const ST7735_GAMMA: [GammaPreset; 2] = [
+    ("controller default", [0; GAMMA_LEN], [0; GAMMA_LEN]),
+    ("Adafruit ST7735R",
+        [0x02, 0x1C, 0x07, 0x12, 0x37, 0x32, 0x29, 0x2D, 0x29, 0x25, 0x2B, 0x39, 0x00, 0x01, 0x03, 0x10],
+        [0x03, 0x1D, 0x07, 0x06, 0x2E, 0x2C, 0x29, 0x2D, 0x2E, 0x2E, 0x37, 0x3F, 0x00, 0x00, 0x02, 0x10]),
+];
+
+//依次显示测试图案，修改只预览不写Flash，按s保存
+fn panel(size: Option<&str>) -> Result<()>{
+    let mut screen = open_screen()?;
+    let info = screen.read_info()?;
+    let (width, height) = match size{
+        Some(size) => parse_size(size)?,
+        None => screen_size(&info).ok_or(anyhow!("unknown screen size: {info}, pass <width>x<height>"))?,
+    };
+    //使用设备识别出的控制器，没有识别或与固件不符时按尺寸判断：240x240和320x240是ST7789，其他是ST7735
+    warn_panel_mismatch(&info);
+    let mismatch = info.split(';').any(|item| item == "panel_mismatch=1");
+    let panel = info.split(';').find_map(|item| item.strip_prefix("panel=")).filter(|_| !mismatch);
+    let presets: &[GammaPreset] = match panel{
+        Some("ILI9341") => &ILI9341_GAMMA,
+        Some("ST7789") => &ST7789_GAMMA,
+        Some("ST7735") => &ST7735_GAMMA,
+        _ if width >= 240 => &ST7789_GAMMA,
+        _ => &ST7735_GAMMA,
+    };
+    let patterns = test_patterns(width as u32, height as u32);
+    let mut panel = PanelConfig::default();
+    let (mut pattern, mut preset) = (0, 0);
+    let stdin = std::io::stdin();
+    loop{
+        screen.set_panel(&panel, false)?;
+        let (name, image) = &patterns[pattern];
+        screen.draw_rgb_image(0, 0, image)?;
+        println!("pattern: {name}  inversion: {:?}  gamma: {}  offset: {},{}  color order: {:?}",
+            panel.inversion, presets[preset].0, panel.offset_x, panel.offset_y, panel.color_order);
+        println!("[enter] next pattern  [i] inversion  [g] next gamma curve  [x+|x-|y+|y-] offset  [c] color order  [s] save  [q] quit");
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0{
+            return Ok(());
+        }
+        match line.trim(){
+            "" => pattern = (pattern + 1) % patterns.len(),
+            //不知道固件的默认值，三种状态轮流切换
+            "i" => panel.inversion = match panel.inversion{
+                Inversion::Default => Inversion::On,
+                Inversion::On => Inversion::Off,
+                Inversion::Off => Inversion::Default,
+            },
+            "g" => {
+                preset = (preset + 1) % presets.len();
+                (_, panel.positive, panel.negative) = presets[preset];
+            }
+            //边框缺了一边时向那一边移动，另一边出现花屏时反过来
+            "x+" => panel.offset_x += 1,
+            "x-" => panel.offset_x = panel.offset_x.saturating_sub(1),
+            "y+" => panel.offset_y += 1,
+            "y-" => panel.offset_y = panel.offset_y.saturating_sub(1),
+            "c" => panel.color_order = match panel.color_order{
+                ColorOrder::Default => ColorOrder::Bgr,
+                ColorOrder::Bgr => ColorOrder::Rgb,
+                ColorOrder::Rgb => ColorOrder::Default,
+            },
+            "s" => {
+                screen.set_panel(&panel, true)?;
+                println!("panel settings saved");
+                return Ok(());
+            }
+            "q" => {
+                println!("not saved, the device restores its settings after a reboot");
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+}
+
+fn test_patterns(width: u32, height: u32) -> Vec<(&'static str, image::RgbImage)>{
+    use image::{Rgb, RgbImage};
+    const BARS: [[u8; 3]; 8] = [
+        [255, 255, 255], [255, 255, 0], [0, 255, 255], [0, 255, 0],
+        [255, 0, 255], [255, 0, 0], [0, 0, 255], [0, 0, 0],
+    ];
+    //灰阶分16级，方便看出相邻两级是否能分辨
+    let ramp = RgbImage::from_fn(width, height, |x, _| {
+        let level = (x * 16 / width * 17) as u8;
+        Rgb([level; 3])
+    });
+    //一像素宽的白色边框，四边都能看到时偏移正确；左上角为红色，显示为蓝色时颜色顺序反了
+    let edges = RgbImage::from_fn(width, height, |x, y| {
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1{
+            Rgb([255, 255, 255])
+        }else if x < 16 && y < 16{
+            Rgb([255, 0, 0])
+        }else if x >= width - 16 && y < 16{
+            Rgb([0, 255, 0])
+        }else if x < 16 && y >= height - 16{
+            Rgb([0, 0, 255])
+        }else{
+            Rgb([0, 0, 0])
+        }
+    });
+    let bars = RgbImage::from_fn(width, height, |x, _| Rgb(BARS[(x * 8 / width) as usize]));
+    //红绿蓝三行的连续渐变
+    let gradients = RgbImage::from_fn(width, height, |x, y| {
+        let level = (x * 255 / (width - 1)) as u8;
+        let mut pixel = [0; 3];
+        pixel[(y * 3 / height) as usize] = level;
+        Rgb(pixel)
+    });
+    let checker = RgbImage::from_fn(width, height, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0{ Rgb([255, 255, 255]) }else{ Rgb([0, 0, 0]) }
+    });
+    vec![
+        ("edges (red top-left, green top-right, blue bottom-left)", edges),
+        ("gray ramp", ramp),
+        ("color bars", bars),
+        ("rgb gradients", gradients),
+        ("checkerboard", checker),
+        ("white", RgbImage::from_pixel(width, height, Rgb([255, 255, 255]))),
+        ("black", RgbImage::new(width, height)),
+    ]
+}
+
+//帧率根据两次读取之间绘制的帧数计算
+fn print_stats(stats: &DeviceStats, last: Option<&DeviceStats>){
+    let ms = |t: &Timing| format!("avg {:>6.1}ms  max {:>6.1}ms", t.avg_us as f32 / 1000., t.max_us as f32 / 1000.);
+    println!("uptime      {:.1}s", stats.uptime_ms as f32 / 1000.);
+    print!("frames      {}", stats.frames);
+    if let Some(last) = last{
+        let elapsed = stats.uptime_ms.wrapping_sub(last.uptime_ms).max(1) as f32 / 1000.;
+        print!("  ({:.1} fps)", stats.frames.wrapping_sub(last.frames) as f32 / elapsed);
+    }
+    println!();
+    println!("dropped     {}", stats.dropped_frames);
+    println!("errors      {}", stats.decode_errors);
+    print!("rejected    {}", stats.rejected_frames);
+    if let Some(error) = FrameError::from_code(stats.last_error){
+        print!("  (last: {error:?})");
+    }
+    println!();
+    println!("receive     {}", ms(&stats.receive));
+    println!("decompress  {}", ms(&stats.decompress));
+    println!("draw        {}", ms(&stats.draw));
+    println!("heap        {}K used  {}K peak  {}K total", stats.heap_used / 1024, stats.heap_peak / 1024, stats.heap_size / 1024);
+}
+
+#[cfg(feature = "usb-raw")]
+fn open_screen() -> Result<impl UsbScreen>{
+    usb_screen::open_usb_screen()?.ok_or(anyhow!("usb screen not found"))
+}
+
+#[cfg(feature = "usb-serial")]
+fn open_screen() -> Result<impl UsbScreen>{
+    open_serial()
+}
+
+#[cfg(feature = "usb-hid")]
+fn open_screen() -> Result<impl UsbScreen>{
+    usb_screen_client::hid::HidScreen::open()
+}
+
+fn parse_size(size: &str) -> Result<(u16, u16)>{
+    size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or(anyhow!("invalid screen size: {size}"))
+}
+
+//设备信息以 USBSCR320x240; 开头
+fn screen_size(info: &str) -> Option<(u16, u16)>{
+    let size = info.strip_prefix("USBSCR")?.split(';').next()?;
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+#[cfg(feature = "usb-serial")]
+fn open_serial() -> Result<Box<dyn serialport::SerialPort>>{
+    let devices = usb_screen::find_usb_serial_device()?;
+    let device = devices.first().ok_or(anyhow!("usb screen not found"))?;
+    Ok(serialport::new(&device.port_name, 115_200).open()?)
+}
@@ -0,0 +1,147 @@
+//录制和回放：Recorder包装任意UsbScreen，把发送的命令包、数据和读取到的回复写入录制文件(格式见usb_screen_core::capture)。
+//回放时按录制的顺序和时间间隔发送到设备或模拟器(emulator.rs)，用于重现用户屏幕上的问题，也可以作为回归测试。
+//  let mut screen = Recorder::create(usb_screen::open_usb_screen()?.unwrap(), "session.cap")?;
+//不方便修改程序时，设置环境变量USB_SCREEN_CAPTURE为文件路径，USB Raw、串口和HID的所有传输都会被录制(包括usb_screen中的函数)。
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use usb_screen_core::capture::{capture_header, records, Record, RecordKind};
+
+use crate::usb_screen::UsbScreen;
+
+pub struct Recorder<S: UsbScreen, W: Write = BufWriter<File>>{
+    screen: S,
+    writer: W,
+    start: Instant,
+}
+
+impl<S: UsbScreen> Recorder<S>{
+    /// 创建录制文件，已存在时覆盖
+    pub fn create<P: AsRef<Path>>(screen: S, path: P) -> Result<Self>{
+        Self::new(screen, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<S: UsbScreen, W: Write> Recorder<S, W>{
+    pub fn new(screen: S, mut writer: W) -> Result<Self>{
+        writer.write_all(&capture_header())?;
+        Ok(Self { screen, writer, start: Instant::now() })
+    }
+
+    /// 结束录制，返回被包装的屏幕和录制数据
+    pub fn finish(mut self) -> Result<(S, W)>{
+        self.writer.flush()?;
+        Ok((self.screen, self.writer))
+    }
+
+    fn record(&mut self, kind: RecordKind, data: &[u8]) -> Result<()>{
+        let time_us = self.start.elapsed().as_micros() as u64;
+        self.writer.write_all(&Record{ kind, time_us, data }.encode())?;
+        Ok(())
+    }
+}
+
+//先记录再发送，发送失败时录制文件中也有这次传输
+impl<S: UsbScreen, W: Write> UsbScreen for Recorder<S, W>{
+    fn frame_buffer(&self) -> Option<usize>{
+        self.screen.frame_buffer()
+    }
+
+    fn stripe(&self) -> Option<usize>{
+        self.screen.stripe()
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        self.record(RecordKind::Packet, packet)?;
+        self.screen.write_packet(packet)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        self.record(RecordKind::Data, data)?;
+        self.screen.write_data(data)
+    }
+
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>{
+        let response = self.screen.read_response(timeout)?;
+        self.record(RecordKind::Response, &response)?;
+        Ok(response)
+    }
+}
+
+const CAPTURE_ENV: &str = "USB_SCREEN_CAPTURE";
+
+struct GlobalCapture{
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+static GLOBAL_CAPTURE: OnceLock<Mutex<Option<GlobalCapture>>> = OnceLock::new();
+
+//由USB Raw、串口和HID的UsbScreen实现在发送和读取时调用，所有到达设备的传输都经过这里。第一次调用时根据环境变量创建录制文件，每条记录都立即写入文件(程序崩溃时不丢失)
+pub(crate) fn record_global(kind: RecordKind, data: &[u8]){
+    let capture = GLOBAL_CAPTURE.get_or_init(|| {
+        let capture = std::env::var_os(CAPTURE_ENV).and_then(|path| {
+            let mut writer = BufWriter::new(File::create(path).ok()?);
+            writer.write_all(&capture_header()).ok()?;
+            Some(GlobalCapture { writer, start: Instant::now() })
+        });
+        Mutex::new(capture)
+    });
+    if let Some(capture) = capture.lock().unwrap().as_mut(){
+        let time_us = capture.start.elapsed().as_micros() as u64;
+        //录制失败不影响传输
+        let _ = capture.writer.write_all(&Record{ kind, time_us, data }.encode());
+        let _ = capture.writer.flush();
+    }
+}
+
+/// 回放录制文件，realtime为true时按录制时的时间间隔发送(回放到模拟器时不需要)。
+/// 录制时读取过回复的位置同样读取一次(设备在主机读取之前不会继续处理)，返回回复与录制时不同的次数
+pub fn replay<S: UsbScreen>(capture: &[u8], screen: &mut S, realtime: bool) -> Result<usize>{
+    let records = records(capture).ok_or(anyhow!("not a capture file"))?;
+    let start = Instant::now();
+    let mut mismatches = 0;
+    for record in records{
+        if realtime{
+            let time = Duration::from_micros(record.time_us);
+            if let Some(wait) = time.checked_sub(start.elapsed()){
+                std::thread::sleep(wait);
+            }
+        }
+        match record.kind{
+            RecordKind::Packet => screen.write_packet(record.data)?,
+            RecordKind::Data => screen.write_data(record.data)?,
+            RecordKind::Response => {
+                if screen.read_response(Duration::from_secs(5))? != record.data{
+                    mismatches += 1;
+                }
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn replay_reproduces_framebuffer(){
+        let mut recorder = Recorder::new(Emulator::new(160, 128), Vec::new()).unwrap();
+        let red = 0xF800u16.to_be_bytes().repeat(40 * 30);
+        recorder.draw_rgb565(&red, 100, 100, 40, 30).unwrap();
+        recorder.read_info().unwrap();
+        let (recorded, capture) = recorder.finish().unwrap();
+
+        let mut replayed = Emulator::new(160, 128);
+        assert_eq!(replay(&capture, &mut replayed, false).unwrap(), 0);
+        assert_eq!(replayed.framebuffer(), recorded.framebuffer());
+        assert_eq!(replayed.pixel(139, 127), Some(0xF800));
+    }
+}
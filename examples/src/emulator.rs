@@ -0,0 +1,575 @@
+//设备模拟器：在主机上运行与固件相同的协议解析(usb_screen_core::receiver)和命令处理(usb_screen_core::command)，绘制到内存中的RGB565帧缓冲区。
+//实现了UsbScreen，可以代替真实设备测试主机程序，绘制结果可以保存为PNG，例如:
+//  let mut screen = Emulator::new(320, 240);
+//  screen.draw_rgb_image(0, 0, &img)?;
+//  screen.save_png("screen.png")?;
+//开机画面和固件升级只在内存中校验，不会重启；空闲画面(吃豆人、时钟)不模拟，帧缓冲区初始为黑色。
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures_lite::future::block_on;
+use image::RgbImage;
+use usb_screen_core::clock::ClockTime;
+use usb_screen_core::command::{Command, CommandHandler};
+use usb_screen_core::detect::{Controller, Detection};
+use usb_screen_core::display::Screen;
+use usb_screen_core::frame::{crop_image, frame_buffer_size, FrameError, StripeTracker};
+use usb_screen_core::protocol::{encode_update_result, magic_number, FirmwareUpdate, EVENT_SUB, PACKET_SIZE};
+use usb_screen_core::receiver::{FlashState, Frame, Packet, Receiver};
+use usb_screen_core::rgb565::rgb565_be_to_pixels;
+use usb_screen_core::settings::Settings;
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::scroll::Scroll;
+use usb_screen_core::stats::DeviceStats;
+use usb_screen_core::storage::{SPLASH_CAPACITY, STAGING_CAPACITY};
+use usb_screen_core::update::{Crc32, UpdateError};
+
+use crate::rgb565::rgb565_u16_image_to_rgb;
+use crate::usb_screen::{DeviceEvent, UsbScreen};
+
+//正在写入Flash的数据(固件中的Storage)
+enum FlashWrite{
+    Splash(Vec<u8>),
+    //开始时的错误保留到IMAGE_BB时返回
+    Update{ update: FirmwareUpdate, data: Result<Vec<u8>, UpdateError> },
+}
+
+//模拟的屏幕控制器：显存和当前的滚动、面板设置
+struct Display{
+    width: u16,
+    height: u16,
+    framebuffer: Vec<u16>,
+    scroll: Scroll,
+    panel: PanelConfig,
+}
+
+impl Screen for Display{
+    //设置屏幕窗口后写入像素：超出窗口的像素丢弃，不足时窗口剩余部分不变，超出屏幕的部分裁掉(调用者已经裁剪过)
+    async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+        if width == 0 || height == 0{
+            return;
+        }
+        let rows = pixels.chunks(width as usize).take(height as usize);
+        for (row_y, row) in rows.enumerate(){
+            let py = y as usize + row_y;
+            if py >= self.height as usize{
+                break;
+            }
+            for (col, pixel) in row.iter().enumerate(){
+                let px = x as usize + col;
+                if px >= self.width as usize{
+                    break;
+                }
+                self.framebuffer[py * self.width as usize + px] = *pixel;
+            }
+        }
+    }
+
+    async fn set_scroll(&mut self, scroll: &Scroll){
+        self.scroll = *scroll;
+    }
+
+    async fn set_panel(&mut self, panel: &PanelConfig){
+        self.panel = *panel;
+    }
+}
+
+pub struct Emulator{
+    display: Display,
+    serial_number: String,
+    started: Instant,
+    receiver: Receiver,
+    commands: CommandHandler,
+    writing_flash: Option<FlashWrite>,
+    settings: Settings,
+    time: Option<ClockTime>,
+    splash: Option<Vec<u8>>,
+    firmware: Option<Vec<u8>>,
+    //启动时读取的控制器ID，None表示没有读取
+    detection: Option<Detection>,
+    stats: DeviceStats,
+    //按整帧统计绘制的横条
+    frames: StripeTracker,
+    events: bool,
+    //等待主机读取的回复和事件
+    responses: VecDeque<Vec<u8>>,
+}
+
+impl Emulator{
+    /// 模拟一个横屏宽高为width x height的设备，设备信息以USBSCR{width}x{height}开头
+    pub fn new(width: u16, height: u16) -> Self{
+        //与固件相同：240x320和240x240屏幕的精灵缓存小一些
+        let sprite_cache_capacity = if Self::is_st7789(width, height){ 1024 * 16 }else{ 1024 * 48 };
+        Self{
+            display: Display{
+                width,
+                height,
+                framebuffer: vec![0; width as usize * height as usize],
+                scroll: Scroll::default(),
+                panel: PanelConfig::default(),
+            },
+            serial_number: format!("USBSCR{width}x{height}"),
+            started: Instant::now(),
+            receiver: Receiver::new(width, height, frame_buffer_size(width, height)),
+            commands: CommandHandler::new(width, height, sprite_cache_capacity),
+            writing_flash: None,
+            settings: Settings::default(),
+            time: None,
+            splash: None,
+            firmware: None,
+            detection: None,
+            stats: DeviceStats::new(),
+            frames: StripeTracker::default(),
+            events: false,
+            responses: VecDeque::new(),
+        }
+    }
+
+    //大于160x128的屏幕按ST7789固件模拟
+    fn is_st7789(width: u16, height: u16) -> bool{
+        width as usize * height as usize > 160 * 128
+    }
+
+    pub fn width(&self) -> u16{
+        self.display.width
+    }
+
+    pub fn height(&self) -> u16{
+        self.display.height
+    }
+
+    /// 帧缓冲区(显存)，每个像素一个RGB565值，逐行排列。硬件滚动时屏幕上看到的内容见pixel和to_rgb_image
+    pub fn framebuffer(&self) -> &[u16]{
+        &self.display.framebuffer
+    }
+
+    /// 屏幕上看到的像素(反色后，不模拟Gamma曲线)
+    pub fn pixel(&self, x: u16, y: u16) -> Option<u16>{
+        let display = &self.display;
+        if x >= display.width || y >= display.height{
+            return None;
+        }
+        let pixel = display.framebuffer[y as usize * display.width as usize + display.scroll.memory_column(x) as usize];
+        Some(if display.panel.inversion.inverted(false){ !pixel }else{ pixel })
+    }
+
+    /// 当前的反色和Gamma曲线，保存到Flash的见settings
+    pub fn panel(&self) -> PanelConfig{
+        self.display.panel
+    }
+
+    /// 硬件滚动的区域和偏移
+    pub fn scroll(&self) -> Scroll{
+        self.display.scroll
+    }
+
+    pub fn to_rgb_image(&self) -> RgbImage{
+        let (width, height) = (self.display.width, self.display.height);
+        let pixels: Vec<u16> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter_map(|(x, y)| self.pixel(x, y))
+            .collect();
+        rgb565_u16_image_to_rgb(&pixels, width as u32, height as u32)
+    }
+
+    /// 把屏幕内容保存为PNG
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()>{
+        self.to_rgb_image().save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+
+    /// SetConfg写入的设置
+    pub fn settings(&self) -> &Settings{
+        &self.settings
+    }
+
+    /// SetTime_设置的时间
+    pub fn time(&self) -> Option<ClockTime>{
+        self.time
+    }
+
+    /// 最后一次写入的开机画面数据(可以用storage::decode_splash解析)
+    pub fn splash(&self) -> Option<&[u8]>{
+        self.splash.as_deref()
+    }
+
+    /// 最后一次校验通过的固件
+    pub fn firmware(&self) -> Option<&[u8]>{
+        self.firmware.as_deref()
+    }
+
+    /// 模拟启动时读取到的屏幕控制器ID，之后的设备信息中包含panel=等字段
+    pub fn set_panel_detection(&mut self, detection: Detection){
+        self.detection = Some(detection);
+    }
+
+    /// 模拟触摸或按键，主机开启事件(EventSub)后才会发送
+    pub fn push_event(&mut self, event: DeviceEvent){
+        if !self.events{
+            return;
+        }
+        let data = match event{
+            DeviceEvent::Touch(event) => event.encode().to_vec(),
+            DeviceEvent::Input(event) => event.encode().to_vec(),
+        };
+        self.responses.push_back(data);
+    }
+
+    /// 读取下一个事件，没有事件时返回None
+    pub fn read_event(&mut self) -> Option<DeviceEvent>{
+        let index = self.responses.iter().position(|data| DeviceEvent::decode(data).is_some())?;
+        self.responses.remove(index).and_then(|data| DeviceEvent::decode(&data))
+    }
+
+    //处理收到的一个包，解析与固件的Receiver相同，这里只模拟执行
+    fn handle(&mut self, data: &[u8]){
+        let writing = match self.writing_flash{
+            Some(FlashWrite::Splash(_)) => FlashState::Splash,
+            Some(FlashWrite::Update{ .. }) => FlashState::Update,
+            None => FlashState::Idle,
+        };
+        let now_us = self.started.elapsed().as_micros() as u64;
+        let packet = match self.receiver.handle(data, writing, now_us){
+            Some(packet) => packet,
+            None => return,
+        };
+        match packet{
+            Packet::Frame(frame) => self.draw_image(frame),
+            Packet::Rejected(err) => self.reject(err),
+            Packet::Command(command) => self.execute(command),
+            Packet::SetConfig(config) => {
+                self.settings.set(config.key, config.value);
+            }
+            Packet::SetPanel(cmd) => {
+                if cmd.save{
+                    self.settings.panel = cmd.panel;
+                }
+                self.execute(Command::SetPanel(cmd.panel));
+            }
+            Packet::SetTime(time) => self.time = Some(time),
+            Packet::BeginSplash => self.writing_flash = Some(FlashWrite::Splash(Vec::new())),
+            Packet::BeginUpdate(update) => {
+                let data = if update.len == 0 || update.len as usize > STAGING_CAPACITY{
+                    Err(UpdateError::TooLarge)
+                }else{
+                    Ok(Vec::with_capacity(update.len as usize))
+                };
+                self.writing_flash = Some(FlashWrite::Update{ update, data });
+            }
+            //超出容量的数据丢弃
+            Packet::SplashData(data) => {
+                if let Some(FlashWrite::Splash(splash)) = self.writing_flash.as_mut(){
+                    let len = data.len().min(SPLASH_CAPACITY - splash.len());
+                    splash.extend_from_slice(&data[..len]);
+                }
+            }
+            Packet::UpdateData(data) => {
+                if let Some(FlashWrite::Update{ update, data: firmware }) = self.writing_flash.as_mut(){
+                    match firmware{
+                        Ok(received) if received.len() + data.len() > update.len as usize => *firmware = Err(UpdateError::Length),
+                        Ok(received) => received.extend_from_slice(data),
+                        Err(_) => {}
+                    }
+                }
+            }
+            Packet::FinishSplash => {
+                if let Some(FlashWrite::Splash(data)) = self.writing_flash.take(){
+                    self.splash = Some(data);
+                }
+            }
+            //校验通过后固件会重启，模拟器只保存固件
+            Packet::FinishUpdate => {
+                if let Some(FlashWrite::Update{ update, data }) = self.writing_flash.take(){
+                    let result = data.and_then(|data| {
+                        if data.len() != update.len as usize{
+                            Err(UpdateError::Length)
+                        }else if Crc32::checksum(&data) != update.crc{
+                            Err(UpdateError::Crc)
+                        }else{
+                            self.firmware = Some(data);
+                            Ok(())
+                        }
+                    });
+                    self.responses.push_back(encode_update_result(result).to_vec());
+                }
+            }
+            Packet::CancelWrites => self.writing_flash = None,
+            //模拟器没有引导程序，忽略
+            Packet::BootUsb => (),
+            Packet::ReadInfo => {
+                let info = self.device_info();
+                self.responses.push_back(info.into_bytes());
+            }
+            Packet::ReadStats{ reset } => {
+                self.stats.uptime_ms = self.started.elapsed().as_millis() as u32;
+                self.responses.push_back(self.stats.encode().to_vec());
+                if reset{
+                    self.stats = DeviceStats::new();
+                }
+            }
+        }
+    }
+
+    //与固件的info::device_info相同，模拟器总是正常上电启动
+    fn device_info(&self) -> String{
+        let sprites = self.commands.sprites();
+        let stripe = self.decode_stripe().map(|stripe| format!(";stripe={stripe}")).unwrap_or_default();
+        let mut info = format!(
+            "{};sprite={}/{};frame={}{stripe};reset=power-on;watchdog_resets=0",
+            self.serial_number, sprites.free(), sprites.capacity(), self.frame_buffer_size()
+        );
+        if let Some(detection) = self.detection{
+            let supported: &[Controller] = if Self::is_st7789(self.display.width, self.display.height){
+                &[Controller::St7789, Controller::Ili9341]
+            }else{
+                &[Controller::St7735]
+            };
+            info.push_str(&detection.info(supported));
+        }
+        info
+    }
+
+    //命令与固件的core1一样由CommandHandler执行，模拟的屏幕不会挂起
+    fn execute(&mut self, command: Command){
+        if let Err(err) = block_on(self.commands.handle(&mut self.display, command)){
+            err.record(&mut self.stats);
+        }
+    }
+
+    //与固件的FRAME_BUFFER相同
+    fn frame_buffer_size(&self) -> usize{
+        frame_buffer_size(self.display.width, self.display.height)
+    }
+
+    //与固件的DECODE_STRIPE相同：只有240x320和240x240(ST7789)屏幕在core0解压横条
+    fn decode_stripe(&self) -> Option<usize>{
+        Self::is_st7789(self.display.width, self.display.height).then_some(self.display.width as usize * 2 * 40)
+    }
+
+    fn reject(&mut self, error: FrameError){
+        self.stats.rejected_frames += 1;
+        self.stats.last_error = error as u32;
+    }
+
+    //固件的send_image和core1_task：解压后裁剪到屏幕内绘制
+    fn draw_image(&mut self, frame: Frame){
+        self.stats.receive.record(frame.receive_us);
+        let start = Instant::now();
+        let mut image = match lz4_flex::decompress_size_prepended(&frame.data){
+            Ok(image) => image,
+            Err(_) => {
+                self.stats.decode_errors += 1;
+                return;
+            }
+        };
+        let decompress_us = start.elapsed().as_micros() as u32;
+        let rect = match crop_image(&mut image, &frame.rect, self.display.width, self.display.height){
+            Ok(rect) => rect,
+            Err(err) => {
+                self.reject(err);
+                return;
+            }
+        };
+        let start = Instant::now();
+        let pixels: Vec<u16> = rgb565_be_to_pixels(&image).collect();
+        block_on(self.display.draw_pixels(&pixels, rect.x, rect.y, rect.width, rect.height));
+        self.stats.decompress.record(decompress_us);
+        self.stats.draw.record(start.elapsed().as_micros() as u32);
+        if self.frames.new_frame(&rect){
+            self.stats.frames += 1;
+        }
+    }
+}
+
+impl UsbScreen for Emulator{
+    fn frame_buffer(&self) -> Option<usize>{
+        Some(self.frame_buffer_size())
+    }
+
+    fn stripe(&self) -> Option<usize>{
+        self.decode_stripe()
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        //EventSub在USB任务中处理，不经过Receiver
+        if magic_number(packet) == Some(EVENT_SUB){
+            self.events = packet.len() >= 10 && u16::from_be_bytes([packet[8], packet[9]]) != 0;
+            return Ok(());
+        }
+        self.handle(packet);
+        Ok(())
+    }
+
+    //USB Raw接口的批量传输在设备端按64字节一个包接收
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        for packet in data.chunks(PACKET_SIZE){
+            self.write_packet(packet)?;
+        }
+        Ok(())
+    }
+
+    //与USB Raw接口相同，跳过之前没有读取的事件
+    fn read_response(&mut self, _timeout: Duration) -> Result<Vec<u8>>{
+        while let Some(data) = self.responses.pop_front(){
+            if DeviceEvent::decode(&data).is_none(){
+                return Ok(data);
+            }
+        }
+        Err(anyhow!("read timeout"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usb_screen_core::panel::Inversion;
+    use usb_screen_core::protocol::{ScrollArea, ScrollOffset, SpriteDraw, IMAGE_BB};
+    use crate::usb_screen::{image_frames, image_header, sprite_header};
+    use usb_screen_core::touch::TouchEvent;
+
+    fn rgb565_be(color: u16, len: usize) -> Vec<u8>{
+        color.to_be_bytes().repeat(len)
+    }
+
+    //不能压缩的数据
+    fn noise(len: usize) -> Vec<u8>{
+        let mut seed = 1u32;
+        (0..len).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }).collect()
+    }
+
+    #[test]
+    fn draws_image_and_clips_to_screen(){
+        let mut screen = Emulator::new(160, 128);
+        screen.draw_rgb565(&rgb565_be(0xF800, 20 * 10), 150, 120, 20, 10).unwrap();
+        assert_eq!(screen.pixel(150, 120), Some(0xF800));
+        assert_eq!(screen.pixel(159, 127), Some(0xF800));
+        assert_eq!(screen.pixel(149, 120), Some(0));
+        assert_eq!(screen.read_stats(false).unwrap().frames, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_frames(){
+        let mut screen = Emulator::new(160, 128);
+        screen.draw_rgb565(&rgb565_be(0xF800, 4), 160, 0, 2, 2).unwrap();
+        screen.draw_rgb565(&rgb565_be(0xF800, 4), 0, 0, 0, 4).unwrap();
+        //数据长度与宽高不符
+        screen.draw_rgb565(&rgb565_be(0xF800, 3), 0, 0, 2, 2).unwrap();
+        let stats = screen.read_stats(false).unwrap();
+        assert_eq!((stats.frames, stats.rejected_frames), (0, 3));
+        assert_eq!(FrameError::from_code(stats.last_error), Some(FrameError::SizeMismatch));
+        assert!(screen.framebuffer().iter().all(|p| *p == 0));
+
+        //旧版本的主机整屏发送压缩效果差的图像，超过帧缓冲
+        let noise = noise(160 * 128 * 2);
+        screen.write_packet(&image_header(0, 0, 160, 128)).unwrap();
+        screen.write_data(&lz4_flex::compress_prepend_size(&noise)).unwrap();
+        screen.write_packet(&IMAGE_BB.to_be_bytes()).unwrap();
+        let stats = screen.read_stats(false).unwrap();
+        assert_eq!(FrameError::from_code(stats.last_error), Some(FrameError::TooLarge));
+    }
+
+    #[test]
+    fn large_frames_are_split_into_stripes(){
+        let mut screen = Emulator::new(320, 240);
+        let noise = noise(320 * 240 * 2);
+        assert!(image_frames(&noise, 0, 0, 320, 240, screen.frame_buffer(), None).len() > 1);
+        screen.draw_rgb565(&noise, 0, 0, 320, 240).unwrap();
+        let stats = screen.read_stats(false).unwrap();
+        //分成横条的一帧按一帧统计
+        assert_eq!(stats.frames, 1);
+        assert_eq!(stats.rejected_frames, 0);
+        assert!(screen.framebuffer().iter().copied().eq(rgb565_be_to_pixels(&noise)));
+
+        //设备报告了stripe时，压缩效果好的图像也分成横条，让设备同时解压和绘制
+        let red = rgb565_be(0xF800, 320 * 240);
+        assert_eq!(image_frames(&red, 0, 0, 320, 240, screen.frame_buffer(), None).len(), 1);
+        assert_eq!(image_frames(&red, 0, 0, 320, 240, screen.frame_buffer(), screen.stripe()).len(), 6);
+        screen.draw_rgb565(&red, 0, 0, 320, 240).unwrap();
+        assert_eq!(screen.read_stats(false).unwrap().frames, 2);
+        assert_eq!(screen.pixel(319, 239), Some(0xF800));
+    }
+
+    #[test]
+    fn sprite_color_key_is_transparent(){
+        let mut screen = Emulator::new(160, 128);
+        let mut sprite = rgb565_be(0x001F, 4);
+        sprite[2..4].copy_from_slice(&0xFFFFu16.to_be_bytes());
+        screen.upload_sprite(&sprite, 1, 2, 2, Some(0xFFFF)).unwrap();
+        screen.draw_sprites(&[SpriteDraw{ id: 1, x: 10, y: 10 }]).unwrap();
+        assert_eq!(screen.pixel(10, 10), Some(0x001F));
+        assert_eq!(screen.pixel(11, 10), Some(0));
+        assert_eq!(screen.pixel(11, 11), Some(0x001F));
+    }
+
+    #[test]
+    fn failed_sprite_upload_keeps_old_sprite(){
+        let mut screen = Emulator::new(160, 128);
+        screen.upload_sprite(&rgb565_be(0x001F, 4), 1, 2, 2, None).unwrap();
+        //数据损坏
+        screen.write_packet(&sprite_header(1, 2, 2, None).encode()).unwrap();
+        screen.write_data(&[8, 0, 0, 0, 0xFF]).unwrap();
+        screen.write_packet(&IMAGE_BB.to_be_bytes()).unwrap();
+        //长度与宽高不符
+        screen.upload_sprite(&rgb565_be(0xF800, 2), 1, 2, 2, None).unwrap();
+        let stats = screen.read_stats(false).unwrap();
+        assert_eq!((stats.decode_errors, stats.rejected_frames), (1, 1));
+        screen.draw_sprites(&[SpriteDraw{ id: 1, x: 0, y: 0 }]).unwrap();
+        assert_eq!(screen.pixel(1, 1), Some(0x001F));
+    }
+
+    #[test]
+    fn panel_preview_and_save(){
+        let mut screen = Emulator::new(160, 128);
+        screen.draw_rgb565(&rgb565_be(0xF800, 4), 0, 0, 2, 2).unwrap();
+        let mut panel = PanelConfig{ inversion: Inversion::On, ..Default::default() };
+        screen.set_panel(&panel, false).unwrap();
+        assert_eq!(screen.pixel(0, 0), Some(!0xF800));
+        assert_eq!(screen.settings().panel, PanelConfig::default());
+        panel.positive[0] = 0xD0;
+        screen.set_panel(&panel, true).unwrap();
+        assert_eq!(screen.settings().panel, panel);
+    }
+
+    #[test]
+    fn responses_and_events(){
+        let mut screen = Emulator::new(320, 240);
+        assert!(screen.read_info().unwrap().starts_with("USBSCR320x240;sprite=16384/16384"));
+        assert!(screen.update_firmware(&[1, 2, 3, 4]).is_ok());
+        assert_eq!(screen.firmware(), Some(&[1u8, 2, 3, 4][..]));
+
+        let touch = DeviceEvent::Touch(TouchEvent{ pressed: true, x: 1, y: 2, raw_x: 3, raw_y: 4, pressure: 5 });
+        screen.push_event(touch);
+        assert_eq!(screen.read_event(), None);
+        let mut enable = EVENT_SUB.to_be_bytes().to_vec();
+        enable.extend_from_slice(&1u16.to_be_bytes());
+        screen.write_packet(&enable).unwrap();
+        screen.push_event(touch);
+        assert_eq!(screen.read_event(), Some(touch));
+        //读取回复时跳过没有读取的事件
+        screen.push_event(touch);
+        assert!(screen.read_info().is_ok());
+        assert_eq!(screen.read_event(), None);
+
+        //与固件相同，识别出其他控制器时报告panel_mismatch
+        screen.set_panel_detection(Detection{ controller: Some(Controller::St7735), id: 0x7C89F0, status: 0 });
+        assert!(screen.read_info().unwrap().ends_with(";panel=ST7735;panel_id=7C89F0;panel_status=00000000;panel_mismatch=1"));
+    }
+
+    #[test]
+    fn scroll_offset_needs_area(){
+        let mut screen = Emulator::new(320, 240);
+        screen.write_packet(&ScrollOffset(10).encode()).unwrap();
+        assert_eq!(screen.scroll(), Scroll::default());
+        screen.write_packet(&ScrollArea{ start: 0, len: 100 }.encode()).unwrap();
+        screen.write_packet(&ScrollOffset(110).encode()).unwrap();
+        assert_eq!(screen.scroll(), Scroll{ start: 0, len: 100, offset: 10 });
+    }
+}
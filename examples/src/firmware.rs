@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+use usb_screen_core::storage::FIRMWARE_CAPACITY;
+
+//固件在Flash中的起始地址
+const FLASH_BASE: u32 = 0x1000_0000;
+
+const UF2_MAGIC_START0: u32 = 0x0A32_4655;
+const UF2_MAGIC_START1: u32 = 0x9E5D_5157;
+const UF2_MAGIC_END: u32 = 0x0AB1_6F30;
+const UF2_BLOCK_SIZE: usize = 512;
+
+/// 读取固件文件，支持elf2uf2-rs生成的uf2文件和objcopy生成的bin文件，返回从0x10000000开始的固件数据
+pub fn load_firmware(path: &str) -> Result<Vec<u8>>{
+    let data = std::fs::read(path)?;
+    let image = if path.to_lowercase().ends_with(".uf2"){
+        uf2_to_bin(&data)?
+    }else{
+        data
+    };
+    if image.is_empty(){
+        return Err(anyhow!("firmware is empty"));
+    }
+    if image.len() > FIRMWARE_CAPACITY{
+        return Err(anyhow!("firmware is {} bytes, device accepts at most {FIRMWARE_CAPACITY}", image.len()));
+    }
+    Ok(image)
+}
+
+/// 把uf2文件中的数据块按地址拼接成bin，中间的空隙填充0xFF
+pub fn uf2_to_bin(data: &[u8]) -> Result<Vec<u8>>{
+    let read_u32 = |block: &[u8], offset: usize| u32::from_le_bytes([block[offset], block[offset+1], block[offset+2], block[offset+3]]);
+    let mut image = vec![];
+    for block in data.chunks(UF2_BLOCK_SIZE){
+        if block.len() != UF2_BLOCK_SIZE
+            || read_u32(block, 0) != UF2_MAGIC_START0
+            || read_u32(block, 4) != UF2_MAGIC_START1
+            || read_u32(block, 508) != UF2_MAGIC_END{
+            return Err(anyhow!("invalid uf2 block"));
+        }
+        let address = read_u32(block, 12);
+        let size = read_u32(block, 16) as usize;
+        if address < FLASH_BASE || size > 476{
+            return Err(anyhow!("uf2 block outside flash: {address:#x}"));
+        }
+        let offset = (address - FLASH_BASE) as usize;
+        if image.len() < offset + size{
+            image.resize(offset + size, 0xFF);
+        }
+        image[offset..offset + size].copy_from_slice(&block[32..32 + size]);
+    }
+    Ok(image)
+}
@@ -0,0 +1,63 @@
+use std::{f32::consts::PI, time::Duration};
+
+use anyhow::Result;
+use image::{Rgb, RgbImage};
+use usb_screen_core::{imageproc::Projection, protocol::{DrawWarp, INTERPOLATION_BILINEAR}};
+
+use crate::rgb565::{rgb888_to_rgb565_be, Rgb565Pixel};
+
+//指针图像大小
+const NEEDLE_WIDTH: u16 = 8;
+const NEEDLE_HEIGHT: u16 = 56;
+//表盘区域大小
+const DIAL_SIZE: u16 = 120;
+
+//指针只上传一次，之后每帧只发送一条DrawWarp指令
+pub fn draw(
+    #[cfg(feature = "usb-serial")]
+    port: &mut dyn serialport::SerialPort,
+    #[cfg(feature = "usb-raw")]
+    interface:&nusb::Interface,
+    width: u16, height: u16) -> Result<()>{
+    let mut needle = RgbImage::new(NEEDLE_WIDTH as u32, NEEDLE_HEIGHT as u32);
+    for (x, _y, p) in needle.enumerate_pixels_mut(){
+        *p = if x == 0 || x == NEEDLE_WIDTH as u32 - 1 { Rgb([120, 0, 0]) } else { Rgb([255, 40, 40]) };
+    }
+    let rgb565 = rgb888_to_rgb565_be(&needle, NEEDLE_WIDTH as usize, NEEDLE_HEIGHT as usize);
+
+    #[cfg(feature = "usb-serial")]
+    crate::usb_screen::upload_sprite_serial(&rgb565, 0, NEEDLE_WIDTH, NEEDLE_HEIGHT, None, port)?;
+    #[cfg(feature = "usb-raw")]
+    crate::usb_screen::upload_sprite(&rgb565, 0, NEEDLE_WIDTH, NEEDLE_HEIGHT, None, interface)?;
+
+    let x = width/2 - DIAL_SIZE/2;
+    let y = height/2 - DIAL_SIZE/2;
+    let center = DIAL_SIZE as f32 / 2.0;
+    let mut angle = 0.0;
+
+    loop{
+        //以指针底部中点为轴旋转，再移动到表盘中心
+        let projection = Projection::translate(center, center)
+            * Projection::rotate(angle)
+            * Projection::translate(-(NEEDLE_WIDTH as f32) / 2.0, -(NEEDLE_HEIGHT as f32));
+        let warp = DrawWarp{
+            id: 0,
+            x,
+            y,
+            width: DIAL_SIZE,
+            height: DIAL_SIZE,
+            background: Rgb565Pixel::from_rgb(0, 0, 0).0,
+            //双线性插值，旋转后的指针边缘更平滑
+            interpolation: INTERPOLATION_BILINEAR,
+            matrix: projection.matrix(),
+        };
+
+        #[cfg(feature = "usb-serial")]
+        crate::usb_screen::draw_warp_serial(&warp, port)?;
+        #[cfg(feature = "usb-raw")]
+        crate::usb_screen::draw_warp(&warp, interface)?;
+
+        angle = (angle + PI / 30.0) % (PI * 2.0);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,108 @@
+//通过Linux的hidraw传输，不需要libusb和串口权限(需要/dev/hidrawN的读写权限，可以用udev规则设置)
+
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use usb_screen_core::capture::RecordKind;
+use usb_screen_core::hid::{encode_reports, ReportAssembler, HID_PAYLOAD_SIZE, HID_REPORT_SIZE};
+
+use crate::capture::record_global;
+use crate::usb_screen::UsbScreen;
+
+//与固件中的VID/PID相同
+const HID_ID: &str = "0003:0000C0DE:0000CAFE";
+const O_NONBLOCK: i32 = 0o4000;
+
+pub struct HidScreen{
+    file: File,
+}
+
+impl HidScreen{
+    /// 打开第一个找到的USB屏幕
+    pub fn open() -> Result<Self>{
+        let path = find_hidraw()?.ok_or(anyhow!("usb screen not found"))?;
+        Self::open_path(&path)
+    }
+
+    pub fn open_path(path: &str) -> Result<Self>{
+        let file = OpenOptions::new().read(true).write(true).custom_flags(O_NONBLOCK).open(path)?;
+        Ok(Self { file })
+    }
+
+    //一个命令包或一段数据编码为若干报告
+    fn write_reports(&mut self, packet: &[u8]) -> Result<()>{
+        for report in encode_reports(packet){
+            self.write_report(&report)?;
+        }
+        Ok(())
+    }
+
+    fn write_report(&mut self, report: &[u8; HID_REPORT_SIZE]) -> Result<()>{
+        loop{
+            match self.file.write(report){
+                Ok(_) => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl UsbScreen for HidScreen{
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        record_global(RecordKind::Packet, packet);
+        self.write_reports(packet)
+    }
+
+    //每个报告是一个独立的数据包
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        record_global(RecordKind::Data, data);
+        for chunk in data.chunks(HID_PAYLOAD_SIZE){
+            self.write_reports(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>{
+        let deadline = Instant::now() + timeout;
+        let mut assembler = ReportAssembler::new(usize::MAX);
+        let mut report = [0u8; HID_REPORT_SIZE];
+        loop{
+            match self.file.read(&mut report){
+                Ok(len) => {
+                    if let Some(packet) = assembler.push(&report[..len]){
+                        record_global(RecordKind::Response, packet);
+                        return Ok(packet.to_vec());
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    if Instant::now() > deadline{
+                        return Err(anyhow!("read timeout"));
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// 在/sys/class/hidraw中查找USB屏幕，返回/dev/hidrawN
+pub fn find_hidraw() -> Result<Option<String>>{
+    for entry in std::fs::read_dir("/sys/class/hidraw")?{
+        let entry = entry?;
+        let uevent = std::fs::read_to_string(entry.path().join("device/uevent")).unwrap_or_default();
+        //同一个设备还有触摸屏和键盘接口，用报告描述符区分(Usage Page 0xFF00)
+        let descriptor = std::fs::read(entry.path().join("device/report_descriptor")).unwrap_or_default();
+        let matched = uevent.lines().any(|l| l == format!("HID_ID={HID_ID}"))
+            && uevent.lines().any(|l| l.starts_with("HID_UNIQ=USBSCR"))
+            && descriptor.starts_with(&[0x06, 0x00, 0xFF]);
+        if matched{
+            return Ok(Some(format!("/dev/{}", entry.file_name().to_string_lossy())));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,9 @@
+//主机端与USB屏幕通信的代码，示例程序(main.rs)和命令行工具(bin/usbscreen.rs)共用
+pub mod capture;
+pub mod emulator;
+pub mod firmware;
+#[cfg(target_os = "linux")]
+pub mod hid;
+pub mod rgb565;
+pub mod scroll;
+pub mod usb_screen;
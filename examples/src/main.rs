@@ -3,13 +3,15 @@ use anyhow::Result;
 use image::open;
 use rgb565::rgb888_to_rgb565_le;
 use usb_screen::find_usb_serial_device;
-mod rgb565;
+use usb_screen_client::{rgb565, usb_screen};
 mod rgb2yuv;
-mod usb_screen;
 mod draw_bitmap;
 mod clock;
 mod draw_gif;
 mod reboot;
+mod gauge;
+#[cfg(feature = "usb-raw")]
+mod pager;
 
 #[cfg(feature = "usb-serial")]
 fn main() -> Result<()>{
@@ -61,6 +63,10 @@ fn main() -> Result<()>{
 
     // clock::draw(screen.as_mut(), width, height)?;
 
+    // gauge::draw(&interface, width, height)?;
+
+    // pager::draw(&interface, width, height)?;
+
     draw_gif::draw(&interface, width, height)?;
 
     Ok(())
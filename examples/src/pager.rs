@@ -0,0 +1,41 @@
+use chrono::Local;
+use image::buffer::ConvertBuffer;
+use offscreen_canvas::{Font, FontSettings, OffscreenCanvas, BLUE, WHITE};
+use anyhow::{anyhow, Result};
+use usb_screen_core::input::{InputEvent, INPUT_BUTTON, INPUT_ENCODER};
+
+use crate::usb_screen::{self, DeviceEvent};
+
+const PAGES: [&str; 3] = ["CPU", "Memory", "Clock"];
+
+//用设备上的按键或旋转编码器翻页：按键0上一页，按键1下一页，编码器转动一格翻一页
+pub fn draw(interface:&nusb::Interface, screen_width: u16, screen_height: u16) -> Result<()>{
+    let font_bytes:&[u8] = include_bytes!("../assets/VonwaonBitmap-16px.ttf");
+    let font = Font::from_bytes(font_bytes, FontSettings::default()).map_err(|err| anyhow!("{err}"))?;
+    let mut canvas = OffscreenCanvas::new(screen_width as u32, screen_height as u32, font);
+
+    let mut page = 0;
+    let mut render = |page: usize| -> Result<()>{
+        canvas.clear(BLUE);
+        canvas.draw_text(PAGES[page], WHITE, 32., 10, 10);
+        if PAGES[page] == "Clock"{
+            let date = Local::now().format("%Y/%m/%d %H:%M:%S").to_string();
+            canvas.draw_text(&date, WHITE, 16., 10, 60);
+        }
+        canvas.draw_text(&format!("{}/{}", page + 1, PAGES.len()), WHITE, 16., 10, screen_height as i32 - 20);
+        usb_screen::draw_rgb_image(0, 0, &canvas.image_data().convert(), interface)
+    };
+    render(page)?;
+
+    //事件流在drop时关闭事件
+    for event in usb_screen::events(interface)?{
+        let step = match event?{
+            DeviceEvent::Input(InputEvent { kind: INPUT_ENCODER, value, .. }) => value as isize,
+            DeviceEvent::Input(InputEvent { kind: INPUT_BUTTON, id, value: 1 }) => if id == 0{ -1 }else{ 1 },
+            _ => continue,
+        };
+        page = (page as isize + step).rem_euclid(PAGES.len() as isize) as usize;
+        render(page)?;
+    }
+    Ok(())
+}
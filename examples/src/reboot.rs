@@ -1,7 +1,6 @@
 use anyhow::Result;
-use futures_lite::future::block_on;
 
-use crate::usb_screen::{find_usb_serial_device, open_usb_screen, BULK_OUT_EP};
+use crate::usb_screen::{find_usb_serial_device, open_usb_screen, UsbScreen};
 
 pub fn reboot_serial() -> Result<()>{
     let devices = find_usb_serial_device()?;
@@ -15,7 +14,7 @@ pub fn reboot_serial() -> Result<()>{
     let mut screen = serialport::new(&devices[0].port_name, 115_200).open()?;
 
     const BOOT_USB:u64 = 7093010483740242786;
-    screen.write(&BOOT_USB.to_be_bytes())?;
+    screen.write_packet(&BOOT_USB.to_be_bytes())?;
     Ok(())
 }
 
@@ -26,9 +25,9 @@ pub fn reboot_usb_raw() -> Result<()>{
         return Ok(());
     }
 
-    let interface = devices.unwrap();
+    let mut interface = devices.unwrap();
     
     const BOOT_USB:u64 = 7093010483740242786;
-    block_on(interface.bulk_out(BULK_OUT_EP, BOOT_USB.to_be_bytes().into())).status?;
+    interface.write_packet(&BOOT_USB.to_be_bytes())?;
     Ok(())
 }
\ No newline at end of file
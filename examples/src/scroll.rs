@@ -0,0 +1,118 @@
+//跑马灯：利用硬件滚动(usb_screen_core::scroll)让一行文字从右向左循环滚动，每次只发送新露出的几列，
+//比每次重新发送整行图像的数据量小得多。
+//  let mut ticker = ScrollingText::new("BREAKING NEWS", 2, 0xFFFF, 0x0000, 0, 100, 320);
+//  ticker.start(&mut screen, 240)?;
+//  loop{ ticker.step(&mut screen, 2)?; std::thread::sleep(Duration::from_millis(20)); }
+//滚动区域内的整列都会滚动，start时把这些列填充为背景色，其他内容不要画在这些列中。
+
+use anyhow::Result;
+use usb_screen_core::font::render_text;
+
+use crate::usb_screen::UsbScreen;
+
+pub struct ScrollingText{
+    //文字的点阵(RGB565)和宽高
+    text: Vec<u16>,
+    text_width: usize,
+    text_height: usize,
+    background: u16,
+    //滚动区域为屏幕上x..x+width列，文字画在y行开始
+    x: u16,
+    y: u16,
+    width: u16,
+    //区域第一列显示的文字列，超过文字宽度的部分是空白(长度为区域宽度)
+    position: usize,
+    //硬件滚动偏移
+    offset: u16,
+}
+
+impl ScrollingText{
+    /// 用内置的点阵字体(usb_screen_core::font)绘制文字，scale为放大倍数
+    pub fn new(text: &str, scale: usize, color: u16, background: u16, x: u16, y: u16, width: u16) -> Self{
+        let (text, text_width, text_height) = render_text(text, scale, color, background);
+        Self { text, text_width, text_height, background, x, y, width, position: 0, offset: 0 }
+    }
+
+    //一次循环的列数：文字加上一个区域宽度的空白，文字完全移出后再从右边进入
+    fn period(&self) -> usize{
+        self.text_width + self.width as usize
+    }
+
+    //从文字第start列开始的len列，逐行排列的RGB565 BE数据
+    fn columns(&self, start: usize, len: u16) -> Vec<u8>{
+        let mut data = Vec::with_capacity(len as usize * self.text_height * 2);
+        for row in 0..self.text_height{
+            for col in start..start + len as usize{
+                let col = col % self.period();
+                let pixel = if col < self.text_width{ self.text[row * self.text_width + col] }else{ self.background };
+                data.extend_from_slice(&pixel.to_be_bytes());
+            }
+        }
+        data
+    }
+
+    /// 定义滚动区域并把区域的整列填充为背景色，文字从右边进入
+    pub fn start<S: UsbScreen>(&mut self, screen: &mut S, screen_height: u16) -> Result<()>{
+        self.position = self.text_width;
+        self.offset = 0;
+        screen.set_scroll_area(self.x, self.width)?;
+        let background = self.background.to_be_bytes().repeat(self.width as usize * screen_height as usize);
+        screen.draw_rgb565(&background, self.x, 0, self.width, screen_height)
+    }
+
+    /// 向左滚动columns列(最多为区域宽度)，只发送新露出的列
+    pub fn step<S: UsbScreen>(&mut self, screen: &mut S, columns: u16) -> Result<()>{
+        let columns = columns.min(self.width);
+        if columns == 0{
+            return Ok(());
+        }
+        let offset = self.offset;
+        self.offset = (self.offset + columns) % self.width;
+        screen.set_scroll_offset(self.offset)?;
+
+        //新露出的列写入刚从左边移出的显存列(offset..offset+columns，可能绕回区域开头)，
+        //内容为区域右边之后的文字列
+        let mut done = 0;
+        while done < columns{
+            let memory = (offset + done) % self.width;
+            let len = (columns - done).min(self.width - memory);
+            let data = self.columns(self.position + (self.width + done) as usize, len);
+            screen.draw_rgb565(&data, self.x + memory, self.y, len, self.text_height as u16)?;
+            done += len;
+        }
+        self.position = (self.position + columns as usize) % self.period();
+        Ok(())
+    }
+
+    /// 取消滚动，显存中的内容按原来的位置显示
+    pub fn stop<S: UsbScreen>(&mut self, screen: &mut S) -> Result<()>{
+        screen.set_scroll_area(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn ticker_shows_text_columns(){
+        let mut screen = Emulator::new(160, 128);
+        let mut ticker = ScrollingText::new("AB", 2, 0xFFFF, 0x001F, 10, 20, 50);
+        ticker.start(&mut screen, 128).unwrap();
+        for _ in 0..40{
+            ticker.step(&mut screen, 7).unwrap();
+            for v in 0..50u16{
+                let expected = ticker.columns(ticker.position + v as usize, 1);
+                for row in 0..ticker.text_height{
+                    let pixel = u16::from_be_bytes([expected[row * 2], expected[row * 2 + 1]]);
+                    assert_eq!(screen.pixel(10 + v, 20 + row as u16), Some(pixel));
+                }
+            }
+        }
+        //区域外不受影响
+        assert_eq!(screen.pixel(9, 20), Some(0));
+        ticker.stop(&mut screen).unwrap();
+        assert!(!screen.scroll().is_active());
+    }
+}
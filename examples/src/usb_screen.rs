@@ -1,14 +1,314 @@
-use futures_lite::future::block_on;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_lite::future::{self, block_on};
 use image::{Rgb, RgbImage};
 use nusb::Interface;
 use anyhow::Result;
 use serialport::{SerialPort, SerialPortInfo, SerialPortType};
 
+use nusb::transfer::RequestBuffer;
+use usb_screen_core::capture::RecordKind;
+use usb_screen_core::clock::ClockTime;
+use usb_screen_core::frame::{decode_stripes, frame_buffer_size, stripes, Rect};
+use usb_screen_core::protocol::{decode_update_result, DrawSprites, DrawWarp, FirmwareUpdate, ScrollArea, ScrollOffset, SetConfig, SetPanel, SetTime, SpriteDraw, UploadSprite, IMAGE_AA, IMAGE_BB, MAGIC_NUM_LEN, PACKET_SIZE, READ_INF, READ_STATS, SPRITE_FORMAT_RGB565, SPRITE_FORMAT_RGB565_KEY, EVENT_SUB, WRITE_SPLASH};
+use usb_screen_core::input::InputEvent;
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::serial::{encode_frame, ResponseDecoder};
+use usb_screen_core::settings::CONFIG_TOUCH_CALIBRATION;
+use usb_screen_core::stats::DeviceStats;
+use usb_screen_core::storage::{encode_splash, SplashHeader, SPLASH_CAPACITY};
+use usb_screen_core::touch::{Calibration, TouchEvent};
+use usb_screen_core::update::Crc32;
+
+use crate::capture::record_global;
 use crate::rgb565::rgb888_to_rgb565_be;
 
 pub const BULK_OUT_EP: u8 = 0x01;
 pub const BULK_IN_EP: u8 = 0x81;
 
+/// USB Raw、串口和HID三种传输方式共用的接口，协议相关的方法都有默认实现，
+/// 只需要实现发送和接收。例如: `let mut screen: Box<dyn UsbScreen> = Box::new(hid::HidScreen::open()?);`
+pub trait UsbScreen{
+    /// 发送一个命令包(最多64字节)
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>;
+    /// 发送图像、固件等数据
+    fn write_data(&mut self, data: &[u8]) -> Result<()>;
+    /// 读取设备的回复(ReadInfo、ReadStat、FwUpdate)
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>;
+
+    fn draw_rgb_image(&mut self, x: u16, y: u16, img:&RgbImage) -> Result<()>{
+        //ST7789驱动使用的是Big-Endian
+        let rgb565 = rgb888_to_rgb565_be(img, img.width() as usize, img.height() as usize);
+        self.draw_rgb565(&rgb565, x, y, img.width() as u16, img.height() as u16)
+    }
+
+    fn clear_screen(&mut self, color: Rgb<u8>, width: u16, height: u16) -> Result<()>{
+        self.draw_rgb_image(0, 0, &RgbImage::from_pixel(width as u32, height as u32, color))
+    }
+
+    /// 设备一帧最多占用的内存(设备信息中的frame=)，draw_rgb565按它决定是否分成横条发送
+    fn frame_buffer(&self) -> Option<usize>{
+        device_frame_buffer()
+    }
+
+    /// 设备在core0解压的横条大小(设备信息中的stripe=)，更大的图像按它分成横条，设备解压和绘制同时进行
+    fn stripe(&self) -> Option<usize>{
+        device_stripe()
+    }
+
+    fn draw_rgb565(&mut self, rgb565:&[u8], x: u16, y: u16, width: u16, height: u16) -> Result<()>{
+        for (img_begin, data) in image_frames(rgb565, x, y, width, height, self.frame_buffer(), self.stripe()){
+            self.write_packet(&img_begin)?;
+            self.write_data(&data)?;
+            self.write_packet(&IMAGE_BB.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn upload_sprite(&mut self, rgb565:&[u8], id: u16, width: u16, height: u16, key: Option<u16>) -> Result<()>{
+        self.write_packet(&sprite_header(id, width, height, key).encode())?;
+        self.write_data(&lz4_flex::compress_prepend_size(rgb565))?;
+        self.write_packet(&IMAGE_BB.to_be_bytes())
+    }
+
+    fn draw_sprites(&mut self, draws: &[SpriteDraw]) -> Result<()>{
+        for cmd in DrawSprites::pack(draws){
+            let (buf, len) = cmd.encode();
+            self.write_packet(&buf[..len])?;
+        }
+        Ok(())
+    }
+
+    fn draw_warp(&mut self, warp: &DrawWarp) -> Result<()>{
+        self.write_packet(&warp.encode())
+    }
+
+    /// 定义硬件滚动区域: 屏幕上start..start+len列的整列(横屏时控制器沿x方向滚动，见usb_screen_core::scroll)，len为0时取消滚动
+    fn set_scroll_area(&mut self, start: u16, len: u16) -> Result<()>{
+        self.write_packet(&ScrollArea{ start, len }.encode())
+    }
+
+    /// 设置滚动偏移: 区域内屏幕第x列显示显存中第 start + (x - start + offset) % len 列
+    fn set_scroll_offset(&mut self, offset: u16) -> Result<()>{
+        self.write_packet(&ScrollOffset(offset).encode())
+    }
+
+    fn read_info(&mut self) -> Result<String>{
+        self.write_packet(&READ_INF.to_be_bytes())?;
+        let info = String::from_utf8(self.read_response(Duration::from_secs(1))?)?;
+        remember_device_info(&info);
+        Ok(info)
+    }
+
+    /// 读取性能计数器，reset为true时读取后清零
+    fn read_stats(&mut self, reset: bool) -> Result<DeviceStats>{
+        let mut buf = [0u8; MAGIC_NUM_LEN + 2];
+        buf[0..8].copy_from_slice(&READ_STATS.to_be_bytes());
+        buf[8..10].copy_from_slice(&(reset as u16).to_be_bytes());
+        self.write_packet(&buf)?;
+        DeviceStats::decode(&self.read_response(Duration::from_secs(1))?)
+            .ok_or(anyhow::anyhow!("unexpected response from device"))
+    }
+
+    fn set_config(&mut self, key: u16, value: u32) -> Result<()>{
+        self.write_packet(&SetConfig{ key, value }.encode())
+    }
+
+    /// 设置反色和Gamma曲线，save为false时只预览(重启后恢复)
+    fn set_panel(&mut self, panel: &PanelConfig, save: bool) -> Result<()>{
+        self.write_packet(&SetPanel{ save, panel: *panel }.encode())
+    }
+
+    fn set_time(&mut self) -> Result<()>{
+        self.write_packet(&SetTime(local_time()).encode())
+    }
+
+    fn write_splash(&mut self, frames: &[RgbImage], x: u16, y: u16, delay_ms: u16) -> Result<()>{
+        let data = splash_data(frames, x, y, delay_ms)?;
+        self.write_packet(&WRITE_SPLASH.to_be_bytes())?;
+        self.write_data(&data)?;
+        self.write_packet(&IMAGE_BB.to_be_bytes())
+    }
+
+    /// 写入触摸屏校准参数(保存在设备Flash中)
+    fn set_touch_calibration(&mut self, calibration: &Calibration) -> Result<()>{
+        for (i, bits) in calibration.to_bits().iter().enumerate(){
+            self.set_config(CONFIG_TOUCH_CALIBRATION + i as u16, *bits)?;
+        }
+        Ok(())
+    }
+
+    fn update_firmware(&mut self, firmware: &[u8]) -> Result<()>{
+        let begin = FirmwareUpdate{ len: firmware.len() as u32, crc: Crc32::checksum(firmware) }.encode();
+        self.write_packet(&begin)?;
+        self.write_data(firmware)?;
+        self.write_packet(&IMAGE_BB.to_be_bytes())?;
+        //设备读回暂存区校验CRC需要一些时间
+        update_result(&self.read_response(Duration::from_secs(5))?)
+    }
+}
+
+impl UsbScreen for Interface{
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        record_global(RecordKind::Packet, packet);
+        block_on(self.bulk_out(BULK_OUT_EP, packet.to_vec())).status?;
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        record_global(RecordKind::Data, data);
+        block_on(self.bulk_out(BULK_OUT_EP, data.to_vec())).status?;
+        Ok(())
+    }
+
+    //设备以短包(或空包)结束，跳过之前没有读取的事件。超时后取消正在等待的传输
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>{
+        let deadline = Instant::now() + timeout;
+        let mut response = vec![];
+        loop{
+            let transfer = self.bulk_in(BULK_IN_EP, RequestBuffer::new(PACKET_SIZE));
+            let data = block_on_deadline(transfer, deadline)
+                .ok_or(anyhow::anyhow!("timed out waiting for response"))?
+                .into_result()?;
+            if response.is_empty() && DeviceEvent::decode(&data).is_some(){
+                continue;
+            }
+            response.extend_from_slice(&data);
+            if data.len() < PACKET_SIZE{
+                record_global(RecordKind::Response, &response);
+                return Ok(response);
+            }
+        }
+    }
+}
+
+//等待future完成，到达deadline时返回None。nusb的传输在future被drop时取消，不会留下等待中的传输
+fn block_on_deadline<F: Future>(future: F, deadline: Instant) -> Option<F::Output>{
+    block_on(future::or(async{ Some(future.await) }, async{ Timer{ deadline, started: false }.await; None }))
+}
+
+//到达deadline时完成的future，第一次poll时启动一个线程在deadline唤醒
+struct Timer{
+    deadline: Instant,
+    started: bool,
+}
+
+impl Future for Timer{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()>{
+        let now = Instant::now();
+        if now >= self.deadline{
+            return Poll::Ready(());
+        }
+        if !self.started{
+            self.started = true;
+            let (wait, waker) = (self.deadline - now, cx.waker().clone());
+            std::thread::spawn(move || {
+                std::thread::sleep(wait);
+                waker.wake();
+            });
+        }
+        Poll::Pending
+    }
+}
+
+impl UsbScreen for dyn SerialPort + '_{
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        record_global(RecordKind::Packet, packet);
+        write_serial(self, packet)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        record_global(RecordKind::Data, data);
+        write_serial(self, data)
+    }
+
+    //回复是一帧(见usb_screen_core::serial)，串口每次可能只读到一部分，一直读到帧结束或超时。帧之前的日志丢弃
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>{
+        let deadline = Instant::now() + timeout;
+        let mut decoder = ResponseDecoder::new(MAX_RESPONSE_LEN);
+        let mut buf = [0u8; PACKET_SIZE];
+        loop{
+            let wait = deadline.saturating_duration_since(Instant::now());
+            if wait.is_zero(){
+                return Err(anyhow::anyhow!("timed out waiting for response"));
+            }
+            self.set_timeout(wait)?;
+            let len = match self.read(&mut buf){
+                Ok(len) => len,
+                Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            for &byte in &buf[..len]{
+                if let Some(response) = decoder.push(byte){
+                    record_global(RecordKind::Response, response);
+                    return Ok(response.to_vec());
+                }
+            }
+        }
+    }
+}
+
+//Box<dyn SerialPort>、Box<dyn UsbScreen>等也可以直接使用
+impl<S: UsbScreen + ?Sized> UsbScreen for Box<S>{
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>{
+        self.as_mut().write_packet(packet)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<()>{
+        self.as_mut().write_data(data)
+    }
+
+    fn read_response(&mut self, timeout: Duration) -> Result<Vec<u8>>{
+        self.as_mut().read_response(timeout)
+    }
+
+    fn frame_buffer(&self) -> Option<usize>{
+        self.as_ref().frame_buffer()
+    }
+
+    fn stripe(&self) -> Option<usize>{
+        self.as_ref().stripe()
+    }
+}
+
+//读取回复时允许的最大长度，最长的ReadInfo带上panic信息也不到1K
+const MAX_RESPONSE_LEN: usize = 4096;
+
+//串口写入超时：这段时间内一个字节都没有写入时返回错误
+const SERIAL_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 通过串口发送命令包或数据：每64字节编码为一帧(见usb_screen_core::serial)，设备丢失字节后可以重新同步。
+/// 处理部分写入，超时后返回错误
+pub fn write_serial(port: &mut dyn SerialPort, data: &[u8]) -> Result<()>{
+    let mut frames = Vec::with_capacity(data.len() + data.len() / PACKET_SIZE * 3 + 3);
+    for packet in data.chunks(PACKET_SIZE){
+        frames.extend_from_slice(&encode_frame(packet));
+    }
+    let mut buf = frames.as_slice();
+    let mut deadline = Instant::now() + SERIAL_WRITE_TIMEOUT;
+    while !buf.is_empty(){
+        match port.write(buf){
+            Ok(0) => return Err(anyhow::anyhow!("serial port closed")),
+            Ok(n) => {
+                buf = &buf[n..];
+                deadline = Instant::now() + SERIAL_WRITE_TIMEOUT;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) if err.kind() == ErrorKind::TimedOut && Instant::now() < deadline => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    port.flush()?;
+    Ok(())
+}
+
 pub fn open_usb_screen() -> Result<Option<Interface>>{
     let mut di = nusb::list_devices()?;
     for d in di{
@@ -39,79 +339,295 @@ pub fn find_usb_serial_device() -> Result<Vec<SerialPortInfo>>{
 }
 
 pub fn clear_screen(color: Rgb<u8>, interface:&Interface, width: u16, height: u16) -> anyhow::Result<()>{
-    let mut img = RgbImage::new(width as u32, height as u32);
-    for p in img.pixels_mut(){
-        *p = color;
-    }
-    draw_rgb_image(0, 0, &img, interface)
+    interface.clone().clear_screen(color, width, height)
 }
 
 pub fn clear_screen_serial(color: Rgb<u8>, port:&mut dyn SerialPort, width: u16, height: u16) -> anyhow::Result<()>{
-    let mut img = RgbImage::new(width as u32, height as u32);
-    for p in img.pixels_mut(){
-        *p = color;
-    }
-    draw_rgb_image_serial(0, 0, &img, port)
+    port.clear_screen(color, width, height)
 }
 
 pub fn draw_rgb_image(x: u16, y: u16, img:&RgbImage, interface:&Interface) -> anyhow::Result<()>{
-    //ST7789驱动使用的是Big-Endian
-    let rgb565 = rgb888_to_rgb565_be(&img, img.width() as usize, img.height() as usize);
-    draw_rgb565(&rgb565, x, y, img.width() as u16, img.height() as u16, interface)
+    interface.clone().draw_rgb_image(x, y, img)
 }
 
 pub fn draw_rgb565(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, interface:&Interface) -> anyhow::Result<()>{
-    let rgb565_u8_slice = lz4_flex::compress_prepend_size(rgb565);
+    interface.clone().draw_rgb565(rgb565, x, y, width, height)
+}
 
-    const IMAGE_AA:u64 = 7596835243154170209;
-    const BOOT_USB:u64 = 7093010483740242786;
-    const IMAGE_BB:u64 = 7596835243154170466;
+pub fn draw_rgb_image_serial(x: u16, y: u16, img:&RgbImage, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.draw_rgb_image(x, y, img)
+}
 
-    let img_begin = &mut [0u8; 16];
+pub fn draw_rgb565_serial(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.draw_rgb565(rgb565, x, y, width, height)
+}
+
+/// 图像开始的命令包(IMAGE_AA)
+pub fn image_header(x: u16, y: u16, width: u16, height: u16) -> [u8; 16]{
+    let mut img_begin = [0u8; 16];
     img_begin[0..8].copy_from_slice(&IMAGE_AA.to_be_bytes());
     img_begin[8..10].copy_from_slice(&width.to_be_bytes());
     img_begin[10..12].copy_from_slice(&height.to_be_bytes());
     img_begin[12..14].copy_from_slice(&x.to_be_bytes());
     img_begin[14..16].copy_from_slice(&y.to_be_bytes());
-    // println!("draw:{x}x{y} {width}x{height}");
+    img_begin
+}
 
-    block_on(interface.bulk_out(BULK_OUT_EP, img_begin.into())).status?;
-    //读取
-    // let result = block_on(interface.bulk_in(BULK_IN_EP, RequestBuffer::new(64))).data;
-    // let msg = String::from_utf8(result)?;
-    // println!("{msg}ms");
+/// 压缩图像，返回要发送的每一帧的IMAGE_AA包和压缩数据。
+/// 压缩数据加上解压后的图像超过设备的帧缓冲(frame_buffer)时分成若干横条，每条单独作为一帧发送；
+/// 不知道设备的帧缓冲时(旧版本固件)，按与图像一样大的屏幕计算。
+/// 设备报告了stripe时，比它大的图像按stripe分成横条，设备在core0解压下一条的同时core1绘制上一条
+pub fn image_frames(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, frame_buffer: Option<usize>, stripe: Option<usize>) -> Vec<([u8; 16], Vec<u8>)>{
+    let compressed = lz4_flex::compress_prepend_size(rgb565);
+    let frame_buffer = frame_buffer.unwrap_or(frame_buffer_size(width, height));
+    let rect = Rect{ x, y, width, height };
+    let pipelined = stripe.is_some_and(|stripe| rgb565.len() > stripe);
+    //长度与宽高不符的图像不能分割，由设备拒绝
+    if (compressed.len() + rgb565.len() <= frame_buffer && !pipelined) || rgb565.is_empty() || rgb565.len() != rect.pixels() * 2{
+        return vec![(image_header(x, y, width, height), compressed)];
+    }
+    let parts: Vec<Rect> = match stripe{
+        Some(stripe) if pipelined => decode_stripes(rect, frame_buffer, stripe).collect(),
+        _ => stripes(rect, frame_buffer).collect(),
+    };
+    let row = width as usize * 2;
+    parts.into_iter().map(|stripe| {
+        let start = (stripe.y - y) as usize * row;
+        let data = &rgb565[start..start + stripe.pixels() * 2];
+        (image_header(stripe.x, stripe.y, stripe.width, stripe.height), lz4_flex::compress_prepend_size(data))
+    }).collect()
+}
 
-    block_on(interface.bulk_out(BULK_OUT_EP, rgb565_u8_slice.into())).status?;
-    block_on(interface.bulk_out(BULK_OUT_EP, IMAGE_BB.to_be_bytes().into())).status?;
-    Ok(())
+//设备信息中的frame=和stripe=，0表示还没有读取过或设备没有报告
+static DEVICE_FRAME_BUFFER: AtomicUsize = AtomicUsize::new(0);
+static DEVICE_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+//记录设备信息中的frame=和stripe=，连接了多个设备时使用最小的
+fn remember_device_info(info: &str){
+    for (key, value) in [("frame=", &DEVICE_FRAME_BUFFER), ("stripe=", &DEVICE_STRIPE)]{
+        if let Some(new) = info.split(';').find_map(|item| item.strip_prefix(key)?.parse::<usize>().ok()){
+            let _ = value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+                Some(if old == 0{ new }else{ old.min(new) })
+            });
+        }
+    }
 }
 
-pub fn draw_rgb_image_serial(x: u16, y: u16, img:&RgbImage, port:&mut dyn SerialPort) -> anyhow::Result<()>{
-    //ST7789驱动使用的是Big-Endian
-    let rgb565 = rgb888_to_rgb565_be(&img, img.width() as usize, img.height() as usize);
-    draw_rgb565_serial(&rgb565, x, y, img.width() as u16, img.height() as u16, port)
+/// 读取过的设备信息中报告的帧缓冲，还没有读取过设备信息或旧版本固件没有报告时返回None
+pub fn device_frame_buffer() -> Option<usize>{
+    match DEVICE_FRAME_BUFFER.load(Ordering::Relaxed){
+        0 => None,
+        frame_buffer => Some(frame_buffer),
+    }
 }
 
-pub fn draw_rgb565_serial(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, port:&mut dyn SerialPort) -> anyhow::Result<()>{
-    let rgb565_u8_slice = lz4_flex::compress_prepend_size(rgb565);
+/// 读取过的设备信息中报告的横条大小，ST7735和旧版本固件没有报告时返回None
+pub fn device_stripe() -> Option<usize>{
+    match DEVICE_STRIPE.load(Ordering::Relaxed){
+        0 => None,
+        stripe => Some(stripe),
+    }
+}
 
-    const IMAGE_AA:u64 = 7596835243154170209;
-    const BOOT_USB:u64 = 7093010483740242786;
-    const IMAGE_BB:u64 = 7596835243154170466;
+/// 上传精灵到设备，之后用draw_sprites绘制(每个8字节)，或用draw_warp旋转、缩放、平移后绘制
+/// key: 透明色(RGB565)，等于该颜色的像素不绘制
+pub fn upload_sprite(rgb565:&[u8], id: u16, width: u16, height: u16, key: Option<u16>, interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().upload_sprite(rgb565, id, width, height, key)
+}
 
-    let img_begin = &mut [0u8; 16];
-    img_begin[0..8].copy_from_slice(&IMAGE_AA.to_be_bytes());
-    img_begin[8..10].copy_from_slice(&width.to_be_bytes());
-    img_begin[10..12].copy_from_slice(&height.to_be_bytes());
-    img_begin[12..14].copy_from_slice(&x.to_be_bytes());
-    img_begin[14..16].copy_from_slice(&y.to_be_bytes());
-    // println!("draw:{x}x{y} {width}x{height} len={}", rgb565_u8_slice.len());
+/// 绘制设备上的精灵，每个USB包最多7个
+pub fn draw_sprites(draws: &[SpriteDraw], interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().draw_sprites(draws)
+}
 
-    port.write(img_begin)?;
-    port.flush()?;
-    port.write(&rgb565_u8_slice)?;
-    port.flush()?;
-    port.write(&IMAGE_BB.to_be_bytes())?;
-    port.flush()?;
-    Ok(())
-}
\ No newline at end of file
+/// 使用3x3变换矩阵绘制设备上的精灵，只需要传输一条指令
+pub fn draw_warp(warp: &DrawWarp, interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().draw_warp(warp)
+}
+
+/// 读取设备信息，例如: USBSCR320x240;01;sprite=16384/16384;frame=172800
+pub fn read_info(interface:&Interface) -> anyhow::Result<String>{
+    interface.clone().read_info()
+}
+
+pub fn upload_sprite_serial(rgb565:&[u8], id: u16, width: u16, height: u16, key: Option<u16>, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.upload_sprite(rgb565, id, width, height, key)
+}
+
+pub fn draw_sprites_serial(draws: &[SpriteDraw], port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.draw_sprites(draws)
+}
+
+pub fn draw_warp_serial(warp: &DrawWarp, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.draw_warp(warp)
+}
+
+pub fn read_info_serial(port:&mut dyn SerialPort) -> anyhow::Result<String>{
+    port.read_info()
+}
+
+/// 设备通过USB Raw接口发送的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEvent{
+    Touch(TouchEvent),
+    Input(InputEvent),
+}
+
+impl DeviceEvent{
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        TouchEvent::decode(data).map(DeviceEvent::Touch)
+            .or_else(|| InputEvent::decode(data).map(DeviceEvent::Input))
+    }
+}
+
+/// 开启或关闭触摸和按键事件，开启后必须不断调用read_event读取
+pub fn set_events(enable: bool, interface:&Interface) -> anyhow::Result<()>{
+    let mut buf = [0u8; 10];
+    buf[0..8].copy_from_slice(&EVENT_SUB.to_be_bytes());
+    buf[8..10].copy_from_slice(&(enable as u16).to_be_bytes());
+    interface.clone().write_packet(&buf)
+}
+
+/// 等待下一个事件
+pub fn read_event(interface:&Interface) -> anyhow::Result<DeviceEvent>{
+    loop{
+        let data = block_on(interface.bulk_in(BULK_IN_EP, RequestBuffer::new(PACKET_SIZE))).into_result()?;
+        if let Some(event) = DeviceEvent::decode(&data){
+            return Ok(event);
+        }
+    }
+}
+
+/// 等待下一个触摸事件(按下、移动或抬起)，忽略按键事件
+pub fn read_touch_event(interface:&Interface) -> anyhow::Result<TouchEvent>{
+    loop{
+        if let DeviceEvent::Touch(event) = read_event(interface)?{
+            return Ok(event);
+        }
+    }
+}
+
+/// 事件流，用for循环读取，drop时关闭事件
+pub struct DeviceEvents<'a>{
+    interface: &'a Interface,
+}
+
+pub fn events(interface:&Interface) -> anyhow::Result<DeviceEvents<'_>>{
+    set_events(true, interface)?;
+    Ok(DeviceEvents{ interface })
+}
+
+impl Iterator for DeviceEvents<'_>{
+    type Item = anyhow::Result<DeviceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        Some(read_event(self.interface))
+    }
+}
+
+impl Drop for DeviceEvents<'_>{
+    fn drop(&mut self){
+        let _ = set_events(false, self.interface);
+    }
+}
+
+/// 修改设备设置，键见usb_screen_core::settings，设置保存在Flash中
+pub fn set_config(key: u16, value: u32, interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().set_config(key, value)
+}
+
+pub fn set_config_serial(key: u16, value: u32, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.set_config(key, value)
+}
+
+/// 把电脑的本地时间写入设备RTC，设备断电后需要重新设置
+pub fn set_time(interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().set_time()
+}
+
+pub fn set_time_serial(port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.set_time()
+}
+
+fn local_time() -> ClockTime{
+    use chrono::{Datelike, Local, Timelike};
+    let now = Local::now();
+    ClockTime{
+        year: now.year() as u16,
+        month: now.month() as u8,
+        day: now.day() as u8,
+        weekday: now.weekday().num_days_from_sunday() as u8,
+        hour: now.hour() as u8,
+        minute: now.minute() as u8,
+        second: now.second() as u8,
+    }
+}
+
+/// 把开机画面写入设备Flash，多帧时按delay_ms循环播放。需要再把设置CONFIG_SPLASH_MODE改为自定义
+pub fn write_splash(frames: &[RgbImage], x: u16, y: u16, delay_ms: u16, interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().write_splash(frames, x, y, delay_ms)
+}
+
+pub fn write_splash_serial(frames: &[RgbImage], x: u16, y: u16, delay_ms: u16, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.write_splash(frames, x, y, delay_ms)
+}
+
+fn splash_data(frames: &[RgbImage], x: u16, y: u16, delay_ms: u16) -> anyhow::Result<Vec<u8>>{
+    let first = frames.first().ok_or(anyhow::anyhow!("no frames"))?;
+    let (width, height) = first.dimensions();
+    if frames.iter().any(|f| f.dimensions() != (width, height)){
+        return Err(anyhow::anyhow!("all frames must have the same size"));
+    }
+    let compressed: Vec<Vec<u8>> = frames.iter().map(|f|{
+        lz4_flex::compress_prepend_size(&rgb888_to_rgb565_be(f, width as usize, height as usize))
+    }).collect();
+    let compressed: Vec<&[u8]> = compressed.iter().map(|f| f.as_slice()).collect();
+    let header = SplashHeader{ x, y, width: width as u16, height: height as u16, frames: frames.len() as u16, delay_ms };
+    let data = encode_splash(&header, &compressed);
+    if data.len() > SPLASH_CAPACITY{
+        return Err(anyhow::anyhow!("splash is {} bytes, flash capacity is {SPLASH_CAPACITY}", data.len()));
+    }
+    Ok(data)
+}
+
+/// 升级固件，firmware为从0x10000000开始的固件数据(见firmware::load_firmware)。
+/// 设备校验通过后返回Ok并自动重启，重启时把新固件复制到固件区(几秒钟)，之后重新枚举
+pub fn update_firmware(firmware: &[u8], interface:&Interface) -> anyhow::Result<()>{
+    interface.clone().update_firmware(firmware)
+}
+
+pub fn update_firmware_serial(firmware: &[u8], port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    port.update_firmware(firmware)
+}
+
+fn update_result(data: &[u8]) -> anyhow::Result<()>{
+    match decode_update_result(data){
+        Some(Ok(())) => Ok(()),
+        Some(Err(err)) => Err(anyhow::anyhow!("firmware update failed: {err:?}")),
+        None => Err(anyhow::anyhow!("unexpected response from device")),
+    }
+}
+
+/// 上传精灵开始的命令包(UploadSprite)
+pub fn sprite_header(id: u16, width: u16, height: u16, key: Option<u16>) -> UploadSprite{
+    UploadSprite{
+        id,
+        width,
+        height,
+        format: if key.is_some(){ SPRITE_FORMAT_RGB565_KEY }else{ SPRITE_FORMAT_RGB565 },
+        key: key.unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfers_stop_waiting_at_deadline(){
+        let start = Instant::now();
+        assert_eq!(block_on_deadline(future::pending::<()>(), start + Duration::from_millis(50)), None);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(block_on_deadline(future::ready(1), start), Some(1));
+    }
+}
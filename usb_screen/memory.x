@@ -1,6 +1,7 @@
 MEMORY {
   BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-  FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+  /* 768K之后保留给固件升级暂存区、自定义开机画面和设置，见 usb_screen_core/src/storage.rs */
+  FLASH : ORIGIN = 0x10000100, LENGTH = 768K - 0x100
   RAM   : ORIGIN = 0x20000000, LENGTH = 256K
 }
 
@@ -0,0 +1,62 @@
+//RP2040硬件RTC，core0收到SetTime后设置时间，core1的时钟画面读取时间
+
+use core::cell::RefCell;
+use embassy_rp::peripherals::RTC;
+use embassy_rp::rtc::{DateTime, DayOfWeek, Rtc};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use usb_screen_core::clock::ClockTime;
+
+static CLOCK: Mutex<CriticalSectionRawMutex, RefCell<Option<Rtc<'static, RTC>>>> = Mutex::new(RefCell::new(None));
+
+pub fn init(rtc: RTC){
+    let rtc = Rtc::new(rtc);
+    CLOCK.lock(|clock| clock.replace(Some(rtc)));
+}
+
+pub fn set_time(time: &ClockTime){
+    let weekday = match time.weekday{
+        0 => DayOfWeek::Sunday,
+        1 => DayOfWeek::Monday,
+        2 => DayOfWeek::Tuesday,
+        3 => DayOfWeek::Wednesday,
+        4 => DayOfWeek::Thursday,
+        5 => DayOfWeek::Friday,
+        _ => DayOfWeek::Saturday,
+    };
+    let datetime = DateTime{
+        year: time.year,
+        month: time.month,
+        day: time.day,
+        day_of_week: weekday,
+        hour: time.hour,
+        minute: time.minute,
+        second: time.second,
+    };
+    CLOCK.lock(|clock| {
+        if let Some(rtc) = clock.borrow_mut().as_mut(){
+            let _ = rtc.set_datetime(datetime);
+        }
+    });
+}
+
+//读取时间，RTC没有设置过时间(上电后)返回None
+pub fn now() -> Option<ClockTime>{
+    CLOCK.lock(|clock| {
+        let clock = clock.borrow();
+        let rtc = clock.as_ref()?;
+        if !rtc.is_running(){
+            return None;
+        }
+        let now = rtc.now().ok()?;
+        Some(ClockTime{
+            year: now.year,
+            month: now.month,
+            day: now.day,
+            weekday: now.day_of_week as u8,
+            hour: now.hour,
+            minute: now.minute,
+            second: now.second,
+        })
+    })
+}
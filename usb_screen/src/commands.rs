@@ -0,0 +1,38 @@
+//core1执行命令(usb_screen_core::command)，失败的命令记录到性能计数器，精灵缓存的剩余空间报告在设备信息中
+
+use portable_atomic::Ordering;
+use usb_screen_core::command::{Command, CommandError};
+
+use crate::display::Screen;
+use crate::info::{SPRITE_CACHE_FREE, SPRITE_CACHE_SIZE};
+use crate::{stats, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//精灵缓存容量(字节)，240x320屏幕一帧(FRAME_BUFFER)就需要169K，所以缓存小一些
+#[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
+const SPRITE_CACHE_CAPACITY: usize = 1024 * 48;
+#[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+const SPRITE_CACHE_CAPACITY: usize = 1024 * 16;
+
+//core1解压精灵时core0可能正在接收下一帧：一帧的内存，加上缓存满时上传最大的精灵(压缩数据和解压后的图像，或解压后的图像和转换后的像素，约为容量的2倍)，
+//再留8K给其他分配(变换绘制的横条、设备信息等)，必须放得进堆内存
+const _: () = assert!(crate::FRAME_BUFFER + SPRITE_CACHE_CAPACITY * 3 + 1024 * 8 <= crate::HEAP_SIZE);
+
+pub struct CommandHandler(usb_screen_core::command::CommandHandler);
+
+impl CommandHandler{
+    pub fn new() -> Self{
+        let handler = usb_screen_core::command::CommandHandler::new(SCREEN_WIDTH, SCREEN_HEIGHT, SPRITE_CACHE_CAPACITY);
+        SPRITE_CACHE_SIZE.store(handler.sprites().capacity() as u32, Ordering::Relaxed);
+        SPRITE_CACHE_FREE.store(handler.sprites().free() as u32, Ordering::Relaxed);
+        Self(handler)
+    }
+
+    pub async fn handle<S: Screen>(&mut self, screen: &mut S, command: Command){
+        match self.0.handle(screen, command).await{
+            Ok(()) => (),
+            Err(CommandError::Rejected(err)) => stats::reject(err),
+            Err(err) => stats::update(|s| err.record(s)),
+        }
+        SPRITE_CACHE_FREE.store(self.0.sprites().free() as u32, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,3 @@
+//三种屏幕驱动共用的绘制接口，定义在usb_screen_core中，命令处理和模拟器共用
+
+pub use usb_screen_core::display::Screen;
@@ -0,0 +1,224 @@
+//没有图像传输时显示的内容：自定义开机画面、黑屏或时钟，内置动画仍由各个core1_task绘制
+
+use alloc::string::String;
+use alloc::vec;
+use byte_slice_cast::AsMutByteSlice;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant};
+use usb_screen_core::crash::wrap_lines;
+use usb_screen_core::font::{render_text, CELL_HEIGHT, CELL_WIDTH};
+use usb_screen_core::settings::SplashMode;
+use usb_screen_core::storage::{decode_splash, SplashHeader};
+
+use crate::{clock, info};
+use crate::display::Screen;
+use crate::storage::{settings, splash_data};
+use crate::{receive_message, try_receive_message, Message, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//静态画面(黑屏、单帧图像)和动画帧之间每次最多等待这么久，调用者在两次绘制之间检查触摸等输入
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+//时钟画面的颜色
+const CLOCK_COLOR: u16 = 0xFFFF;
+const DATE_COLOR: u16 = 0x8C71;
+//控制器与固件不符的提示的颜色
+const WARNING_COLOR: u16 = 0xFD20;
+
+//USB断开(或总线复位)时由core0发出，core1收到后回到空闲画面
+pub static HOST_DISCONNECTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+//设置中的超时时间，超过这个时间没有新图像时回到空闲画面
+pub fn idle_timeout() -> Option<Duration>{
+    match settings().idle_timeout{
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    }
+}
+
+pub struct IdleScreen{
+    //下一帧的序号
+    frame: usize,
+    //静态画面已经绘制
+    drawn: bool,
+    mode: SplashMode,
+    //时钟画面上次绘制的时间(秒)
+    clock_second: Option<u8>,
+    //自定义开机画面下一帧的时间
+    next_frame: Option<Instant>,
+    //等待期间收到的图像或命令
+    message: Option<Message>,
+    //启动时识别出的控制器与固件不符，空闲时只显示这个提示
+    warning: Option<String>,
+}
+
+impl IdleScreen{
+    pub fn new() -> Self{
+        //在读取控制器ID之后创建
+        let warning = info::panel_mismatch_warning();
+        Self { frame: 0, drawn: false, mode: SplashMode::BuiltIn, clock_second: None, next_frame: None, message: None, warning }
+    }
+
+    //取出空闲画面等待期间收到的图像或命令，没有时从通道中取
+    pub fn try_receive(&mut self) -> Option<Message>{
+        self.message.take().or_else(try_receive_message)
+    }
+
+    //开始接收图像，清除之前的断开信号
+    pub fn leave(&mut self){
+        HOST_DISCONNECTED.reset();
+    }
+
+    //从图像传输回到空闲画面，清屏后从头开始绘制
+    pub async fn enter<S: Screen>(&mut self, screen: &mut S){
+        self.frame = 0;
+        self.drawn = false;
+        self.clock_second = None;
+        self.next_frame = None;
+        screen.fill_rect(0, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT).await;
+    }
+
+    //绘制一帧，返回false时由调用者绘制内置动画
+    pub async fn draw<S: Screen>(&mut self, screen: &mut S) -> bool{
+        let mode = settings().splash_mode;
+        if mode != self.mode{
+            //设置改变后重新开始
+            self.mode = mode;
+            self.frame = 0;
+            self.drawn = false;
+            self.clock_second = None;
+            self.next_frame = None;
+        }
+        //按错误的控制器初始化时动画和开机画面可能显示不正常，提示更换固件
+        if self.warning.is_some(){
+            return self.draw_warning(screen).await;
+        }
+        match mode{
+            SplashMode::BuiltIn => false,
+            SplashMode::Blank => {
+                if !self.drawn{
+                    screen.fill_rect(0, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT).await;
+                    self.drawn = true;
+                }
+                self.wait(IDLE_POLL).await;
+                true
+            }
+            //Flash中没有有效的开机画面时显示内置动画
+            SplashMode::Custom => self.draw_custom(screen).await,
+            //RTC没有设置时间时显示内置动画
+            SplashMode::Clock => self.draw_clock(screen).await,
+        }
+    }
+
+    async fn draw_warning<S: Screen>(&mut self, screen: &mut S) -> bool{
+        if !self.drawn{
+            self.drawn = true;
+            screen.fill_rect(0, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT).await;
+            let warning = self.warning.as_deref().unwrap_or("");
+            let scale = (SCREEN_WIDTH as usize / 160).max(1);
+            let columns = SCREEN_WIDTH as usize / (CELL_WIDTH * scale);
+            let lines = wrap_lines(warning, columns).count();
+            let mut y = (SCREEN_HEIGHT as usize).saturating_sub(lines * CELL_HEIGHT * scale) / 2;
+            for line in wrap_lines(warning, columns){
+                let (pixels, width, height) = render_text(line, scale, WARNING_COLOR, 0);
+                let x = (SCREEN_WIDTH as usize).saturating_sub(width) / 2;
+                screen.draw_pixels(&pixels, x as u16, y as u16, width as u16, height as u16).await;
+                y += height;
+            }
+        }
+        self.wait(IDLE_POLL).await;
+        true
+    }
+
+    async fn draw_clock<S: Screen>(&mut self, screen: &mut S) -> bool{
+        let now = match clock::now(){
+            Some(now) => now,
+            None => return false,
+        };
+        if self.clock_second == Some(now.second){
+            self.wait(IDLE_POLL).await;
+            return true;
+        }
+        if self.clock_second.is_none(){
+            screen.fill_rect(0, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT).await;
+        }
+        self.clock_second = Some(now.second);
+
+        //时间占屏幕宽度的90%，日期在时间下方
+        let time = now.time_text();
+        let date = now.date_text();
+        let time_scale = (SCREEN_WIDTH as usize * 9 / 10 / (time.len() * CELL_WIDTH)).max(1);
+        let date_scale = (time_scale / 2).max(1);
+        let (time_pixels, time_width, time_height) = render_text(&time, time_scale, CLOCK_COLOR, 0);
+        let (date_pixels, date_width, date_height) = render_text(&date, date_scale, DATE_COLOR, 0);
+        let top = (SCREEN_HEIGHT as usize).saturating_sub(time_height + date_height) / 2;
+        let time_x = (SCREEN_WIDTH as usize).saturating_sub(time_width) / 2;
+        let date_x = (SCREEN_WIDTH as usize).saturating_sub(date_width) / 2;
+        screen.draw_pixels(&time_pixels, time_x as u16, top as u16, time_width as u16, time_height as u16).await;
+        if date_width <= SCREEN_WIDTH as usize{
+            screen.draw_pixels(&date_pixels, date_x as u16, (top + time_height) as u16, date_width as u16, date_height as u16).await;
+        }
+        true
+    }
+
+    async fn draw_custom<S: Screen>(&mut self, screen: &mut S) -> bool{
+        let (header, frames) = match decode_splash(splash_data()){
+            Some(splash) => splash,
+            None => return false,
+        };
+        //图像必须在屏幕内
+        if header.x as u32 + header.width as u32 > SCREEN_WIDTH as u32
+            || header.y as u32 + header.height as u32 > SCREEN_HEIGHT as u32{
+            return false;
+        }
+        if self.drawn{
+            self.wait(IDLE_POLL).await;
+            return true;
+        }
+        //帧间隔由主机设置，最长65秒，分成多次等待
+        if let Some(next_frame) = self.next_frame{
+            let now = Instant::now();
+            if now < next_frame{
+                self.wait((next_frame - now).min(IDLE_POLL)).await;
+                return true;
+            }
+        }
+        if self.frame == 0{
+            screen.fill_rect(0, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT).await;
+        }
+        let frame = frames[self.frame % frames.len()];
+        draw_frame(screen, &header, frame).await;
+        self.frame = (self.frame + 1) % frames.len();
+        if frames.len() == 1{
+            self.drawn = true;
+        }else{
+            self.next_frame = Some(Instant::now() + Duration::from_millis(header.delay_ms as u64));
+        }
+        true
+    }
+
+    //等待duration，期间收到图像或命令时立即返回，交给下一次try_receive。
+    //receive_message在watchdog::core1_waiting中等待，core1在空闲画面中每隔HEARTBEAT_INTERVAL至少报告一次，长的帧间隔不会触发看门狗
+    async fn wait(&mut self, duration: Duration){
+        if self.message.is_none(){
+            self.message = receive_message(Some(duration)).await;
+        }
+    }
+}
+
+//解压一帧并绘制，直接解压到u16数组中，不需要额外的内存
+async fn draw_frame<S: Screen>(screen: &mut S, header: &SplashHeader, frame: &[u8]){
+    //跳过compress_prepend_size写入的4字节长度
+    if frame.len() < 4{
+        return;
+    }
+    let mut pixels = vec![0u16; header.width as usize * header.height as usize];
+    match lz4_flex::block::decompress_into(&frame[4..], pixels.as_mut_byte_slice()){
+        Ok(len) if len == header.frame_bytes() => (),
+        _ => return,
+    }
+    for pixel in pixels.iter_mut(){
+        *pixel = u16::from_be(*pixel);
+    }
+    screen.draw_pixels(&pixels, header.x, header.y, header.width, header.height).await;
+}
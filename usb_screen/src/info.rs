@@ -0,0 +1,61 @@
+//READ_INF命令返回的设备信息: 串口号;键=值;键=值...
+
+use core::cell::Cell;
+use alloc::format;
+use alloc::string::String;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use portable_atomic::{AtomicU32, Ordering};
+use usb_screen_core::detect::{Controller, Detection};
+
+//精灵缓存的剩余空间和总容量(字节)，由core1更新，core0读取
+pub static SPRITE_CACHE_FREE: AtomicU32 = AtomicU32::new(0);
+pub static SPRITE_CACHE_SIZE: AtomicU32 = AtomicU32::new(0);
+//启动时读取的屏幕控制器ID，None表示没有读取(st7789-240x240使用的st7789库不支持读取)
+static PANEL_DETECTION: Mutex<CriticalSectionRawMutex, Cell<Option<Detection>>> = Mutex::new(Cell::new(None));
+
+//编译时选择的屏幕驱动支持的控制器，识别出其他型号时报告panel_mismatch=1
+#[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
+const SUPPORTED_CONTROLLERS: &[Controller] = &[Controller::St7735];
+#[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+const SUPPORTED_CONTROLLERS: &[Controller] = &[Controller::St7789, Controller::Ili9341];
+
+pub fn set_panel_detection(detection: Detection){
+    PANEL_DETECTION.lock(|d| d.set(Some(detection)));
+}
+
+//识别出的控制器与固件不符时在空闲画面上显示的提示，没有识别出型号或相符时为None
+pub fn panel_mismatch_warning() -> Option<String>{
+    let detection = PANEL_DETECTION.lock(|d| d.get())?;
+    let controller = detection.controller.filter(|_| detection.mismatch(SUPPORTED_CONTROLLERS))?;
+    Some(format!(
+        "{} PANEL DETECTED\nFIRMWARE IS FOR {}\nFLASH THE {} FIRMWARE",
+        controller.name(),
+        SUPPORTED_CONTROLLERS[0].name(),
+        controller.name()
+    ))
+}
+
+pub fn device_info(serial_number: &str) -> String{
+    let mut info = format!(
+        "{serial_number};sprite={}/{};frame={}",
+        SPRITE_CACHE_FREE.load(Ordering::Relaxed),
+        SPRITE_CACHE_SIZE.load(Ordering::Relaxed),
+        crate::FRAME_BUFFER
+    );
+    //ST7789在core0解压的横条大小，主机按它分割大图像
+    #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+    info.push_str(&format!(";stripe={}", crate::DECODE_STRIPE));
+    let (reason, watchdog_resets) = crate::watchdog::reset_reason();
+    info.push_str(&format!(";reset={};watchdog_resets={watchdog_resets}", reason.as_str()));
+    //识别出其他型号时固件仍按编译时选择的屏幕初始化，主机提示更换固件
+    if let Some(detection) = PANEL_DETECTION.lock(|d| d.get()){
+        info.push_str(&detection.info(SUPPORTED_CONTROLLERS));
+    }
+    //上次运行时panic的位置和信息，分号和换行会破坏格式，替换掉
+    if let Some(message) = crate::panic::last_panic(){
+        info.push_str(";panic=");
+        info.extend(message.chars().map(|c| if c == ';' || c == '\n'{ ',' }else{ c }));
+    }
+    info
+}
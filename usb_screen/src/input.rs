@@ -0,0 +1,71 @@
+//按键和旋转编码器：引脚在设置中配置(见usb_screen_core::input)，重启后生效。
+//在core0中扫描，事件发送给USB任务，通过HID键盘和USB Raw接口发送给主机。
+
+use alloc::vec::Vec;
+use embassy_rp::gpio::{AnyPin, Input, Pull};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Ticker};
+use usb_screen_core::input::{Debouncer, InputConfig, InputEvent, KeyUsage, QuadratureDecoder, INPUT_BUTTON, INPUT_ENCODER};
+
+//扫描间隔，消抖需要连续4次采样相同(8ms)
+const SCAN_INTERVAL: Duration = Duration::from_millis(2);
+
+//屏幕、触摸和SPI使用的引脚，不能配置为按键或TE
+const RESERVED_PINS: &[u8] = &[4, 6, 7, 8, 9, 10, 13, 14, 15];
+
+//事件和对应的HID按键，满了以后丢弃
+pub static INPUT_CHANNEL: Channel<CriticalSectionRawMutex, (InputEvent, KeyUsage), 8> = Channel::new();
+
+//设置中配置的引脚
+pub fn configured_pin(pin: u8) -> Option<AnyPin>{
+    if pin > 29 || RESERVED_PINS.contains(&pin){
+        log::warn!("pin {pin} is not available");
+        return None;
+    }
+    //设置中的引脚不会被其他代码使用
+    Some(unsafe{ AnyPin::steal(pin) })
+}
+
+//按键按下时接地，使用内部上拉
+fn input_pin(pin: u8) -> Option<Input<'static, AnyPin>>{
+    Some(Input::new(configured_pin(pin)?, Pull::Up))
+}
+
+#[embassy_executor::task]
+pub async fn input_task(config: InputConfig){
+    let mut buttons: Vec<_> = config.buttons.iter().enumerate()
+        .filter_map(|(id, button)| {
+            let button = (*button)?;
+            Some((id as u16, input_pin(button.pin)?, button.usage, Debouncer::default()))
+        })
+        .collect();
+    let mut encoder = config.encoder.and_then(|encoder| {
+        let pin_a = input_pin(encoder.pin_a)?;
+        let pin_b = input_pin(encoder.pin_b)?;
+        Some((pin_a, pin_b, encoder, QuadratureDecoder::default()))
+    });
+    if buttons.is_empty() && encoder.is_none(){
+        return;
+    }
+    log::info!("input: {} buttons, encoder: {}", buttons.len(), encoder.is_some());
+
+    let mut ticker = Ticker::every(SCAN_INTERVAL);
+    loop {
+        ticker.next().await;
+        for (id, pin, usage, debouncer) in buttons.iter_mut(){
+            if let Some(pressed) = debouncer.update(pin.is_low()){
+                let event = InputEvent { kind: INPUT_BUTTON, id: *id, value: pressed as i16 };
+                let _ = INPUT_CHANNEL.try_send((event, *usage));
+            }
+        }
+        if let Some((pin_a, pin_b, config, decoder)) = encoder.as_mut(){
+            let step = decoder.update(pin_a.is_high(), pin_b.is_high());
+            if step != 0{
+                let usage = if step > 0{ config.clockwise }else{ config.counter_clockwise };
+                let event = InputEvent { kind: INPUT_ENCODER, id: 0, value: step as i16 };
+                let _ = INPUT_CHANNEL.try_send((event, usage));
+            }
+        }
+    }
+}
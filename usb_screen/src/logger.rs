@@ -0,0 +1,35 @@
+//日志输出到USB串口：主机通过USB Raw接口传输图像时，可以用串口工具查看设备日志
+
+use alloc::format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pipe::Pipe;
+use log::{LevelFilter, Metadata, Record};
+
+//日志缓冲区，满了以后新的日志直接丢弃，不会阻塞
+pub static LOG_PIPE: Pipe<CriticalSectionRawMutex, 1024> = Pipe::new();
+
+struct UsbLogger;
+
+impl log::Log for UsbLogger{
+    fn enabled(&self, _metadata: &Metadata) -> bool{
+        true
+    }
+
+    fn log(&self, record: &Record){
+        //日志中不能出现串口帧的分隔符0，主机读取回复时靠它区分日志和回复
+        let line = format!("[{}] {}\r\n", record.level(), record.args()).replace('\0', " ");
+        let _ = LOG_PIPE.try_write(line.as_bytes());
+    }
+
+    fn flush(&self){}
+}
+
+static LOGGER: UsbLogger = UsbLogger;
+
+//RP2040没有原子比较交换指令，在启动core1之前调用racy版本
+pub fn init(){
+    unsafe{
+        let _ = log::set_logger_racy(&LOGGER);
+        log::set_max_level_racy(LevelFilter::Info);
+    }
+}
@@ -8,16 +8,15 @@
 extern crate alloc;
 
 use core::mem::MaybeUninit;
-use alloc::vec;
 use alloc::vec::Vec;
-use embassy_executor::{Executor, Spawner};
+use embassy_executor::Executor;
 use embassy_rp::bind_interrupts;
-use embassy_rp::rom_data::reset_to_usb_boot;
 use embassy_rp::multicore::{spawn_core1, Stack};
 use embassy_rp::peripherals::{PIN_13, PIN_14, PIN_4, PIN_6, PIN_7, SPI0, USB};
-use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_rp::usb::InterruptHandler;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
+use embassy_futures::select::{select, select3, Either3};
 use embedded_alloc::Heap;
 use static_cell::StaticCell;
 #[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
@@ -29,12 +28,61 @@ mod st7789;
 // mod rgb2yuv;
 mod rgb565;
 mod splash;
+mod display;
+mod clock;
+mod commands;
+mod info;
+mod idle;
+mod input;
+mod logger;
+mod panic;
+mod receiver;
+mod stats;
+mod storage;
+#[cfg(feature = "st7789-240x320")]
+mod tearing;
+#[cfg(feature = "st7789-240x320")]
+mod touch;
+mod update;
+mod usb;
+mod watchdog;
 #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
 mod resize;
-use panic_halt as _;
+use commands::CommandHandler;
+use usb_screen_core::command::Command;
+use storage::Storage;
+use idle::IdleScreen;
+use usb_screen_core::frame::{Rect, StripeTracker};
+#[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+use usb_screen_core::frame::crop_image;
 
 pub const DISPLAY_FREQ: u32 = 64_000_000;
 
+//屏幕宽高(横屏)
+#[cfg(feature = "st7735-128x160")]
+pub const SCREEN_WIDTH: u16 = 160;
+#[cfg(feature = "st7735-128x160")]
+pub const SCREEN_HEIGHT: u16 = 128;
+#[cfg(feature = "st7735-128x128")]
+pub const SCREEN_WIDTH: u16 = 128;
+#[cfg(feature = "st7735-128x128")]
+pub const SCREEN_HEIGHT: u16 = 128;
+#[cfg(feature = "st7789-240x240")]
+pub const SCREEN_WIDTH: u16 = 240;
+#[cfg(feature = "st7789-240x240")]
+pub const SCREEN_HEIGHT: u16 = 240;
+#[cfg(feature = "st7789-240x320")]
+pub const SCREEN_WIDTH: u16 = 320;
+#[cfg(feature = "st7789-240x320")]
+pub const SCREEN_HEIGHT: u16 = 240;
+
+//一帧最多占用的内存(压缩数据加上解压后的图像)，通过设备信息的frame=告诉主机，超过时主机分成横条发送
+pub const FRAME_BUFFER: usize = usb_screen_core::frame::frame_buffer_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+
+//解压后不超过这个长度的横条在core0解压，core1同时绘制上一条，通过设备信息的stripe=告诉主机
+#[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+pub const DECODE_STRIPE: usize = SCREEN_WIDTH as usize * 2 * 40;
+
 #[cfg(feature = "serial-num-1")]
 static mut SERIAL_NUMBER: [u8; 16] = *b"USBSCR0000000001";
 #[cfg(feature = "serial-num-2")]
@@ -52,12 +100,22 @@ static mut SERIAL_NUMBER: [u8; 16] = *b"USBSCR0000000007";
 #[cfg(feature = "serial-num-8")]
 static mut SERIAL_NUMBER: [u8; 16] = *b"USBSCR0000000008";
 
-type ImageInfo = (Vec<u8>, u16, u16, u16, u16);
+//core0发给core1的图像数据
+enum ImageData{
+    //lz4压缩的整帧，由core1解压
+    #[allow(dead_code)]
+    Compressed(Vec<u8>),
+    //core0已经解压并裁剪到屏幕内的图像(Big-Endian)
+    Pixels(Vec<u8>),
+}
+
+type ImageInfo = (ImageData, u16, u16, u16, u16);
 
 static mut CORE1_STACK: Stack<4096> = Stack::new();
 static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
 static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
 static USB_CHANNEL: Channel<CriticalSectionRawMutex, ImageInfo, 1> = Channel::new();
+static COMMAND_CHANNEL: Channel<CriticalSectionRawMutex, Command, 2> = Channel::new();
 #[allow(dead_code)]
 static DRAW_CHANNEL: Channel<CriticalSectionRawMutex, u16, 1> = Channel::new();
 
@@ -68,16 +126,6 @@ static DISPLAY_LOCK: embassy_sync::mutex::Mutex<CriticalSectionRawMutex, core::c
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
-//图像传输开始标记(8字节)
-const IMAGE_AA:u64 = 7596835243154170209;
-//图像传输结束标记(8字节)
-const IMAGE_BB:u64 = 7596835243154170466;
-//重启到U盘模式命令(8字节)
-const BOOT_USB:u64 = 7093010483740242786;
-//读取设备信息(8字节) 串口读取信息使用
-const READ_INF:u64 = u64::from_be_bytes(*b"ReadInfo");
-const MAGIC_NUM_LEN: usize = 8;
-
 //embassy-executor使用12K, 堆内存使用剩余内存
 const HEAP_SIZE: usize = 1024*226; //经过测试200K内存不足够解压320x240的lz4图像
 static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
@@ -90,6 +138,36 @@ pub fn test_runner(_test: &[&dyn Fn()]) {
     loop {}
 }
 
+//core1从core0收到的图像或命令
+enum Message{
+    Image(ImageInfo),
+    Command(Command),
+}
+
+fn try_receive_message() -> Option<Message>{
+    if let Ok(command) = COMMAND_CHANNEL.try_receive(){
+        return Some(Message::Command(command));
+    }
+    USB_CHANNEL.try_receive().ok().map(Message::Image)
+}
+
+//等待图像或命令，超时或USB断开时返回None
+async fn receive_message(timeout: Option<embassy_time::Duration>) -> Option<Message>{
+    let idle = async {
+        match timeout{
+            Some(timeout) => {
+                select(embassy_time::Timer::after(timeout), idle::HOST_DISCONNECTED.wait()).await;
+            }
+            None => idle::HOST_DISCONNECTED.wait().await,
+        }
+    };
+    match watchdog::core1_waiting(select3(USB_CHANNEL.receive(), COMMAND_CHANNEL.receive(), idle)).await{
+        Either3::First(image) => Some(Message::Image(image)),
+        Either3::Second(command) => Some(Message::Command(command)),
+        Either3::Third(()) => None,
+    }
+}
+
 #[cortex_m_rt::entry]
 fn main() -> ! {
     {
@@ -124,6 +202,7 @@ fn main() -> ! {
         [使用lz4压缩格式速度]
         160x128 每帧9K，速度>56帧（传输18ms/解压8ms/绘制26ms） core0解压，core1绘制速度最快，可超过30帧。
         320x240 每帧16.3K，速度>31.4帧（传输32ms/解压18ms/绘制?ms）
+        以上为手工测量的数据，实际的每帧耗时、丢帧数和内存峰值可以用 usbscreen stats 读取(见stats模块)
         [直接传输RGB565内存占用]
         160x128: 接收缓冲区40K + 40K缓冲区。
         320x240: 接收缓冲区50K + 150K绘图缓冲区。
@@ -148,6 +227,17 @@ fn main() -> ! {
 
     unsafe{ SERIAL_NUMBER[6..6+screen_size.len()].copy_from_slice(screen_size.as_bytes()); }
 
+    //启动core1之前读取设置，core1根据设置决定空闲时显示的内容
+    logger::init();
+    panic::init();
+    let mut wdt = embassy_rp::watchdog::Watchdog::new(p.WATCHDOG);
+    watchdog::init(&mut wdt, panic::last_panic().is_some());
+    let mut storage = Storage::new(p.FLASH);
+    storage.load_settings();
+    //上次升级写入了新固件时，复制到固件区后重启，不会返回
+    storage.apply_pending_update();
+    clock::init(p.RTC);
+
     spawn_core1(
         p.CORE1,
         unsafe { &mut *core::ptr::addr_of_mut!(CORE1_STACK) },
@@ -164,331 +254,18 @@ fn main() -> ! {
                 }
                 #[cfg(feature = "st7789-240x320")]
                 {
-                    spawner.spawn(core1_task(p.SPI0, p.PIN_6, p.PIN_7, p.PIN_4, p.PIN_13, p.PIN_14, p.PIN_9)).unwrap();
+                    spawner.spawn(core1_task(p.SPI0, p.PIN_6, p.PIN_7, p.PIN_4, p.PIN_13, p.PIN_14, p.PIN_9, p.PIN_8, p.PIN_10)).unwrap();
                 }
             });
         },
     );
 
     let executor0 = EXECUTOR0.init(Executor::new());
-    #[cfg(feature = "usb-raw")]
-    executor0.run(|spawner| spawner.spawn(core0_task_usb_raw(p.USB, spawner.clone())).unwrap());
-
-    #[cfg(feature = "usb-serial")]
-    executor0.run(|spawner| spawner.spawn(core0_task_usb_serial(p.USB, spawner.clone())).unwrap());
-
-}
-
-//通过USB serial传输数据
-#[cfg(feature = "usb-serial")]
-#[embassy_executor::task]
-async fn core0_task_usb_serial(usb: USB, spawner: Spawner) {
-    use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
-
-    // Create the driver, from the HAL.
-    let driver = Driver::new(usb, Irqs);
-
-    // Create embassy-usb Config
-    let config = {
-        let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
-        config.manufacturer = Some("planet");
-        config.product = Some("USB Serial Screen");
-        //串号不能重复
-        let serial_number: &'static mut str = unsafe { core::str::from_utf8_unchecked_mut(&mut SERIAL_NUMBER[..]) };
-        config.serial_number = Some(serial_number);
-        config.max_power = 500;
-        config.max_packet_size_0 = 64;
-
-        // Required for windows compatibility.
-        // https://developer.nordicsemi.com/nRF_Connect_SDK/doc/1.9.1/kconfig/CONFIG_CDC_ACM_IAD.html#help
-        config.device_class = 0xEF;
-        config.device_sub_class = 0x02;
-        config.device_protocol = 0x01;
-        config.composite_with_iads = true;
-        config
-    };
-
-    // Create embassy-usb DeviceBuilder using the driver and config.
-    // It needs some buffers for building the descriptors.
-    let mut builder = {
-        static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
-        static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
-        static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
-        static DECICE_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
-        
-        let builder = embassy_usb::Builder::new(
-            driver,
-            config,
-            DECICE_DESCRIPTOR.init([0; 256]),
-            CONFIG_DESCRIPTOR.init([0; 256]),
-            BOS_DESCRIPTOR.init([0; 256]),
-            &mut [], // no msos descriptors
-            CONTROL_BUF.init([0; 64]),
-        );
-        builder
-    };
-
-    // Create classes on the builder.
-    let mut class = {
-        static STATE: StaticCell<State> = StaticCell::new();
-        let state = STATE.init(State::new());
-        CdcAcmClass::new(&mut builder, state, 64)
-    };
-
-    // Build the builder.
-    let usb = builder.build();
-
-    // Run the USB device.
-    spawner.spawn(usb_task(usb)).unwrap();
-
-    // 接收串口数据
-
-    let magic_num_buf = &mut [0u8; MAGIC_NUM_LEN];
-    let mut image_width = 0;
-    let mut image_height = 0;
-    let mut image_x = 0;
-    let mut image_y = 0;
-
-    //接收到的数据
-    let mut image_buf = vec::Vec::new();
-    
-    loop {
-        class.wait_connection().await;
-        
-        let mut buf = [0; 64];
-
-        loop {
-            let ret = class.read_packet(&mut buf).await;
-            if ret.is_err(){
-                continue;
-            }
-            let len = ret.unwrap();
-            let data = &buf[..len];
-
-            //串口数据有可能出错
-            if len < MAGIC_NUM_LEN{
-                continue;
-            }
-            
-            magic_num_buf.copy_from_slice(&data[0..MAGIC_NUM_LEN]);
-            let magic_num = u64::from_be_bytes(*magic_num_buf);
-            
-            if magic_num == IMAGE_AA{
-                image_width = u16::from_be_bytes([data[MAGIC_NUM_LEN], data[MAGIC_NUM_LEN+1]]);
-                image_height = u16::from_be_bytes([data[MAGIC_NUM_LEN+2], data[MAGIC_NUM_LEN+3]]);
-                image_x = u16::from_be_bytes([data[MAGIC_NUM_LEN+4], data[MAGIC_NUM_LEN+5]]);
-                image_y = u16::from_be_bytes([data[MAGIC_NUM_LEN+6], data[MAGIC_NUM_LEN+7]]);
-                image_buf.clear();
-            }else if magic_num == IMAGE_BB{
-                #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
-                {
-                    loop{
-                        if let Ok(mut lock) = DISPLAY_LOCK.try_lock(){
-                            if *lock.get_mut() == false{
-                                break;
-                            }
-                        }
-                        embassy_time::Timer::after_millis(1).await;
-                    }
-                    let _ = USB_CHANNEL.try_send((image_buf.clone(), image_x, image_y, image_width, image_height));
-                    image_buf.clear();
-                }
-                
-                #[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
-                {
-                    if let Ok(image) = lz4_flex::decompress_size_prepended(&image_buf){
-                        let _ = USB_CHANNEL.try_send((image, image_x, image_y, image_width, image_height));
-                    }
-                    image_buf.clear();
-                }
-            }else if magic_num == BOOT_USB{
-                reset_to_usb_boot(0, 0);
-            }else if magic_num == READ_INF{
-                //返回串口号
-                let serial_number: &'static mut str = unsafe { core::str::from_utf8_unchecked_mut(&mut SERIAL_NUMBER[..]) };
-                let _ = class.write_packet(serial_number.as_bytes()).await;
-            }else{
-                //图像传输中
-                if image_buf.len() <320*240*2{
-                    image_buf.extend_from_slice(data);
-                }
-            }
-        }
-    }
-}
-
-#[cfg(feature = "usb-serial")]
-type MyUsbDriver = Driver<'static, USB>;
-#[cfg(feature = "usb-serial")]
-type MyUsbDevice = embassy_usb::UsbDevice<'static, MyUsbDriver>;
-
-#[cfg(feature = "usb-serial")]
-#[embassy_executor::task]
-async fn usb_task(mut usb: MyUsbDevice) -> ! {
-    usb.run().await
-}
-
-// 通过USB Raw Bulk接收数据
-#[cfg(feature = "usb-raw")]
-#[embassy_executor::task]
-async fn core0_task_usb_raw(usb: USB, _spawner: Spawner) {
-    use embassy_usb::driver::{Endpoint, EndpointOut};
-    use embassy_usb::Config;
-    use embassy_usb::Builder;
-    use embassy_usb::msos::windows_version;
-    use futures::future::join;
-    
-    //-------------- 初始化 usb ------------------------
-    
-    // Create the driver, from the HAL.
-    let driver = Driver::new(usb, Irqs);
-
-    // Create embassy-usb Config
-    let mut config = Config::new(0xc0de, 0xcafe);
-    config.manufacturer = Some("planet");
-    config.product = Some("USB Screen");
-    let serial_number: &'static mut str = unsafe { core::str::from_utf8_unchecked_mut(&mut SERIAL_NUMBER[..]) };
-    config.serial_number = Some(serial_number);
-    config.max_power = 500;
-    config.max_packet_size_0 = 64;
-
-    // Required for windows compatibility.
-    // https://developer.nordicsemi.com/nRF_Connect_SDK/doc/1.9.1/kconfig/CONFIG_CDC_ACM_IAD.html#help
-    config.device_class = 0xEF;
-    config.device_sub_class = 0x02;
-    config.device_protocol = 0x01;
-    config.composite_with_iads = true;
-
-    // Create embassy-usb DeviceBuilder using the driver and config.
-    // It needs some buffers for building the descriptors.
-    let mut config_descriptor = [0; 256];
-    let mut bos_descriptor = [0; 256];
-    let mut device_descriptor = [0; 256];
-    let mut msos_descriptor = [0; 256];
-    let mut control_buf = [0; 64];
-
-    let mut builder = Builder::new(
-        driver,
-        config,
-        &mut device_descriptor,
-        &mut config_descriptor,
-        &mut bos_descriptor,
-        &mut msos_descriptor,
-        &mut control_buf,
-    );
-
-    // 这是一个随机生成的 GUID，允许 Windows 上的客户端找到我们的设备
-    const DEVICE_INTERFACE_GUIDS: &[&str] = &["{705E1599-5BFF-8DA9-6E33-7141B0636461}"];
-
-    // Add the Microsoft OS Descriptor (MSOS/MOD) descriptor.
-    // We tell Windows that this entire device is compatible with the "WINUSB" feature,
-    // which causes it to use the built-in WinUSB driver automatically, which in turn
-    // can be used by libusb/rusb software without needing a custom driver or INF file.
-    // In principle you might want to call msos_feature() just on a specific function,
-    // if your device also has other functions that still use standard class drivers.
-    builder.msos_descriptor(windows_version::WIN8_1, 0);
-    builder.msos_feature(embassy_usb::msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
-    builder.msos_feature(embassy_usb::msos::RegistryPropertyFeatureDescriptor::new(
-        "DeviceInterfaceGUIDs",
-        embassy_usb::msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
-    ));
-
-    // Add a vendor-specific function (class 0xFF), and corresponding interface,
-    // that uses our custom handler.
-    let mut function = builder.function(0xFF, 0, 0);
-    let mut interface = function.interface();
-    let mut alt = interface.alt_setting(0xFF, 0, 0, None);
-    let mut read_ep = alt.endpoint_bulk_out(64);
-    #[allow(unused_mut, unused_variables)]
-    let mut write_ep = alt.endpoint_bulk_in(64);
-    drop(function);
-
-    // Build the builder.
-    let mut usb = builder.build();
-
-    // Run the USB device.
-    let usb_fut = usb.run();
-
-    let magic_num_buf = &mut [0u8; MAGIC_NUM_LEN];
-    let mut image_width = 0;
-    let mut image_height = 0;
-    let mut image_x = 0;
-    let mut image_y = 0;
-
-    //接收到的数据
-    let mut buf = vec::Vec::new();
-
-    //图像接收任务
-    let echo_fut = async {
-        loop {
-            read_ep.wait_enabled().await;
-            loop {
-                let mut data = [0; 64];
-                match read_ep.read(&mut data).await {
-                    Ok(n) => {
-                        magic_num_buf.copy_from_slice(&data[0..MAGIC_NUM_LEN]);
-                        let magic_num = u64::from_be_bytes(*magic_num_buf);
-                        
-                        if magic_num == IMAGE_AA{
-                            //图像开始
-                            image_width = u16::from_be_bytes([data[MAGIC_NUM_LEN], data[MAGIC_NUM_LEN+1]]);
-                            image_height = u16::from_be_bytes([data[MAGIC_NUM_LEN+2], data[MAGIC_NUM_LEN+3]]);
-                            image_x = u16::from_be_bytes([data[MAGIC_NUM_LEN+4], data[MAGIC_NUM_LEN+5]]);
-                            image_y = u16::from_be_bytes([data[MAGIC_NUM_LEN+6], data[MAGIC_NUM_LEN+7]]);
-                            //清空数据
-                            buf.clear();
-
-                            //往回传输数据(往USB写入数据后，USB主机必须读取，否则会导致程序卡死)
-                            // let msg = format!("rev:{image_x}x{image_y} {image_width}x{image_height}");
-                            // write_ep.write(msg.as_byte_slice()).await.ok();
-                        }else if magic_num == IMAGE_BB{
-
-                            //240x320屏幕占用内存较大，绘制的同时再解压数据内存不够用（150K*2），所以仅缓存一次接收到的压缩数据
-                            //等待core1解压绘制完成后，再发送新的压缩帧，达到12帧左右的速度
-                            #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
-                            {
-                                //如果正在绘制中，等待绘制完成
-                                loop{
-                                    if let Ok(mut lock) = DISPLAY_LOCK.try_lock(){
-                                        if *lock.get_mut() == false{
-                                            break;
-                                        }
-                                    }
-                                    embassy_time::Timer::after_millis(1).await;
-                                }
-                                //压缩图像结束，发送数据到core1线程
-                                let _ = USB_CHANNEL.try_send((buf.clone(), image_x, image_y, image_width, image_height));
-                                //清空数组
-                                buf.clear();
-                            }
-                            
-                            //160x128屏幕，在core0解压，core1绘制速度最快
-                            #[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
-                            {
-                                let image = lz4_flex::decompress_size_prepended(&buf).unwrap();
-                                buf.clear();
-                                //解压后的图像结束，发送数据到core1线程
-                                let _ = USB_CHANNEL.try_send((image, image_x, image_y, image_width, image_height));
-                            }
-
-                        }else if magic_num == BOOT_USB{
-                            reset_to_usb_boot(0, 0);
-                        }else{
-                            //图像传输中
-                            if buf.len() <320*240*2{
-                                buf.extend_from_slice(&data[0..n]);
-                            }
-                        }
-                    }
-                    Err(_) => break,
-                }
-            }
-        }
-    };
-
-    // Run everything concurrently.
-    // If we had made everything `'static` above instead, we could do this using separate tasks instead.
-    join(usb_fut, echo_fut).await;
+    executor0.run(|spawner| {
+        spawner.spawn(input::input_task(usb_screen_core::input::InputConfig::from_bits(&storage::settings().inputs))).unwrap();
+        spawner.spawn(usb::core0_task_usb(p.USB, storage)).unwrap();
+        spawner.spawn(watchdog::watchdog_task(wdt)).unwrap();
+    });
 }
 
 #[cfg(any(feature = "st7735-128x160",feature = "st7735-128x128"))]
@@ -497,21 +274,33 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     use byte_slice_cast::AsByteSlice;
 
     let mut display_manager = st7735::ST7735DisplayManager::new(spi, p6, p7, p4, p13, p14, dma_ch0, dma_ch1).await.unwrap();
+    //Flash中保存的反色和Gamma曲线
+    display::Screen::set_panel(&mut display_manager, &storage::settings().panel).await;
 
     let mut splash = splash::Controller::new(embassy_rp::clocks::RoscRng);
     let mut frame_received = false;
     //在没有图像传输时，绘制吃豆人图像
     let mut canvas = splash::Canvas::new();
+    let mut commands = CommandHandler::new();
+    let mut idle = IdleScreen::new();
+    let mut frames = StripeTracker::default();
 
     loop {
+        //每处理一条消息或绘制一帧空闲画面报告一次，SPI传输卡住时看门狗复位
+        watchdog::core1_alive();
         //没有接收到任何图像时，循环绘制吃豆人
-        let (image, x, y, width, height) = if !frame_received{
-            match USB_CHANNEL.try_receive(){
-                Ok(ret) => {
+        let message = if !frame_received{
+            match idle.try_receive(){
+                Some(ret) => {
                     frame_received = true;
+                    idle.leave();
                     ret
                 }
-                Err(_) => {
+                None => {
+                    //自定义开机画面或黑屏
+                    if idle.draw(&mut display_manager).await{
+                        continue;
+                    }
                     splash.update();
                     splash.render(&mut canvas);
                     display_manager.display_image_be(canvas.buf.as_byte_slice(), 0, 0, canvas.width as u16, canvas.height as u16).await;
@@ -519,13 +308,39 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
                 }
             }
         }else{
-            //一旦从USB接收到图像，就一直等待图像到达
-            USB_CHANNEL.receive().await
+            //一旦从USB接收到图像，就一直等待图像到达，超时或USB断开后回到空闲画面
+            match receive_message(idle::idle_timeout()).await{
+                Some(message) => message,
+                None => {
+                    frame_received = false;
+                    idle.enter(&mut display_manager).await;
+                    continue;
+                }
+            }
+        };
+
+        let (image, x, y, width, height) = match message{
+            //ST7735的图像都已经由core0解压
+            Message::Image((ImageData::Pixels(image), x, y, width, height)) => (image, x, y, width, height),
+            Message::Image(_) => continue,
+            Message::Command(command) => {
+                commands.handle(&mut display_manager, command).await;
+                continue;
+            }
         };
         
         //绘制
         //全屏绘制可达到40帧左右(core0解压)
+        let start = embassy_time::Instant::now();
         display_manager.display_image_be(&image, x, y, width, height).await;
+        let draw_us = stats::elapsed_us(start);
+        let new_frame = frames.new_frame(&Rect{ x, y, width, height });
+        stats::update(|s| {
+            s.draw.record(draw_us);
+            if new_frame{
+                s.frames += 1;
+            }
+        });
         //释放内存
         drop(image);
     }
@@ -536,15 +351,17 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
 //参考代码：https://github.com/embassy-rs/embassy/blob/main/examples/rp/src/bin/spi_display.rs
 #[cfg(feature = "st7789-240x320")]
 #[embassy_executor::task]
-async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14: PIN_14, display_cs: embassy_rp::peripherals::PIN_9) {
+async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14: PIN_14, display_cs: embassy_rp::peripherals::PIN_9, touch_cs: embassy_rp::peripherals::PIN_8, touch_irq: embassy_rp::peripherals::PIN_10) {
     use core::{cell::RefCell, f32::consts::PI};
 
     use embassy_embedded_hal::shared_bus::blocking::spi::SpiDeviceWithConfig;
-    use embassy_rp::{clocks::RoscRng, gpio::{Level, Output}, spi::{self, Blocking, Spi}};
+    use embassy_futures::select::Either;
+    use embassy_rp::{clocks::RoscRng, gpio::{Input, Level, Output, Pull}, spi::{self, Blocking, Spi}};
     use embassy_time::{Duration, Timer};
     use rgb565::rgb_to_rgb565;
     use splash::utils::random_usize;
-    use st7789::{interface::SPIDeviceInterface, Orientation, ST7789};
+    use st7789::{interface::SPIDeviceInterface, Orientation, TearingEffect, ST7789};
+    use usb_screen_core::detect::Controller;
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
     use embassy_sync::blocking_mutex::Mutex;
     use micromath::F32Ext;
@@ -558,13 +375,20 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     AO > dc(PIN13)
     CS > cs(PIN9)
     BL > bl(VCC)
+
+    XPT2046触摸(可选，和屏幕共用SCL、SDA):
+    T_DO > miso(PIN4)
+    T_CS > cs(PIN8)
+    T_IRQ > irq(PIN10)
     */
     
     let spi_sclk = p6;
     let spi_mosi = p7;
     let spi_miso = p4;
-    let dc = Output::new(p13, Level::Low);
-    let rst: Output<PIN_14> = Output::new(p14, Level::Low);
+    let mut dc = Output::new(p13, Level::Low);
+    //释放复位，读取控制器ID前需要等待复位完成
+    let rst: Output<PIN_14> = Output::new(p14, Level::High);
+    Timer::after_millis(120).await;
 
     // create SPI
     let mut display_config = spi::Config::default();
@@ -572,12 +396,31 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     // display_config.phase = spi::Phase::CaptureOnSecondTransition;
     // display_config.polarity = spi::Polarity::IdleHigh;
 
-    let touch_config = spi::Config::default();
+    let mut touch_config = spi::Config::default();
+    touch_config.frequency = touch::TOUCH_FREQ;
 
     let spi: Spi<'_, _, Blocking> = Spi::new_blocking(spi, spi_sclk, spi_mosi, spi_miso, touch_config.clone());
     let spi_bus: Mutex<NoopRawMutex, _> = Mutex::new(RefCell::new(spi));
 
-    let display_spi = SpiDeviceWithConfig::new(&spi_bus, Output::new(display_cs, Level::High), display_config);
+    //通过MISO(和触摸共用)读取屏幕控制器ID，ILI9341使用自己的初始化参数，没有回复时按ST7789初始化
+    let mut display_cs = Output::new(display_cs, Level::High);
+    let mut detect_config = spi::Config::default();
+    detect_config.frequency = usb_screen_core::detect::READ_FREQ;
+    let detection = st7789::interface::detect(&mut SpiDeviceWithConfig::new(&spi_bus, &mut display_cs, detect_config), &mut dc);
+    info::set_panel_detection(detection);
+    let controller = match detection.controller{
+        Some(controller @ (Controller::St7789 | Controller::Ili9341)) => controller,
+        Some(controller) => {
+            log::warn!("detected {} panel, the firmware is built for ST7789 240x320, flash the firmware for this panel", controller.name());
+            Controller::St7789
+        }
+        None => Controller::St7789,
+    };
+
+    let display_spi = SpiDeviceWithConfig::new(&spi_bus, display_cs, display_config);
+    //没有接触摸屏时IRQ保持高电平，不会读取
+    let touch_spi = SpiDeviceWithConfig::new(&spi_bus, Output::new(touch_cs, Level::High), touch_config);
+    let mut touch = touch::TouchPanel::new(touch_spi, Input::new(touch_irq, Pull::Up));
     
     // display interface abstraction from SPI and DC
     let di = SPIDeviceInterface::new(display_spi, dc);
@@ -588,6 +431,7 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     let mut display = ST7789::new(di, rst, 240, 320);
     #[cfg(feature = "st7789-240x240")]
     let mut display = ST7789::new(di, rst, 240, 240);
+    display.set_controller(controller);
 
     #[cfg(feature = "st7789-240x320")]
     let screen_width = 320;
@@ -601,7 +445,14 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     // initialize
     display.init().await.unwrap();
     display.set_orientation(Orientation::Landscape).unwrap();
+    //Flash中保存的反色和Gamma曲线
+    display::Screen::set_panel(&mut display, &storage::settings().panel).await;
     st7789::interface::clear_rect(&mut display, rgb_to_rgb565(0, 0, 0), 0, 0, screen_width, screen_height);
+    //接了TE引脚时让屏幕在垂直消隐期间输出TE信号
+    let mut tearing = tearing::TearingSync::new(storage::settings().te_pin);
+    if tearing.connected(){
+        let _ = display.set_tearing_effect(TearingEffect::Vertical);
+    }
     
     let mut frame_received = false;
     let mut clear = false;
@@ -611,16 +462,27 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     let mut cy = 120.;
     let mut scale = 5.0;
     let mut depress = 5.0;
+    let mut commands = CommandHandler::new();
+    let mut idle = IdleScreen::new();
+    let mut frames = StripeTracker::default();
 
     loop {
+        //每处理一条消息或绘制一帧空闲画面报告一次，SPI传输卡住时看门狗复位
+        watchdog::core1_alive();
         //没有接收到任何图像时，循环绘制图案
-        let (compressed, x, y, width, height) = if !frame_received{
-            match USB_CHANNEL.try_receive(){
-                Ok(ret) => {
+        let message = if !frame_received{
+            match idle.try_receive(){
+                Some(ret) => {
                     frame_received = true;
+                    idle.leave();
                     ret
                 }
-                Err(_) => {
+                None => {
+                    touch.poll();
+                    //自定义开机画面或黑屏
+                    if idle.draw(&mut display).await{
+                        continue;
+                    }
                     if !clear{
                         t = random_usize(&mut RoscRng, 3, 8) as f32;
                         d = random_usize(&mut RoscRng, 40, 100) as f32;
@@ -663,26 +525,86 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
                 }
             }
         }else{
-            //一旦从USB接收到图像，就一直等待图像到达
-            USB_CHANNEL.receive().await
+            //一旦从USB接收到图像，就一直等待图像到达，超时或USB断开后回到空闲画面，等待的同时读取触摸屏
+            match select(receive_message(idle::idle_timeout()), touch.wait()).await{
+                Either::Second(()) => {
+                    touch.sample();
+                    continue;
+                }
+                Either::First(Some(message)) => message,
+                Either::First(None) => {
+                    frame_received = false;
+                    clear = false;
+                    idle.enter(&mut display).await;
+                    continue;
+                }
+            }
         };
 
         let mut lock = DISPLAY_LOCK.lock().await;
         *lock.get_mut() = true;
 
-        //解压 如果是串口传输，有可能出现错误帧，这里要进行判断
-        let image = match lz4_flex::decompress_size_prepended(&compressed){
-            Err(_err) => {
+        let (data, x, y, width, height) = match message{
+            Message::Image(image) => image,
+            Message::Command(command) => {
+                commands.handle(&mut display, command).await;
                 *lock.get_mut() = false;
                 drop(lock);
                 continue;
             }
-            Ok(image) => image
         };
-        drop(compressed);
 
+        let (image, rect, decompress_us) = match data{
+            //core0已经解压的横条，core1绘制的同时core0解压下一条
+            ImageData::Pixels(image) => (image, Rect{ x, y, width, height }, None),
+            ImageData::Compressed(compressed) => {
+                //解压 如果是串口传输，有可能出现错误帧，这里要进行判断
+                let start = embassy_time::Instant::now();
+                let mut image = match lz4_flex::decompress_size_prepended(&compressed){
+                    Err(_err) => {
+                        stats::update(|s| s.decode_errors += 1);
+                        *lock.get_mut() = false;
+                        drop(lock);
+                        continue;
+                    }
+                    Ok(image) => image
+                };
+                let decompress_us = stats::elapsed_us(start);
+                //压缩数据和解压后的图像同时存在时内存使用最多
+                stats::sample_heap();
+                drop(compressed);
+
+                //裁剪到屏幕内，长度与宽高不符时丢弃
+                match crop_image(&mut image, &Rect{ x, y, width, height }, SCREEN_WIDTH, SCREEN_HEIGHT){
+                    Ok(rect) => (image, rect, Some(decompress_us)),
+                    Err(err) => {
+                        stats::reject(err);
+                        *lock.get_mut() = false;
+                        drop(lock);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        //开启TE同步时等待垂直消隐，等待时间不计入绘制耗时；分成横条的一帧只在第一条之前等待
+        let new_frame = frames.new_frame(&rect);
+        if new_frame{
+            tearing.wait().await;
+        }
         //调用draw_rgb565_u8速度最快，使用Big-Endian
-        st7789::interface::draw_rgb565_u8(&mut display, &image, x, y, width, height);
+        let start = embassy_time::Instant::now();
+        st7789::interface::draw_rgb565_u8(&mut display, &image, rect.x, rect.y, rect.width, rect.height);
+        let draw_us = stats::elapsed_us(start);
+        stats::update(|s| {
+            if let Some(decompress_us) = decompress_us{
+                s.decompress.record(decompress_us);
+            }
+            s.draw.record(draw_us);
+            if new_frame{
+                s.frames += 1;
+            }
+        });
         //释放内存
         drop(image);
         // DRAW_CHANNEL.send(0).await;
@@ -767,6 +689,7 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     display.init(&mut embassy_time::Delay).unwrap();
     // set default orientation
     display.set_orientation(st7789::Orientation::Landscape).unwrap();
+    display::Screen::set_panel(&mut display, &storage::settings().panel).await;
     st7789_240x240::clear_rect(&mut display, rgb_to_rgb565(0, 0, 0), 0, 0, screen_width, screen_height);
     
     let mut frame_received = false;
@@ -777,16 +700,26 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
     let mut cy = 120.;
     let mut scale = 5.0;
     let mut depress = 5.0;
+    let mut commands = CommandHandler::new();
+    let mut idle = IdleScreen::new();
+    let mut frames = StripeTracker::default();
 
     loop {
+        //每处理一条消息或绘制一帧空闲画面报告一次，SPI传输卡住时看门狗复位
+        watchdog::core1_alive();
         //没有接收到任何图像时，循环绘制图案
-        let (compressed, x, y, width, height) = if !frame_received{
-            match USB_CHANNEL.try_receive(){
-                Ok(ret) => {
+        let message = if !frame_received{
+            match idle.try_receive(){
+                Some(ret) => {
                     frame_received = true;
+                    idle.leave();
                     ret
                 }
-                Err(_) => {
+                None => {
+                    //自定义开机画面或黑屏
+                    if idle.draw(&mut display).await{
+                        continue;
+                    }
                     if !clear{
                         t = random_usize(&mut RoscRng, 3, 8) as f32;
                         d = random_usize(&mut RoscRng, 40, 100) as f32;
@@ -829,26 +762,78 @@ async fn core1_task(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14
                 }
             }
         }else{
-            //一旦从USB接收到图像，就一直等待图像到达
-            USB_CHANNEL.receive().await
+            //一旦从USB接收到图像，就一直等待图像到达，超时或USB断开后回到空闲画面
+            match receive_message(idle::idle_timeout()).await{
+                Some(message) => message,
+                None => {
+                    frame_received = false;
+                    clear = false;
+                    idle.enter(&mut display).await;
+                    continue;
+                }
+            }
         };
 
         let mut lock = DISPLAY_LOCK.lock().await;
         *lock.get_mut() = true;
 
-        //解压 如果是串口传输，有可能出现错误帧，这里要进行判断
-        let image = match lz4_flex::decompress_size_prepended(&compressed){
-            Err(_err) => {
+        let (data, x, y, width, height) = match message{
+            Message::Image(image) => image,
+            Message::Command(command) => {
+                commands.handle(&mut display, command).await;
                 *lock.get_mut() = false;
                 drop(lock);
                 continue;
             }
-            Ok(image) => image
         };
-        drop(compressed);
+
+        let (image, rect, decompress_us) = match data{
+            //core0已经解压的横条，core1绘制的同时core0解压下一条
+            ImageData::Pixels(image) => (image, Rect{ x, y, width, height }, None),
+            ImageData::Compressed(compressed) => {
+                //解压 如果是串口传输，有可能出现错误帧，这里要进行判断
+                let start = embassy_time::Instant::now();
+                let mut image = match lz4_flex::decompress_size_prepended(&compressed){
+                    Err(_err) => {
+                        stats::update(|s| s.decode_errors += 1);
+                        *lock.get_mut() = false;
+                        drop(lock);
+                        continue;
+                    }
+                    Ok(image) => image
+                };
+                let decompress_us = stats::elapsed_us(start);
+                //压缩数据和解压后的图像同时存在时内存使用最多
+                stats::sample_heap();
+                drop(compressed);
+
+                //裁剪到屏幕内，长度与宽高不符时丢弃
+                match crop_image(&mut image, &Rect{ x, y, width, height }, SCREEN_WIDTH, SCREEN_HEIGHT){
+                    Ok(rect) => (image, rect, Some(decompress_us)),
+                    Err(err) => {
+                        stats::reject(err);
+                        *lock.get_mut() = false;
+                        drop(lock);
+                        continue;
+                    }
+                }
+            }
+        };
 
         //调用draw_rgb565_u8速度最快，使用Big-Endian
-        st7789_240x240::draw_rgb565_u8(&mut display, &image, x, y, width, height);
+        let start = embassy_time::Instant::now();
+        st7789_240x240::draw_rgb565_u8(&mut display, &image, rect.x, rect.y, rect.width, rect.height);
+        let draw_us = stats::elapsed_us(start);
+        let new_frame = frames.new_frame(&rect);
+        stats::update(|s| {
+            if let Some(decompress_us) = decompress_us{
+                s.decompress.record(decompress_us);
+            }
+            s.draw.record(draw_us);
+            if new_frame{
+                s.frames += 1;
+            }
+        });
         //释放内存
         drop(image);
         // DRAW_CHANNEL.send(0).await;
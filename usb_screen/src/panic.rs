@@ -0,0 +1,124 @@
+//panic处理：在屏幕上显示panic的位置和信息，保存到复位后不会清除的RAM中，几秒后重启。
+//重启后通过日志和设备信息(ReadInfo中的panic=...)报告给主机。
+//panic时其他代码可能正持有屏幕或者堆内存，这里直接重新初始化SPI写屏幕，不分配内存。
+
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::string::String;
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::spi::{self, Spi};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use usb_screen_core::crash::{wrap_lines, CrashRecord, FixedWriter, CRASH_MESSAGE_LEN};
+use usb_screen_core::font::{glyph, CELL_HEIGHT, CELL_WIDTH, GLYPH_WIDTH};
+
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//.uninit段不会在启动时清零，看门狗复位和软件复位后内容不变
+#[link_section = ".uninit.CRASH_RECORD"]
+static mut CRASH_RECORD: MaybeUninit<CrashRecord> = MaybeUninit::uninit();
+
+//上次运行时的panic信息，启动时读取
+static LAST_PANIC: Mutex<CriticalSectionRawMutex, RefCell<Option<String>>> = Mutex::new(RefCell::new(None));
+
+//panic处理中再次panic时直接重启
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+const BACKGROUND: u16 = 0x8000;
+const FOREGROUND: u16 = 0xFFFF;
+//显示panic信息后等待重启的时间(时钟周期，125MHz下约5秒)
+const REBOOT_DELAY_CYCLES: u32 = 125_000_000 * 5;
+
+//启动时调用：读取并清除上次的panic信息
+pub fn init(){
+    //CrashRecord只包含整数，任意数据都是有效值，是否保存过由魔数和CRC判断
+    let message = unsafe{ (*core::ptr::addr_of_mut!(CRASH_RECORD)).assume_init_mut().take() };
+    if let Some(message) = &message{
+        log::error!("last run panicked: {message}");
+    }
+    LAST_PANIC.lock(|p| *p.borrow_mut() = message);
+}
+
+//上次运行时的panic信息
+pub fn last_panic() -> Option<String>{
+    LAST_PANIC.lock(|p| p.borrow().clone())
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> !{
+    cortex_m::interrupt::disable();
+    if PANICKING.load(Ordering::Relaxed){
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+    PANICKING.store(true, Ordering::Relaxed);
+    //显示panic信息期间不喂狗，先停止看门狗
+    crate::watchdog::disable();
+
+    let mut message = FixedWriter::<CRASH_MESSAGE_LEN>::new();
+    let _ = write!(message, "{info}");
+    unsafe{ (*core::ptr::addr_of_mut!(CRASH_RECORD)).write(CrashRecord::new()).store(message.as_str()) };
+
+    //core0 panic时core1可能正在绘制，先停止core1
+    if embassy_rp::pac::SIO.cpuid().read() == 0{
+        embassy_rp::pac::PSM.frce_off().modify(|w| w.set_proc1(true));
+    }
+    draw_panic(message.as_str());
+
+    cortex_m::asm::delay(REBOOT_DELAY_CYCLES);
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+//屏幕已经初始化并设置为横屏，只需要设置窗口写入像素
+fn draw_panic(message: &str){
+    let p = unsafe{ embassy_rp::Peripherals::steal() };
+    let mut config = spi::Config::default();
+    config.frequency = crate::DISPLAY_FREQ;
+    #[cfg(feature = "st7789-240x240")]
+    {
+        config.phase = spi::Phase::CaptureOnSecondTransition;
+        config.polarity = spi::Polarity::IdleHigh;
+    }
+    let mut spi = Spi::new_blocking_txonly(p.SPI0, p.PIN_6, p.PIN_7, config);
+    let mut dc = Output::new(p.PIN_13, Level::Low);
+    //ST7735的CS直接接地
+    #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+    let _cs = Output::new(p.PIN_9, Level::Low);
+
+    let mut command = |cmd: u8, data: &[u8]| {
+        dc.set_low();
+        let _ = spi.blocking_write(&[cmd]);
+        dc.set_high();
+        let _ = spi.blocking_write(data);
+    };
+
+    let (width, height) = (SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize);
+    let end_x = (SCREEN_WIDTH - 1).to_be_bytes();
+    let end_y = (SCREEN_HEIGHT - 1).to_be_bytes();
+    //CASET、RASET、RAMWR
+    command(0x2A, &[0, 0, end_x[0], end_x[1]]);
+    command(0x2B, &[0, 0, end_y[0], end_y[1]]);
+    command(0x2C, &[]);
+
+    //逐行生成像素，每个像素大端发送
+    let lines = wrap_lines(message, width / CELL_WIDTH);
+    let mut row = [0u8; SCREEN_WIDTH as usize * 2];
+    let mut text_lines = lines.chain(core::iter::repeat(""));
+    let mut line = "";
+    for y in 0..height{
+        let gy = y % CELL_HEIGHT;
+        if gy == 0{
+            line = text_lines.next().unwrap_or("");
+        }
+        for (x, pixel) in row.chunks_exact_mut(2).enumerate(){
+            let (col, gx) = (x / CELL_WIDTH, x % CELL_WIDTH);
+            let on = gy < CELL_HEIGHT - 1 && gx < GLYPH_WIDTH
+                && line.chars().nth(col).map(|c| glyph(c)[gy] & (1 << (GLYPH_WIDTH - 1 - gx)) != 0).unwrap_or(false);
+            pixel.copy_from_slice(&if on{ FOREGROUND }else{ BACKGROUND }.to_be_bytes());
+        }
+        let _ = spi.blocking_write(&row);
+    }
+}
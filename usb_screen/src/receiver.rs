@@ -0,0 +1,160 @@
+//协议解析(usb_screen_core::receiver)，USB Raw、USB串口和HID接口各使用一个Receiver，解析后的图像和命令都发送给core1
+
+use core::cell::RefCell;
+use alloc::string::String;
+use alloc::vec::Vec;
+use embassy_rp::rom_data::reset_to_usb_boot;
+use embassy_time::Instant;
+use usb_screen_core::command::Command;
+use usb_screen_core::protocol::encode_update_result;
+use usb_screen_core::frame::Rect;
+use usb_screen_core::receiver::{Frame, Packet};
+use usb_screen_core::stats::DeviceStats;
+use usb_screen_core::update::UpdateError;
+
+use crate::storage::Storage;
+use crate::{clock, info, stats, COMMAND_CHANNEL, FRAME_BUFFER, ImageData, SCREEN_HEIGHT, SCREEN_WIDTH, SERIAL_NUMBER, USB_CHANNEL};
+
+//需要返回给主机的数据
+pub enum Response{
+    //设备信息
+    Info(String),
+    //固件升级结果，成功时发送后重启
+    Update(Result<(), UpdateError>),
+    //性能计数器
+    Stats(DeviceStats),
+}
+
+impl Response{
+    pub fn data(&self) -> Vec<u8>{
+        match self{
+            Response::Info(info) => info.as_bytes().to_vec(),
+            Response::Update(result) => encode_update_result(*result).to_vec(),
+            Response::Stats(stats) => stats.encode().to_vec(),
+        }
+    }
+
+    pub fn reboot(&self) -> bool{
+        matches!(self, Response::Update(Ok(())))
+    }
+}
+
+pub struct Receiver(usb_screen_core::receiver::Receiver);
+
+impl Receiver{
+    pub fn new() -> Self{
+        Self(usb_screen_core::receiver::Receiver::new(SCREEN_WIDTH, SCREEN_HEIGHT, FRAME_BUFFER))
+    }
+
+    //USB断开，放弃正在接收的数据
+    pub fn disconnected(&mut self, storage: &RefCell<Storage>){
+        if self.0.disconnected(){
+            storage.borrow_mut().cancel_writes();
+        }
+    }
+
+    //处理收到的一个包，需要回复主机时返回Response
+    pub async fn handle(&mut self, storage: &RefCell<Storage>, data: &[u8]) -> Option<Response>{
+        let writing = storage.borrow().writing();
+        match self.0.handle(data, writing, Instant::now().as_micros())?{
+            Packet::Frame(frame) => send_image(frame).await,
+            Packet::Rejected(err) => stats::reject(err),
+            Packet::Command(command) => COMMAND_CHANNEL.send(command).await,
+            Packet::SetConfig(config) => storage.borrow_mut().set_config(config.key, config.value),
+            //先保存再设置，预览时不写入Flash
+            Packet::SetPanel(cmd) => {
+                if cmd.save{
+                    storage.borrow_mut().set_panel(cmd.panel);
+                }
+                COMMAND_CHANNEL.send(Command::SetPanel(cmd.panel)).await;
+            }
+            Packet::SetTime(time) => clock::set_time(&time),
+            Packet::BeginSplash => storage.borrow_mut().begin_splash(),
+            Packet::BeginUpdate(update) => storage.borrow_mut().begin_update(update.len, update.crc),
+            //开机画面直接写入Flash
+            Packet::SplashData(data) => storage.borrow_mut().write_splash(data),
+            //固件直接写入暂存区
+            Packet::UpdateData(data) => storage.borrow_mut().write_update(data),
+            Packet::FinishSplash => storage.borrow_mut().finish_splash(),
+            //返回升级结果，成功时等待主机读取后重启
+            Packet::FinishUpdate => {
+                let result = storage.borrow_mut().finish_update();
+                log::info!("firmware update: {result:?}");
+                return Some(Response::Update(result));
+            }
+            Packet::CancelWrites => storage.borrow_mut().cancel_writes(),
+            Packet::BootUsb => reset_to_usb_boot(0, 0),
+            Packet::ReadInfo => {
+                let serial_number = unsafe { core::str::from_utf8_unchecked(&*core::ptr::addr_of!(SERIAL_NUMBER)) };
+                return Some(Response::Info(info::device_info(serial_number)));
+            }
+            Packet::ReadStats{ reset } => return Some(Response::Stats(stats::snapshot(reset))),
+        }
+        None
+    }
+}
+
+//图像接收完成，位置和大小已经由usb_screen_core::receiver检查过
+async fn send_image(frame: Frame){
+    stats::update(|s| s.receive.record(frame.receive_us));
+    let Frame{ rect, data, .. } = frame;
+
+    //240x320屏幕占用内存较大，绘制的同时再解压数据内存不够用（150K*2），所以仅缓存一次接收到的压缩数据
+    //等待core1解压绘制完成后，再发送新的压缩帧，达到12帧左右的速度
+    #[cfg(any(feature = "st7789-240x320", feature = "st7789-240x240"))]
+    {
+        //主机分成的小横条在core0解压，core1同时绘制上一条，core0正在解压、通道中和core1正在绘制的横条各一条，内存足够
+        //通道已满时等待core1取走，不丢弃横条
+        if usb_screen_core::frame::decompressed_len(&data).is_some_and(|len| len <= crate::DECODE_STRIPE){
+            if let Some((image, rect)) = decompress(&data, &rect){
+                USB_CHANNEL.send((ImageData::Pixels(image), rect.x, rect.y, rect.width, rect.height)).await;
+            }
+            return;
+        }
+        //如果正在绘制中，等待绘制完成
+        loop{
+            if let Ok(mut lock) = crate::DISPLAY_LOCK.try_lock(){
+                if *lock.get_mut() == false{
+                    break;
+                }
+            }
+            embassy_time::Timer::after_millis(1).await;
+        }
+        //压缩图像结束，发送数据到core1线程，接收缓冲区一起交出去，core0不再占用一帧的内存
+        if USB_CHANNEL.try_send((ImageData::Compressed(data), rect.x, rect.y, rect.width, rect.height)).is_err(){
+            stats::update(|s| s.dropped_frames += 1);
+        }
+    }
+
+    //160x128屏幕，在core0解压，core1绘制速度最快
+    #[cfg(any(feature = "st7735-128x160", feature = "st7735-128x128"))]
+    {
+        if let Some((image, rect)) = decompress(&data, &rect){
+            if USB_CHANNEL.try_send((ImageData::Pixels(image), rect.x, rect.y, rect.width, rect.height)).is_err(){
+                stats::update(|s| s.dropped_frames += 1);
+            }
+        }
+    }
+}
+
+//在core0解压并裁剪到屏幕内，core1只绘制可见部分，解压失败或长度不符时记录到性能计数器
+fn decompress(data: &[u8], rect: &Rect) -> Option<(Vec<u8>, Rect)>{
+    let start = Instant::now();
+    let mut image = match lz4_flex::decompress_size_prepended(data){
+        Ok(image) => image,
+        Err(_) => {
+            stats::update(|s| s.decode_errors += 1);
+            return None;
+        }
+    };
+    let decompress_us = stats::elapsed_us(start);
+    stats::update(|s| s.decompress.record(decompress_us));
+    stats::sample_heap();
+    match usb_screen_core::frame::crop_image(&mut image, rect, SCREEN_WIDTH, SCREEN_HEIGHT){
+        Ok(rect) => Some((image, rect)),
+        Err(err) => {
+            stats::reject(err);
+            None
+        }
+    }
+}
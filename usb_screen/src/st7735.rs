@@ -6,6 +6,11 @@ use alloc::vec::{self, Vec};
 use embassy_rp::{gpio::{Level, Output, Pin}, peripherals::{DMA_CH0, DMA_CH1, PIN_13, PIN_14, PIN_4, PIN_6, PIN_7, SPI0}, spi::{Async, Instance, Spi}};
 use embassy_time::Timer;
 use anyhow::{anyhow, Result};
+
+use crate::display::Screen;
+use usb_screen_core::detect::{Controller, Detection, READ_FREQ, RDDID, RDDST, RDID4};
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::scroll::Scroll;
 //关于 st7735s LCD 屏幕的一些问题处理
 //https://hacperme.com/posts/notes/20230525_st7735s_notes/
 
@@ -30,6 +35,8 @@ pub enum Instruction {
     RAMWR = 0x2C,
     RAMRD = 0x2E,
     PTLAR = 0x30,
+    VSCRDEF = 0x33,
+    VSCSAD = 0x37,
     COLMOD = 0x3A,
     MADCTL = 0x36,
     FRMCTR1 = 0xB1,
@@ -82,6 +89,9 @@ where
     /// Whether the colours are inverted (true) or not (false)
     inverted: bool,
 
+    /// Current orientation, resent with the BGR bit when the color order changes
+    orientation: Orientation,
+
     /// Global image offset
     dx: u16,
     dy: u16,
@@ -111,6 +121,7 @@ where
             rst,
             rgb,
             inverted,
+            orientation: Orientation::Portrait,
             dx: 0,
             dy: 0,
             width,
@@ -175,6 +186,30 @@ where
         Ok(())
     }
 
+    async fn read_command(&mut self, command: u8, buf: &mut [u8]) -> Result<(), ()> {
+        self.dc.set_low();
+        self.spi.write(&[command]).await.map_err(|_| ())?;
+        self.spi.read(buf).await.map_err(|_| ())
+    }
+
+    /// Reads the controller ID and status through MISO, must be called before `init`.
+    /// The SPI clock is lowered while reading because reads are much slower than writes.
+    pub async fn detect(&mut self) -> Detection {
+        let _ = self.hard_reset().await;
+        Timer::after_millis(120).await;
+        self.spi.set_frequency(READ_FREQ);
+        let mut rddid = [0; 4];
+        let mut rddst = [0; 5];
+        let mut rdid4 = [0; 4];
+        for (command, buf) in [(RDDID, &mut rddid[..]), (RDDST, &mut rddst[..]), (RDID4, &mut rdid4[..])] {
+            if self.read_command(command, buf).await.is_err() {
+                buf.fill(0);
+            }
+        }
+        self.spi.set_frequency(crate::DISPLAY_FREQ);
+        Detection::new(&rddid, &rddst, &rdid4)
+    }
+
     fn start_data(&mut self){
         self.dc.set_high();
     }
@@ -210,9 +245,44 @@ where
         } else {
             self.write_command(Instruction::MADCTL, &[*orientation as u8 | 0x08]).await?;
         }
+        self.orientation = *orientation;
         Ok(())
     }
 
+    /// Switches between RGB (true) and BGR (false) color order
+    pub async fn set_color_order(&mut self, rgb: bool) -> Result<(), ()> {
+        self.rgb = rgb;
+        let orientation = self.orientation;
+        self.set_orientation(&orientation).await
+    }
+
+    /// Defines the vertical scroll area (top fixed, scroll, bottom fixed lines) and the first line shown in it
+    pub async fn set_scroll(&mut self, definition: [u16; 3], start: u16) -> Result<(), ()> {
+        self.write_command(Instruction::VSCRDEF, &[]).await?;
+        self.start_data();
+        for lines in definition {
+            self.write_word(lines).await?;
+        }
+        self.write_command(Instruction::VSCSAD, &[]).await?;
+        self.start_data();
+        self.write_word(start).await
+    }
+
+    /// Turns display inversion on (INVON) or off (INVOFF)
+    pub async fn set_inverted(&mut self, inverted: bool) -> Result<(), ()> {
+        if inverted {
+            self.write_command(Instruction::INVON, &[]).await
+        } else {
+            self.write_command(Instruction::INVOFF, &[]).await
+        }
+    }
+
+    /// Sets the positive and negative gamma curves (GMCTRP1 / GMCTRN1, 16 bytes each)
+    pub async fn set_gamma(&mut self, positive: &[u8], negative: &[u8]) -> Result<(), ()> {
+        self.write_command(Instruction::GMCTRP1, positive).await?;
+        self.write_command(Instruction::GMCTRN1, negative).await
+    }
+
     /// Sets the global offset of the displayed image
     pub fn set_offset(&mut self, dx: u16, dy: u16) {
         self.dx = dx;
@@ -415,6 +485,10 @@ pub struct ST7735DisplayManager<'a>{
     pub display: ST7735<'a, SPI0, PIN_13, PIN_14>,
 }
 
+//构造时的颜色顺序和反色，SetPanel选择固件默认时使用
+const RGB: bool = true;
+const INVERTED: bool = false;
+
 impl <'a> ST7735DisplayManager<'a>{
     pub async fn new(spi: SPI0, p6: PIN_6, p7: PIN_7, p4: PIN_4, p13: PIN_13, p14: PIN_14, dma_ch0: DMA_CH0, dma_ch1: DMA_CH1) -> Result<Self>{            
         let spi_sclk = p6;
@@ -426,9 +500,16 @@ impl <'a> ST7735DisplayManager<'a>{
 
         let dc = Output::new(p13, Level::Low);
         let rst: Output<PIN_14> = Output::new(p14, Level::Low);
-        let screen_width = 128;
-        let screen_height = 160;
-        let mut disp = ST7735::new(spi, dc, Some(rst), true, false, screen_width, screen_height);
+        //驱动中的宽高是竖屏的，128x128的模块显存偏移不同，通过SetPanel设置
+        let screen_width = crate::SCREEN_HEIGHT as u32;
+        let screen_height = crate::SCREEN_WIDTH as u32;
+        let mut disp = ST7735::new(spi, dc, Some(rst), RGB, INVERTED, screen_width, screen_height);
+        //模块有SDO引脚并接到GPIO4时可以读取控制器ID，没有回复时按ST7735初始化
+        let detection = disp.detect().await;
+        crate::info::set_panel_detection(detection);
+        if let Some(controller) = detection.controller.filter(|c| *c != Controller::St7735){
+            log::warn!("detected {} panel, the firmware is built for ST7735, flash the firmware for this panel", controller.name());
+        }
         disp.init().await.map_err(|_| anyhow!("init error") )?;
         disp.set_orientation(&Orientation::Landscape).await.map_err(|_| anyhow!("init error") )?;
 
@@ -452,4 +533,27 @@ impl <'a> ST7735DisplayManager<'a>{
     pub async fn clear_rect(&mut self, color: u16, x: u16, y: u16, width: u16, height:u16){
         self.display.clear_rect(color, x, y, width, height).await;       
     }
-}
\ No newline at end of file
+}
+
+impl <'a> Screen for ST7735DisplayManager<'a>{
+    async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+        let _ = self.display.set_pixels_buffered(x, y, x+width-1, y+height-1, pixels.iter().cloned()).await;
+    }
+
+    //ST7735S显存162行，横屏时屏幕的x加上了dx
+    async fn set_scroll(&mut self, scroll: &Scroll){
+        let dx = self.display.dx;
+        let mut definition = scroll.definition(162 - dx);
+        definition[0] += dx;
+        let _ = self.display.set_scroll(definition, scroll.start_line() + dx).await;
+    }
+
+    async fn set_panel(&mut self, panel: &PanelConfig){
+        let _ = self.display.set_inverted(panel.inversion.inverted(INVERTED)).await;
+        let _ = self.display.set_color_order(!panel.color_order.bgr(!RGB)).await;
+        self.display.set_offset(panel.offset_x, panel.offset_y);
+        if let Some((positive, negative)) = panel.gamma(16){
+            let _ = self.display.set_gamma(positive, negative).await;
+        }
+    }
+}
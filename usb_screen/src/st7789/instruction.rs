@@ -24,5 +24,13 @@ pub enum Instruction {
     MADCTL = 0x36,
     VSCAD = 0x37,
     COLMOD = 0x3A,
+    // ILI9341 frame rate, display function and power control
+    FRMCTR1 = 0xB1,
+    DFUNCTR = 0xB6,
+    PWCTR1 = 0xC0,
+    PWCTR2 = 0xC1,
     VCMOFSET = 0xC5,
+    VMCTR2 = 0xC7,
+    PVGAMCTRL = 0xE0,
+    NVGAMCTRL = 0xE1,
 }
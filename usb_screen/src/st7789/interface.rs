@@ -10,8 +10,12 @@ use embassy_rp::spi::{Blocking, Spi};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::{Duration, Timer};
 use embedded_hal_1::digital::OutputPin;
-use embedded_hal_1::spi::SpiDevice;
+use embedded_hal_1::spi::{Operation, SpiDevice};
 use super::ST7789;
+use crate::display::Screen;
+use usb_screen_core::detect::{Controller, Detection, RDDID, RDDST, RDID4};
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::scroll::Scroll;
 
 /// SPI display interface.
 ///
@@ -54,6 +58,23 @@ where
     }
 }
 
+/// Reads the controller ID and status through MISO before the display is initialized.
+///
+/// `spi` must be configured for at most `READ_FREQ`, D/C stays low because the
+/// controller ignores it while shifting out the parameters.
+pub fn detect<SPI: SpiDevice, DC: OutputPin>(spi: &mut SPI, dc: &mut DC) -> Detection {
+    let mut rddid = [0; 4];
+    let mut rddst = [0; 5];
+    let mut rdid4 = [0; 4];
+    let _ = dc.set_low();
+    for (command, buf) in [(RDDID, &mut rddid[..]), (RDDST, &mut rddst[..]), (RDID4, &mut rdid4[..])] {
+        if spi.transaction(&mut [Operation::Write(&[command]), Operation::Read(buf)]).is_err() {
+            buf.fill(0);
+        }
+    }
+    Detection::new(&rddid, &rddst, &rdid4)
+}
+
 fn send_u8<T: SpiDevice>(spi: &mut T, words: DataFormat<'_>) -> Result<(), T::Error> {
     match words {
         DataFormat::U8(slice) => spi.write(slice),
@@ -154,4 +175,28 @@ pub fn draw_rgb565_u8(display: &mut ST7789<SPIDeviceInterface<SpiDeviceWithConfi
 pub fn clear_rect(display: &mut ST7789<SPIDeviceInterface<SpiDeviceWithConfig<NoopRawMutex, Spi<SPI0, Blocking>, Output<PIN_9>>, Output<PIN_13>>, Output<PIN_14>>, color: u16, x:u16, y:u16, width: u16, height: u16){
     let colors = iter::repeat(color).take(width as usize *height as usize);
     let _ = display.set_pixels(x, y, width, height, colors);
-}
\ No newline at end of file
+}
+
+impl Screen for ST7789<SPIDeviceInterface<SpiDeviceWithConfig<'_, NoopRawMutex, Spi<'_, SPI0, Blocking>, Output<'_, PIN_9>>, Output<'_, PIN_13>>, Output<'_, PIN_14>>{
+    async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+        let _ = self.set_pixels(x, y, width, height, pixels.iter().cloned());
+    }
+
+    async fn set_scroll(&mut self, scroll: &Scroll){
+        let [top, len, bottom] = scroll.definition(super::SCROLL_LINES);
+        let _ = self.set_scroll_region(top, len, bottom);
+        let _ = self.set_scroll_offset(scroll.start_line());
+    }
+
+    //初始化时为INVOFF，ST7789默认RGB，ILI9341的模块一般是BGR
+    async fn set_panel(&mut self, panel: &PanelConfig){
+        let ili9341 = self.controller() == Controller::Ili9341;
+        let _ = self.set_inverted(panel.inversion.inverted(false));
+        let _ = self.set_color_order(panel.color_order.bgr(ili9341));
+        self.set_offset(panel.offset_x, panel.offset_y);
+        let gamma_len = if ili9341{ super::ILI9341_GAMMA_LEN }else{ super::GAMMA_LEN };
+        if let Some((positive, negative)) = panel.gamma(gamma_len){
+            let _ = self.set_gamma(positive, negative);
+        }
+    }
+}
@@ -3,6 +3,7 @@
 // #![allow(clippy::type_complexity)]
 
 //! This crate provides a ST7789 driver to connect to TFT displays.
+//! ILI9341 shares the command set and is driven by the same code with its own power settings.
 pub mod interface;
 mod instruction;
 use embassy_time::Timer;
@@ -13,6 +14,22 @@ use core::iter::once;
 
 use display_interface::DataFormat::{U16, U8, U16LEIter, U16BEIter, U8Iter};
 use display_interface::WriteOnlyDataCommand;
+use usb_screen_core::detect::Controller;
+
+///
+/// Number of frame memory lines the vertical scroll area is defined over.
+///
+pub const SCROLL_LINES: u16 = 320;
+
+///
+/// Length of the positive and negative gamma curves (PVGAMCTRL / NVGAMCTRL).
+///
+pub const GAMMA_LEN: usize = 14;
+
+///
+/// Length of the ILI9341 gamma curves (GMCTRP1 / GMCTRN1, same opcodes).
+///
+pub const ILI9341_GAMMA_LEN: usize = 15;
 
 ///
 /// ST7789 driver to connect to TFT displays.
@@ -31,6 +48,13 @@ where
     size_y: u16,
     // Current orientation
     orientation: Orientation,
+    // Controller selecting the init sequence
+    controller: Controller,
+    // BGR color order (MADCTL bit 3)
+    bgr: bool,
+    // Offset of the visible area in the frame memory
+    dx: u16,
+    dy: u16,
 }
 
 ///
@@ -95,9 +119,27 @@ where
             size_x,
             size_y,
             orientation: Orientation::default(),
+            controller: Controller::St7789,
+            bgr: false,
+            dx: 0,
+            dy: 0,
         }
     }
 
+    ///
+    /// Selects the controller before `init`, ST7789 and ILI9341 are supported
+    ///
+    pub fn set_controller(&mut self, controller: Controller) {
+        self.controller = controller;
+    }
+
+    ///
+    /// Returns the controller selected with `set_controller`
+    ///
+    pub fn controller(&self) -> Controller {
+        self.controller
+    }
+
     ///
     /// Runs commands to initialize the display
     ///
@@ -109,11 +151,13 @@ where
         self.hard_reset().await?;
         self.write_command(Instruction::SWRESET)?; // reset display
         Timer::after_micros(150_000).await;
+        if self.controller == Controller::Ili9341 {
+            self.init_ili9341_power()?;
+        }
         self.write_command(Instruction::SLPOUT)?; // turn off sleep
         Timer::after_micros(10_000).await;
         self.write_command(Instruction::INVOFF)?; // turn off invert
-        self.write_command(Instruction::VSCRDER)?; // vertical scroll definition
-        self.write_data(&[0u8, 0u8, 0x14u8, 0u8, 0u8, 0u8])?; // 0 TSA, 320 VSA, 0 BSA
+        self.set_scroll_region(0, SCROLL_LINES, 0)?; // 0 TSA, 320 VSA, 0 BSA
         self.write_command(Instruction::MADCTL)?; // left -> right, bottom -> top RGB
         self.write_data(&[0b0000_0000])?;
         self.write_command(Instruction::COLMOD)?; // 16bit 65k colors
@@ -127,6 +171,22 @@ where
         Ok(())
     }
 
+    // ILI9341 power and timing settings, the reset defaults give a dim picture
+    fn init_ili9341_power(&mut self) -> Result<(), Error<PinE>> {
+        self.write_command(Instruction::PWCTR1)?; // GVDD 4.60V
+        self.write_data(&[0x23])?;
+        self.write_command(Instruction::PWCTR2)?; // step-up factor
+        self.write_data(&[0x10])?;
+        self.write_command(Instruction::VCMOFSET)?; // VCOMH 4.25V, VCOML -1.5V
+        self.write_data(&[0x3E, 0x28])?;
+        self.write_command(Instruction::VMCTR2)?; // VCOM offset
+        self.write_data(&[0x86])?;
+        self.write_command(Instruction::FRMCTR1)?; // 79Hz
+        self.write_data(&[0x00, 0x18])?;
+        self.write_command(Instruction::DFUNCTR)?; // 320 lines
+        self.write_data(&[0x08, 0x82, 0x27])
+    }
+
     ///
     /// Performs a hard reset using the RST pin sequence
     ///
@@ -157,11 +217,27 @@ where
     ///
     pub fn set_orientation(&mut self, orientation: Orientation) -> Result<(), Error<PinE>> {
         self.write_command(Instruction::MADCTL)?;
-        self.write_data(&[orientation as u8])?;
+        self.write_data(&[orientation as u8 | if self.bgr { 0b0000_1000 } else { 0 }])?;
         self.orientation = orientation;
         Ok(())
     }
 
+    ///
+    /// Switches between RGB (false) and BGR (true) color order
+    ///
+    pub fn set_color_order(&mut self, bgr: bool) -> Result<(), Error<PinE>> {
+        self.bgr = bgr;
+        self.set_orientation(self.orientation)
+    }
+
+    ///
+    /// Sets the offset of the visible area in the frame memory, added to every address window
+    ///
+    pub fn set_offset(&mut self, dx: u16, dy: u16) {
+        self.dx = dx;
+        self.dy = dy;
+    }
+
     ///
     /// Sets a pixel color at the given coords.
     ///
@@ -266,6 +342,46 @@ where
         self.write_data(&offset.to_be_bytes())
     }
 
+    ///
+    /// Defines the vertical scroll area, the three values must add up to `SCROLL_LINES`
+    /// # Arguments
+    ///
+    /// * `top_fixed` - lines at the top that don't scroll
+    /// * `scroll` - lines in the scroll area
+    /// * `bottom_fixed` - lines at the bottom that don't scroll
+    ///
+    pub fn set_scroll_region(&mut self, top_fixed: u16, scroll: u16, bottom_fixed: u16) -> Result<(), Error<PinE>> {
+        self.write_command(Instruction::VSCRDER)?;
+        self.write_data(&top_fixed.to_be_bytes())?;
+        self.write_data(&scroll.to_be_bytes())?;
+        self.write_data(&bottom_fixed.to_be_bytes())
+    }
+
+    ///
+    /// Turns display inversion on (INVON) or off (INVOFF)
+    ///
+    pub fn set_inverted(&mut self, inverted: bool) -> Result<(), Error<PinE>> {
+        if inverted {
+            self.write_command(Instruction::INVON)
+        } else {
+            self.write_command(Instruction::INVOFF)
+        }
+    }
+
+    ///
+    /// Sets the positive and negative voltage gamma curves
+    /// # Arguments
+    ///
+    /// * `positive` - PVGAMCTRL parameters, `GAMMA_LEN` bytes
+    /// * `negative` - NVGAMCTRL parameters, `GAMMA_LEN` bytes
+    ///
+    pub fn set_gamma(&mut self, positive: &[u8], negative: &[u8]) -> Result<(), Error<PinE>> {
+        self.write_command(Instruction::PVGAMCTRL)?;
+        self.write_data(positive)?;
+        self.write_command(Instruction::NVGAMCTRL)?;
+        self.write_data(negative)
+    }
+
     ///
     /// Release resources allocated to this driver back.
     /// This returns the display interface and the RST pin deconstructing the driver.
@@ -296,11 +412,11 @@ where
         ey: u16,
     ) -> Result<(), Error<PinE>> {
         self.write_command(Instruction::CASET)?;
-        self.write_data(&sx.to_be_bytes())?;
-        self.write_data(&ex.to_be_bytes())?;
+        self.write_data(&(sx + self.dx).to_be_bytes())?;
+        self.write_data(&(ex + self.dx).to_be_bytes())?;
         self.write_command(Instruction::RASET)?;
-        self.write_data(&sy.to_be_bytes())?;
-        self.write_data(&ey.to_be_bytes())
+        self.write_data(&(sy + self.dy).to_be_bytes())?;
+        self.write_data(&(ey + self.dy).to_be_bytes())
     }
 
     ///
@@ -19,6 +19,9 @@ use embedded_graphics::pixelcolor::Rgb565;
 use embedded_graphics::prelude::*;
 use fixed::traits::FixedOptionalFeatures;
 use st7789::{Orientation, ST7789};
+use crate::display::Screen;
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::scroll::Scroll;
 // use crate::usb_serial;
 
 const DISPLAY_FREQ: u32 = 64_000_000;
@@ -233,4 +236,24 @@ pub fn draw_rgb565_u8(display: &mut ST7789<SPIDeviceInterface<SpiDeviceWithConfi
 pub fn clear_rect(display: &mut ST7789<SPIDeviceInterface<SpiDeviceWithConfig<'_, NoopRawMutex, Spi<'_, SPI0, Blocking>, Output<'_, PIN_9>>, Output<'_, PIN_13>>, Output<'_, PIN_14>, Output<'_, PIN_15>>, color: u16, x:u16, y:u16, width: u16, height: u16){
     let colors = iter::repeat(color).take(width as usize *height as usize);
     let _ = display.set_pixels(x, y, x+width-1, y+height-1, colors);
-}
\ No newline at end of file
+}
+
+impl Screen for ST7789<SPIDeviceInterface<SpiDeviceWithConfig<'_, NoopRawMutex, Spi<'_, SPI0, Blocking>, Output<'_, PIN_9>>, Output<'_, PIN_13>>, Output<'_, PIN_14>, Output<'_, PIN_15>>{
+    async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+        let _ = self.set_pixels(x, y, x+width-1, y+height-1, pixels.iter().cloned());
+    }
+
+    //st7789库的滚动区域以起止行表示，显存共320行
+    async fn set_scroll(&mut self, scroll: &Scroll){
+        let [top, len, _] = scroll.definition(320);
+        let _ = self.set_scroll_region(top, top + len);
+        let _ = self.set_scroll_offset(scroll.start_line());
+    }
+
+    //st7789库没有提供发送INVON/INVOFF、Gamma指令和设置偏移、颜色顺序的接口
+    async fn set_panel(&mut self, panel: &PanelConfig){
+        if *panel != PanelConfig::default(){
+            log::warn!("panel settings are not supported on st7789-240x240");
+        }
+    }
+}
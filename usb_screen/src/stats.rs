@@ -0,0 +1,57 @@
+//性能计数器：core0记录接收耗时和丢帧，core1记录解压和绘制耗时(160x128在core0解压)，主机通过ReadStat读取
+
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Instant;
+use usb_screen_core::frame::FrameError;
+use usb_screen_core::stats::DeviceStats;
+
+static STATS: Mutex<CriticalSectionRawMutex, Cell<DeviceStats>> = Mutex::new(Cell::new(DeviceStats::new()));
+
+pub fn update(f: impl FnOnce(&mut DeviceStats)){
+    STATS.lock(|s| {
+        let mut stats = s.get();
+        f(&mut stats);
+        s.set(stats);
+    });
+}
+
+//从start到现在的微秒数
+pub fn elapsed_us(start: Instant) -> u32{
+    start.elapsed().as_micros() as u32
+}
+
+//记录被拒绝的帧和原因
+pub fn reject(error: FrameError){
+    log::warn!("frame rejected: {error:?}");
+    update(|s| {
+        s.rejected_frames += 1;
+        s.last_error = error as u32;
+    });
+}
+
+//记录堆内存使用量的最大值，在分配大块内存(解压图像)之后调用
+pub fn sample_heap(){
+    let used = crate::HEAP.used() as u32;
+    update(|s| {
+        s.heap_used = used;
+        s.heap_peak = s.heap_peak.max(used);
+    });
+}
+
+//读取计数器，reset为true时读取后清零
+pub fn snapshot(reset: bool) -> DeviceStats{
+    let used = crate::HEAP.used() as u32;
+    STATS.lock(|s| {
+        let mut stats = s.get();
+        stats.uptime_ms = Instant::now().as_millis() as u32;
+        stats.heap_used = used;
+        stats.heap_peak = stats.heap_peak.max(used);
+        stats.heap_size = crate::HEAP_SIZE as u32;
+        if reset{
+            s.set(DeviceStats { heap_peak: used, ..DeviceStats::new() });
+        }
+        stats
+    })
+}
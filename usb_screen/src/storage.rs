@@ -0,0 +1,205 @@
+//Flash中保存的设置、自定义开机画面和固件升级暂存区，只在core0中写入，写入时embassy会暂停core1
+
+use core::cell::Cell;
+use alloc::vec::Vec;
+use embassy_rp::flash::{Blocking, Flash};
+use embassy_rp::peripherals::FLASH;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use usb_screen_core::panel::PanelConfig;
+use usb_screen_core::receiver::FlashState;
+use usb_screen_core::settings::Settings;
+use usb_screen_core::storage::{FLASH_SIZE, SECTOR_SIZE, SETTINGS_OFFSET, SPLASH_CAPACITY, SPLASH_OFFSET};
+use usb_screen_core::update::{clear_pending, pending_update, staging_crc, FirmwareUpdater, UpdateError};
+
+//Flash映射到内存的起始地址(XIP)
+const XIP_BASE: usize = 0x1000_0000;
+
+//当前设置，core0修改，core1读取
+pub static SETTINGS: Mutex<CriticalSectionRawMutex, Cell<Settings>> = Mutex::new(Cell::new(Settings::new()));
+
+pub fn settings() -> Settings{
+    SETTINGS.lock(|s| s.get())
+}
+
+//直接读取Flash中的开机画面数据
+pub fn splash_data() -> &'static [u8]{
+    unsafe { core::slice::from_raw_parts((XIP_BASE + SPLASH_OFFSET) as *const u8, SPLASH_CAPACITY) }
+}
+
+pub struct Storage{
+    flash: Flash<'static, FLASH, Blocking, FLASH_SIZE>,
+    //正在写入的开机画面
+    splash: Option<SplashWriter>,
+    //正在进行的固件升级，出错后保存错误，收到IMAGE_BB时返回给主机
+    update: Option<Result<FirmwareUpdater, UpdateError>>,
+}
+
+struct SplashWriter{
+    //第一个扇区(包含头部)最后写入，写入中断时Flash中不会留下有效的头部
+    first_sector: Vec<u8>,
+    sector: Vec<u8>,
+    //sector对应的Flash偏移
+    offset: usize,
+}
+
+impl Storage{
+    pub fn new(flash: FLASH) -> Self{
+        Self { flash: Flash::new_blocking(flash), splash: None, update: None }
+    }
+
+    //读取Flash中的设置
+    pub fn load_settings(&mut self){
+        let mut buf = [0u8; 256];
+        let settings = match self.flash.blocking_read(SETTINGS_OFFSET as u32, &mut buf){
+            Ok(()) => Settings::decode(&buf),
+            Err(_) => Settings::default(),
+        };
+        SETTINGS.lock(|s| s.set(settings));
+    }
+
+    //修改设置并保存
+    pub fn set_config(&mut self, key: u16, value: u32){
+        let mut settings = settings();
+        if !settings.set(key, value){
+            return;
+        }
+        log::info!("config {key}={value}");
+        self.save_settings(settings);
+    }
+
+    //保存反色和Gamma曲线，一次写入Flash
+    pub fn set_panel(&mut self, panel: PanelConfig){
+        let mut settings = settings();
+        settings.panel = panel;
+        log::info!("panel {panel:?}");
+        self.save_settings(settings);
+    }
+
+    fn save_settings(&mut self, settings: Settings){
+        SETTINGS.lock(|s| s.set(settings));
+        let data = settings.encode();
+        let offset = SETTINGS_OFFSET as u32;
+        if self.flash.blocking_erase(offset, offset + SECTOR_SIZE as u32).is_ok(){
+            let _ = self.flash.blocking_write(offset, &data);
+        }
+    }
+
+    pub fn is_writing_splash(&self) -> bool{
+        self.splash.is_some()
+    }
+
+    //正在写入的内容，Receiver据此判断收到的数据是否写入Flash
+    pub fn writing(&self) -> FlashState{
+        if self.is_updating(){
+            FlashState::Update
+        }else if self.is_writing_splash(){
+            FlashState::Splash
+        }else{
+            FlashState::Idle
+        }
+    }
+
+    //开始写入开机画面，先擦除头部所在的扇区，旧的开机画面立即失效
+    pub fn begin_splash(&mut self){
+        let offset = SPLASH_OFFSET as u32;
+        let _ = self.flash.blocking_erase(offset, offset + SECTOR_SIZE as u32);
+        self.update = None;
+        self.splash = Some(SplashWriter{
+            first_sector: Vec::with_capacity(SECTOR_SIZE),
+            sector: Vec::with_capacity(SECTOR_SIZE),
+            offset: SPLASH_OFFSET,
+        });
+    }
+
+    //写入开机画面数据，每满一个扇区写入一次Flash，超出容量的数据丢弃
+    pub fn write_splash(&mut self, mut data: &[u8]){
+        let writer = match self.splash.as_mut(){
+            Some(writer) => writer,
+            None => return,
+        };
+        while !data.is_empty() && writer.offset < SPLASH_OFFSET + SPLASH_CAPACITY{
+            let buf = if writer.offset == SPLASH_OFFSET{ &mut writer.first_sector }else{ &mut writer.sector };
+            let len = data.len().min(SECTOR_SIZE - buf.len());
+            buf.extend_from_slice(&data[..len]);
+            data = &data[len..];
+            if buf.len() == SECTOR_SIZE{
+                if writer.offset != SPLASH_OFFSET{
+                    write_sector(&mut self.flash, writer.offset, &writer.sector);
+                    writer.sector.clear();
+                }
+                writer.offset += SECTOR_SIZE;
+            }
+        }
+    }
+
+    //写入剩余数据和第一个扇区
+    pub fn finish_splash(&mut self){
+        let writer = match self.splash.take(){
+            Some(writer) => writer,
+            None => return,
+        };
+        if !writer.sector.is_empty(){
+            write_sector(&mut self.flash, writer.offset, &writer.sector);
+        }
+        if !writer.first_sector.is_empty(){
+            let _ = self.flash.blocking_write(SPLASH_OFFSET as u32, &writer.first_sector);
+        }
+        log::info!("splash saved");
+    }
+
+    //放弃正在写入的开机画面和固件升级，Flash中的开机画面保持无效
+    pub fn cancel_writes(&mut self){
+        self.splash = None;
+        self.update = None;
+    }
+
+    //如果暂存区中有校验通过的新固件，复制到固件区并重启，必须在core1启动之前调用
+    pub fn apply_pending_update(&mut self){
+        let state = match pending_update(&mut self.flash){
+            Some(state) => state,
+            None => return,
+        };
+        //暂存区损坏时放弃升级
+        if staging_crc(&mut self.flash, state.len) != Ok(state.crc){
+            let _ = clear_pending(&mut self.flash);
+            return;
+        }
+        crate::update::copy_staging_and_reboot(state.len as usize);
+    }
+
+    pub fn is_updating(&self) -> bool{
+        self.update.is_some()
+    }
+
+    //开始固件升级
+    pub fn begin_update(&mut self, len: u32, crc: u32){
+        self.splash = None;
+        self.update = Some(FirmwareUpdater::begin(&mut self.flash, len, crc));
+    }
+
+    //写入固件数据到暂存区
+    pub fn write_update(&mut self, data: &[u8]){
+        if let Some(Ok(updater)) = self.update.as_mut(){
+            if let Err(err) = updater.write(&mut self.flash, data){
+                self.update = Some(Err(err));
+            }
+        }
+    }
+
+    //校验暂存区，成功后重启即可应用升级
+    pub fn finish_update(&mut self) -> Result<(), UpdateError>{
+        match self.update.take(){
+            Some(Ok(updater)) => updater.finish(&mut self.flash).map(|_| ()),
+            Some(Err(err)) => Err(err),
+            None => Err(UpdateError::NotStarted),
+        }
+    }
+}
+
+fn write_sector(flash: &mut Flash<'static, FLASH, Blocking, FLASH_SIZE>, offset: usize, data: &[u8]){
+    let offset = offset as u32;
+    if flash.blocking_erase(offset, offset + SECTOR_SIZE as u32).is_ok(){
+        let _ = flash.blocking_write(offset, data);
+    }
+}
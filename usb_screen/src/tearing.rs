@@ -0,0 +1,41 @@
+//TE(撕裂效应)信号：屏幕在垂直消隐期间把TE引脚拉高。引脚在设置中配置(重启后生效)，
+//开启同步(设置立即生效)后，core1在写入每帧图像之前等待TE上升沿，让写入从屏幕刷新的开头开始。
+
+use embassy_rp::gpio::{AnyPin, Input, Pull};
+use embassy_time::{with_timeout, Duration};
+
+use crate::{input, storage};
+
+//60Hz刷新时两次TE间隔约17ms，超过这个时间没有信号说明TE没有连接或没有开启
+const TE_TIMEOUT: Duration = Duration::from_millis(40);
+
+pub struct TearingSync{
+    pin: Option<Input<'static, AnyPin>>,
+    //没有信号时只警告一次
+    warned: bool,
+}
+
+impl TearingSync{
+    pub fn new(pin: Option<u8>) -> Self{
+        Self { pin: pin.and_then(input::configured_pin).map(|pin| Input::new(pin, Pull::Down)), warned: false }
+    }
+
+    /// 是否配置了TE引脚，配置了才需要让屏幕输出TE信号
+    pub fn connected(&self) -> bool{
+        self.pin.is_some()
+    }
+
+    /// 开启同步时等待下一次垂直消隐开始
+    pub async fn wait(&mut self){
+        let Some(pin) = self.pin.as_mut() else{
+            return;
+        };
+        if !storage::settings().te_sync{
+            return;
+        }
+        if with_timeout(TE_TIMEOUT, pin.wait_for_rising_edge()).await.is_err() && !self.warned{
+            log::warn!("no TE signal");
+            self.warned = true;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//XPT2046电阻触摸屏，和ST7789共用SPI总线，在core1中读取，触摸事件发送给core0的USB任务
+
+use embassy_rp::gpio::Input;
+use embassy_rp::peripherals::PIN_10;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Timer};
+use embedded_hal_1::spi::{Operation, SpiDevice};
+use usb_screen_core::touch::{Calibration, TouchEvent};
+
+use crate::storage::settings;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//XPT2046的SPI频率不能超过2M
+pub const TOUCH_FREQ: u32 = 2_000_000;
+
+//按下时的采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+//每次采样读取的次数，去掉最大最小值后取平均
+const SAMPLES: usize = 5;
+//压力小于这个值认为没有按下
+const MIN_PRESSURE: u16 = 100;
+
+//XPT2046命令: 12位差分模式
+const CMD_X: u8 = 0xD0;
+const CMD_Y: u8 = 0x90;
+const CMD_Z1: u8 = 0xB0;
+const CMD_Z2: u8 = 0xC0;
+
+//core1发送，core0通过HID和USB Raw发送给主机，满了以后丢弃
+pub static TOUCH_CHANNEL: Channel<CriticalSectionRawMutex, TouchEvent, 8> = Channel::new();
+
+pub struct TouchPanel<SPI>{
+    spi: SPI,
+    //触摸时被XPT2046拉低
+    irq: Input<'static, PIN_10>,
+    pressed: bool,
+}
+
+impl<SPI: SpiDevice> TouchPanel<SPI>{
+    pub fn new(spi: SPI, irq: Input<'static, PIN_10>) -> Self{
+        Self { spi, irq, pressed: false }
+    }
+
+    //等待需要采样：没有按下时等待IRQ拉低，按下时每10ms采样一次
+    pub async fn wait(&mut self){
+        if self.pressed{
+            Timer::after(SAMPLE_INTERVAL).await;
+        }else{
+            self.irq.wait_for_low().await;
+        }
+    }
+
+    //不等待，需要采样时直接采样(空闲画面的循环中调用)
+    pub fn poll(&mut self){
+        if self.pressed || self.irq.is_low(){
+            self.sample();
+        }
+    }
+
+    //读取触摸状态，按下、移动和抬起时发送事件
+    pub fn sample(&mut self){
+        let (raw_x, raw_y, pressure) = match self.read(){
+            Some(value) => value,
+            None => return,
+        };
+        let pressed = pressure >= MIN_PRESSURE;
+        if !pressed && !self.pressed{
+            return;
+        }
+        let calibration = Calibration::from_bits(settings().touch_calibration)
+            .unwrap_or_else(|| Calibration::default_for(SCREEN_WIDTH, SCREEN_HEIGHT));
+        let (x, y) = calibration.apply(raw_x, raw_y, SCREEN_WIDTH, SCREEN_HEIGHT);
+        self.pressed = pressed;
+        let _ = TOUCH_CHANNEL.try_send(TouchEvent { pressed, x, y, raw_x, raw_y, pressure });
+    }
+
+    //返回(x, y, 压力)的原始值
+    fn read(&mut self) -> Option<(u16, u16, u16)>{
+        let x = self.read_filtered(CMD_X)?;
+        let y = self.read_filtered(CMD_Y)?;
+        let z1 = self.read_channel(CMD_Z1)?;
+        let z2 = self.read_channel(CMD_Z2)?;
+        //按得越重z1越大、z2越小
+        let pressure = (z1 + 4095).saturating_sub(z2) / 8;
+        Some((x, y, pressure))
+    }
+
+    fn read_filtered(&mut self, cmd: u8) -> Option<u16>{
+        let mut samples = [0u16; SAMPLES];
+        for s in samples.iter_mut(){
+            *s = self.read_channel(cmd)?;
+        }
+        samples.sort_unstable();
+        let sum: u32 = samples[1..SAMPLES-1].iter().map(|&s| s as u32).sum();
+        Some((sum / (SAMPLES as u32 - 2)) as u16)
+    }
+
+    fn read_channel(&mut self, cmd: u8) -> Option<u16>{
+        let mut buf = [0u8; 2];
+        self.spi.transaction(&mut [Operation::Write(&[cmd]), Operation::Read(&mut buf)]).ok()?;
+        Some(u16::from_be_bytes(buf) >> 3)
+    }
+}
@@ -0,0 +1,73 @@
+//固件升级的最后一步：把暂存区复制到固件区。
+//复制过程中固件区的代码会被覆盖，所以复制代码必须在RAM中运行，并且只能调用ROM中的Flash函数。
+//复制过程中断电会导致固件损坏，此时按住BOOTSEL上电重新刷入uf2即可。
+
+use core::mem::MaybeUninit;
+use embassy_rp::rom_data;
+use usb_screen_core::storage::{SECTOR_SIZE, STAGING_OFFSET, UPDATE_STATE_OFFSET};
+
+//Flash映射到内存的起始地址(XIP)
+const XIP_BASE: usize = 0x1000_0000;
+//4K扇区擦除命令
+const SECTOR_ERASE_CMD: u8 = 0x20;
+
+//进入RAM代码之前查好ROM函数地址，RAM代码中不能调用Flash中的查表函数
+struct RomFunctions{
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    flash_enter_cmd_xip: unsafe extern "C" fn(),
+}
+
+//复制暂存区的前len字节到固件区，清除升级状态后重启，必须在core1启动之前调用
+pub fn copy_staging_and_reboot(len: usize) -> !{
+    let rom = RomFunctions{
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        flash_enter_cmd_xip: rom_data::flash_enter_cmd_xip::ptr(),
+    };
+    cortex_m::interrupt::disable();
+    unsafe { copy_staging_in_ram(&rom, len) }
+}
+
+//这个函数在RAM中运行：不能有边界检查、memcpy/memset等会调用Flash中代码的操作
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn copy_staging_in_ram(rom: &RomFunctions, len: usize) -> !{
+    let mut buf = MaybeUninit::<[u8; SECTOR_SIZE]>::uninit();
+    let buf = buf.as_mut_ptr() as *mut u8;
+    let sectors = (len + SECTOR_SIZE - 1) / SECTOR_SIZE;
+    let mut sector = 0;
+    while sector < sectors{
+        let offset = sector * SECTOR_SIZE;
+        //XIP模式下读取暂存区
+        let src = (XIP_BASE + STAGING_OFFSET + offset) as *const u8;
+        let mut i = 0;
+        while i < SECTOR_SIZE{
+            core::ptr::write_volatile(buf.add(i), core::ptr::read_volatile(src.add(i)));
+            i += 1;
+        }
+        //退出XIP后擦除并写入固件区
+        (rom.connect_internal_flash)();
+        (rom.flash_exit_xip)();
+        (rom.flash_range_erase)(offset as u32, SECTOR_SIZE, SECTOR_SIZE as u32, SECTOR_ERASE_CMD);
+        (rom.flash_range_program)(offset as u32, buf, SECTOR_SIZE);
+        (rom.flash_flush_cache)();
+        (rom.flash_enter_cmd_xip)();
+        sector += 1;
+    }
+    //清除升级状态，防止重启后再次复制
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    (rom.flash_range_erase)(UPDATE_STATE_OFFSET as u32, SECTOR_SIZE, SECTOR_SIZE as u32, SECTOR_ERASE_CMD);
+    (rom.flash_flush_cache)();
+    (rom.flash_enter_cmd_xip)();
+    //写入SCB.AIRCR的SYSRESETREQ重启
+    core::ptr::write_volatile(0xE000_ED0C as *mut u32, 0x05FA_0004);
+    loop{}
+}
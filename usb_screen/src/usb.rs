@@ -0,0 +1,483 @@
+//USB复合设备：接口0为WinUSB的Raw Bulk接口，接口1、2为CDC串口，接口3为HID，三个接口使用同样的协议，
+//主机可以任选一种方式传输，不需要重新刷固件。没有用于传输图像的串口还会输出日志。
+//HID不需要驱动和特殊权限，用于无法安装驱动的电脑，但速度只有64K/s左右。
+//240x320屏幕还有一个HID触摸屏接口(接口4)，触摸事件同时通过Raw接口发送给开启了事件的主机程序。
+//设置中配置了HID按键时，最后还有一个HID键盘接口，按键和编码器事件同样会通过Raw接口发送。
+
+use core::cell::{Cell, RefCell};
+use embassy_futures::join::{join, join4};
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::Driver;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{with_timeout, Duration};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::class::hid::{self, HidReaderWriter, ReadError};
+use embassy_usb::driver::{Endpoint, EndpointError, EndpointIn, EndpointOut};
+use embassy_usb::msos::{self, windows_version};
+use embassy_usb::{Builder, Config};
+use static_cell::StaticCell;
+use usb_screen_core::hid::{encode_reports, ReportAssembler, HID_REPORT_SIZE};
+use usb_screen_core::input::{InputConfig, KeyUsage, INPUT_ENCODER};
+use usb_screen_core::protocol::{magic_number, EVENT_SUB, PACKET_SIZE};
+use usb_screen_core::serial::{encode_frame, FrameDecoder};
+
+use crate::input::INPUT_CHANNEL;
+use crate::logger::LOG_PIPE;
+use crate::receiver::{Receiver, Response};
+use crate::storage::{settings, Storage};
+use crate::{idle, watchdog, Irqs, SERIAL_NUMBER};
+
+// 这是一个随机生成的 GUID，允许 Windows 上的客户端找到我们的设备
+const DEVICE_INTERFACE_GUIDS: &[&str] = &["{705E1599-5BFF-8DA9-6E33-7141B0636461}"];
+
+//厂商自定义的HID报告描述符: 64字节输入报告和64字节输出报告，没有报告ID
+const HID_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01,       // Usage (0x01)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x02,       //   Usage (0x02)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x40,       //   Report Count (64)
+    0x81, 0x02,       //   Input (Data, Var, Abs)
+    0x09, 0x03,       //   Usage (0x03)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x40,       //   Report Count (64)
+    0x91, 0x02,       //   Output (Data, Var, Abs)
+    0xC0,             // End Collection
+];
+
+//单点触摸屏，坐标范围0~32767，报告为: 按下和在范围内(2位) + 填充(6位) + x(u16 LE) + y(u16 LE)
+#[cfg(feature = "st7789-240x320")]
+const TOUCH_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x0D,       // Usage Page (Digitizer)
+    0x09, 0x04,       // Usage (Touch Screen)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x22,       //   Usage (Finger)
+    0xA1, 0x02,       //   Collection (Logical)
+    0x09, 0x42,       //     Usage (Tip Switch)
+    0x09, 0x32,       //     Usage (In Range)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x75, 0x01,       //     Report Size (1)
+    0x95, 0x02,       //     Report Count (2)
+    0x81, 0x02,       //     Input (Data, Var, Abs)
+    0x95, 0x06,       //     Report Count (6)
+    0x81, 0x03,       //     Input (Const)
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x30,       //     Usage (X)
+    0x09, 0x31,       //     Usage (Y)
+    0x26, 0xFF, 0x7F, //     Logical Maximum (32767)
+    0x75, 0x10,       //     Report Size (16)
+    0x95, 0x02,       //     Report Count (2)
+    0x81, 0x02,       //     Input (Data, Var, Abs)
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+];
+
+//键盘(报告ID 1)和多媒体键(报告ID 2)
+const KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, 0x01,       //   Report ID (1)
+    0x05, 0x07,       //   Usage Page (Keyboard)
+    0x19, 0xE0,       //   Usage Minimum (Left Control)
+    0x29, 0xE7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Var, Abs)
+    0x95, 0x01,       //   Report Count (1)
+    0x75, 0x08,       //   Report Size (8)
+    0x81, 0x03,       //   Input (Const)
+    0x95, 0x06,       //   Report Count (6)
+    0x75, 0x08,       //   Report Size (8)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x00, //   Usage Maximum (255)
+    0x81, 0x00,       //   Input (Data, Array, Abs)
+    0xC0,             // End Collection
+    0x05, 0x0C,       // Usage Page (Consumer)
+    0x09, 0x01,       // Usage (Consumer Control)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, 0x02,       //   Report ID (2)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x03, //   Logical Maximum (1023)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x03, //   Usage Maximum (1023)
+    0x75, 0x10,       //   Report Size (16)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x00,       //   Input (Data, Array, Abs)
+    0xC0,             // End Collection
+];
+
+//按键报告写入的超时时间，主机没有读取时丢弃
+const KEY_WRITE_TIMEOUT: Duration = Duration::from_millis(20);
+
+//触摸事件写入的超时时间，主机没有读取时丢弃
+#[cfg(feature = "st7789-240x320")]
+const TOUCH_WRITE_TIMEOUT: Duration = Duration::from_millis(20);
+
+//日志写入串口的超时时间，串口没有被打开时主机不会读取数据
+const LOG_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[embassy_executor::task]
+pub async fn core0_task_usb(usb: USB, storage: Storage) {
+    // Create the driver, from the HAL.
+    let driver = Driver::new(usb, Irqs);
+
+    // Create embassy-usb Config
+    let mut config = Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("planet");
+    config.product = Some("USB Screen");
+    //串号不能重复
+    let serial_number: &'static str = unsafe { core::str::from_utf8_unchecked(&*core::ptr::addr_of!(SERIAL_NUMBER)) };
+    config.serial_number = Some(serial_number);
+    config.max_power = 500;
+    config.max_packet_size_0 = 64;
+
+    // Required for windows compatibility.
+    // https://developer.nordicsemi.com/nRF_Connect_SDK/doc/1.9.1/kconfig/CONFIG_CDC_ACM_IAD.html#help
+    config.device_class = 0xEF;
+    config.device_sub_class = 0x02;
+    config.device_protocol = 0x01;
+    config.composite_with_iads = true;
+
+    // Create embassy-usb DeviceBuilder using the driver and config.
+    // It needs some buffers for building the descriptors.
+    static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static MSOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static DEVICE_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        DEVICE_DESCRIPTOR.init([0; 256]),
+        CONFIG_DESCRIPTOR.init([0; 256]),
+        BOS_DESCRIPTOR.init([0; 256]),
+        MSOS_DESCRIPTOR.init([0; 256]),
+        CONTROL_BUF.init([0; 64]),
+    );
+
+    // Add the Microsoft OS Descriptor (MSOS/MOD) descriptor.
+    // WinUSB只绑定到Raw接口(function级别)，串口仍然使用系统的CDC驱动
+    builder.msos_descriptor(windows_version::WIN8_1, 0);
+
+    // Raw接口必须是接口0，主机端使用claim_interface(0)
+    let mut function = builder.function(0xFF, 0, 0);
+    function.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
+    function.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
+        "DeviceInterfaceGUIDs",
+        msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
+    ));
+    let mut interface = function.interface();
+    let mut alt = interface.alt_setting(0xFF, 0, 0, None);
+    let mut read_ep = alt.endpoint_bulk_out(64);
+    let mut write_ep = alt.endpoint_bulk_in(64);
+    drop(function);
+
+    // CDC串口
+    let class = {
+        static STATE: StaticCell<State> = StaticCell::new();
+        let state = STATE.init(State::new());
+        CdcAcmClass::new(&mut builder, state, 64)
+    };
+    let (sender, mut serial_receiver) = class.split();
+    let sender = Mutex::<NoopRawMutex, _>::new(sender);
+
+    // HID
+    let hid = {
+        static STATE: StaticCell<hid::State> = StaticCell::new();
+        let config = hid::Config{
+            report_descriptor: HID_REPORT_DESCRIPTOR,
+            request_handler: None,
+            poll_ms: 1,
+            max_packet_size: 64,
+        };
+        HidReaderWriter::<_, HID_REPORT_SIZE, HID_REPORT_SIZE>::new(&mut builder, STATE.init(hid::State::new()), config)
+    };
+    let (mut hid_reader, mut hid_writer) = hid.split();
+
+    // HID触摸屏，只有输入报告
+    #[cfg(feature = "st7789-240x320")]
+    let mut digitizer = {
+        static STATE: StaticCell<hid::State> = StaticCell::new();
+        let config = hid::Config{
+            report_descriptor: TOUCH_REPORT_DESCRIPTOR,
+            request_handler: None,
+            poll_ms: 10,
+            max_packet_size: 8,
+        };
+        hid::HidWriter::<_, 5>::new(&mut builder, STATE.init(hid::State::new()), config)
+    };
+
+    // HID键盘，只在设置中配置了HID按键时添加
+    let input_config = InputConfig::from_bits(&settings().inputs);
+    let has_keys = input_config.buttons.iter().flatten().map(|b| b.usage)
+        .chain(input_config.encoder.iter().flat_map(|e| [e.clockwise, e.counter_clockwise]))
+        .any(|usage| usage != KeyUsage::None);
+    let mut keyboard = if has_keys{
+        static STATE: StaticCell<hid::State> = StaticCell::new();
+        let config = hid::Config{
+            report_descriptor: KEYBOARD_REPORT_DESCRIPTOR,
+            request_handler: None,
+            poll_ms: 10,
+            max_packet_size: 16,
+        };
+        Some(hid::HidWriter::<_, 9>::new(&mut builder, STATE.init(hid::State::new()), config))
+    }else{
+        None
+    };
+
+    // Build the builder.
+    let mut usb = builder.build();
+
+    //所有接口都在core0的同一个任务中运行，不会同时访问Flash
+    let storage = RefCell::new(storage);
+    //串口收到过协议数据后，不再输出日志，防止和返回的数据混在一起
+    let serial_in_use = Cell::new(false);
+    //Raw接口的主机程序是否开启了事件
+    let events = Cell::new(false);
+    //回复和事件都通过Raw接口的IN端点发送
+    let write_ep = Mutex::<NoopRawMutex, _>::new(write_ep);
+
+    //Raw接口
+    let raw_fut = async {
+        let mut receiver = Receiver::new();
+        loop {
+            read_ep.wait_enabled().await;
+            loop {
+                let mut data = [0; 64];
+                let n = match read_ep.read(&mut data).await{
+                    Ok(n) => n,
+                    Err(_) => {
+                        //USB断开，通知core1回到空闲画面，然后等待重新连接
+                        idle::HOST_DISCONNECTED.signal(());
+                        receiver.disconnected(&storage);
+                        events.set(false);
+                        break;
+                    }
+                };
+                if magic_number(&data[..n]) == Some(EVENT_SUB){
+                    events.set(n >= 10 && u16::from_be_bytes([data[8], data[9]]) != 0);
+                    continue;
+                }
+                //主机发送ReadInfo和升级固件后必须读取，否则会卡死
+                if let Some(response) = watchdog::core0_handle(receiver.handle(&storage, &data[..n])).await{
+                    let data = response.data();
+                    let mut write_ep = write_ep.lock().await;
+                    for packet in data.chunks(PACKET_SIZE){
+                        write_ep.write(packet).await.ok();
+                    }
+                    if data.len() % PACKET_SIZE == 0{
+                        write_ep.write(&[]).await.ok();
+                    }
+                    drop(write_ep);
+                    finish_response(&response).await;
+                }
+            }
+        }
+    };
+
+    //串口，字节流按帧解码(见usb_screen_core::serial)，丢失或多出字节后在下一个分隔符重新同步
+    let serial_fut = async {
+        let mut receiver = Receiver::new();
+        let mut decoder = FrameDecoder::default();
+        loop {
+            serial_receiver.wait_connection().await;
+            serial_in_use.set(false);
+            decoder.reset();
+            let mut data = [0; 64];
+            loop {
+                let n = match serial_receiver.read_packet(&mut data).await{
+                    Ok(n) => n,
+                    Err(EndpointError::Disabled) => {
+                        idle::HOST_DISCONNECTED.signal(());
+                        receiver.disconnected(&storage);
+                        break;
+                    }
+                    Err(_) => continue,
+                };
+                for &byte in &data[..n]{
+                    let packet = match decoder.push(byte){
+                        Some(packet) => packet,
+                        None => continue,
+                    };
+                    serial_in_use.set(true);
+                    //回复整个编码为一帧，持有sender期间日志不会插入帧中
+                    if let Some(response) = watchdog::core0_handle(receiver.handle(&storage, packet)).await{
+                        let frame = encode_frame(&response.data());
+                        let mut sender = sender.lock().await;
+                        for packet in frame.chunks(PACKET_SIZE){
+                            let _ = sender.write_packet(packet).await;
+                        }
+                        if frame.len() % PACKET_SIZE == 0{
+                            let _ = sender.write_packet(&[]).await;
+                        }
+                        drop(sender);
+                        finish_response(&response).await;
+                    }
+                }
+            }
+        }
+    };
+
+    //HID，报告拼接成包后和其他接口一样处理
+    let hid_fut = async {
+        let mut receiver = Receiver::new();
+        let mut assembler = ReportAssembler::default();
+        loop {
+            hid_reader.ready().await;
+            loop {
+                let mut report = [0; HID_REPORT_SIZE];
+                match hid_reader.read(&mut report).await{
+                    Ok(_) => (),
+                    Err(ReadError::Disabled) => {
+                        idle::HOST_DISCONNECTED.signal(());
+                        receiver.disconnected(&storage);
+                        assembler.reset();
+                        break;
+                    }
+                    Err(_) => continue,
+                }
+                let packet = match assembler.push(&report){
+                    Some(packet) => packet,
+                    None => continue,
+                };
+                if let Some(response) = watchdog::core0_handle(receiver.handle(&storage, packet)).await{
+                    for report in encode_reports(&response.data()){
+                        hid_writer.write(&report).await.ok();
+                    }
+                    finish_response(&response).await;
+                }
+            }
+        }
+    };
+
+    //日志
+    let log_fut = async {
+        let mut buf = [0; 64];
+        loop {
+            let n = LOG_PIPE.read(&mut buf).await;
+            let mut sender = sender.lock().await;
+            //串口没有打开或正在传输图像时丢弃日志
+            if serial_in_use.get() || !sender.dtr(){
+                continue;
+            }
+            let _ = with_timeout(LOG_WRITE_TIMEOUT, sender.write_packet(&buf[..n])).await;
+        }
+    };
+
+    //触摸事件
+    #[cfg(feature = "st7789-240x320")]
+    let touch_fut = async {
+        use crate::touch::TOUCH_CHANNEL;
+        use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+        loop {
+            let event = TOUCH_CHANNEL.receive().await;
+            let x = (event.x as u32 * 32767 / (SCREEN_WIDTH as u32 - 1)) as u16;
+            let y = (event.y as u32 * 32767 / (SCREEN_HEIGHT as u32 - 1)) as u16;
+            let mut report = [0u8; 5];
+            report[0] = if event.pressed{ 0b11 }else{ 0 };
+            report[1..3].copy_from_slice(&x.to_le_bytes());
+            report[3..5].copy_from_slice(&y.to_le_bytes());
+            let _ = with_timeout(TOUCH_WRITE_TIMEOUT, digitizer.write(&report)).await;
+            if events.get(){
+                let mut write_ep = write_ep.lock().await;
+                let _ = with_timeout(TOUCH_WRITE_TIMEOUT, write_ep.write(&event.encode())).await;
+            }
+        }
+    };
+    #[cfg(not(feature = "st7789-240x320"))]
+    let touch_fut = async {};
+
+    //按键和编码器
+    let input_fut = async {
+        let mut keys = KeyState::default();
+        loop {
+            let (event, usage) = INPUT_CHANNEL.receive().await;
+            if let Some(keyboard) = keyboard.as_mut(){
+                //编码器每转一格，按下后立即松开
+                let reports = if event.kind == INPUT_ENCODER{
+                    [keys.update(usage, true), keys.update(usage, false)]
+                }else{
+                    [keys.update(usage, event.value != 0), None]
+                };
+                for (report, len) in reports.into_iter().flatten(){
+                    let _ = with_timeout(KEY_WRITE_TIMEOUT, keyboard.write(&report[..len])).await;
+                }
+            }
+            if events.get(){
+                let mut write_ep = write_ep.lock().await;
+                let _ = with_timeout(KEY_WRITE_TIMEOUT, write_ep.write(&event.encode())).await;
+            }
+        }
+    };
+
+    join(usb.run(), join(join4(raw_fut, serial_fut, hid_fut, log_fut), join(touch_fut, input_fut))).await;
+}
+
+//当前按下的键盘按键，多个按键可以同时按下
+#[derive(Default)]
+struct KeyState{
+    modifiers: u8,
+    keys: [u8; 6],
+}
+
+impl KeyState{
+    //返回需要发送的HID报告和长度
+    fn update(&mut self, usage: KeyUsage, pressed: bool) -> Option<([u8; 9], usize)>{
+        match usage{
+            KeyUsage::None => None,
+            //0xE0~0xE7为Ctrl、Shift等修饰键
+            KeyUsage::Keyboard(code @ 0xE0..=0xE7) => {
+                let bit = 1 << (code - 0xE0);
+                if pressed{ self.modifiers |= bit }else{ self.modifiers &= !bit }
+                Some(self.keyboard_report())
+            }
+            KeyUsage::Keyboard(code) => {
+                if pressed{
+                    if !self.keys.contains(&code){
+                        if let Some(slot) = self.keys.iter_mut().find(|k| **k == 0){
+                            *slot = code;
+                        }
+                    }
+                }else{
+                    self.keys.iter_mut().filter(|k| **k == code).for_each(|k| *k = 0);
+                }
+                Some(self.keyboard_report())
+            }
+            KeyUsage::Consumer(usage) => {
+                let usage = if pressed{ usage }else{ 0 };
+                let mut report = [0u8; 9];
+                report[0] = 2;
+                report[1..3].copy_from_slice(&usage.to_le_bytes());
+                Some((report, 3))
+            }
+        }
+    }
+
+    fn keyboard_report(&self) -> ([u8; 9], usize){
+        let mut report = [0u8; 9];
+        report[0] = 1;
+        report[1] = self.modifiers;
+        report[3..9].copy_from_slice(&self.keys);
+        (report, 9)
+    }
+}
+
+//升级固件成功后，等待主机读取结果后重启
+async fn finish_response(response: &Response){
+    if response.reboot(){
+        embassy_time::Timer::after_millis(100).await;
+        crate::watchdog::reboot();
+    }
+}
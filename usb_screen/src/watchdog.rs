@@ -0,0 +1,146 @@
+//看门狗：core1的绘制循环每处理一条消息(或等待消息期间定时)报告一次，core0的USB接收器每处理完一个包报告一次，
+//core0上的喂狗任务只在两个核自上次喂狗以来都报告过时喂狗(core0没有正在处理的包时视为空闲，不需要报告)。
+//任何一个核卡死(例如SPI传输卡住、等待DISPLAY_LOCK不结束)超过WATCHDOG_TIMEOUT后自动复位，两个执行器仍在运行也一样。
+//复位原因在设备信息中报告(reset=...;watchdog_resets=N)。
+
+use core::cell::Cell;
+use core::future::Future;
+
+use embassy_rp::pac;
+use embassy_rp::watchdog::Watchdog;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Ticker, Timer};
+use portable_atomic::{AtomicBool, AtomicU8, Ordering};
+
+//RP2040的看门狗最长约8秒
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(200);
+const FEED_INTERVAL: Duration = Duration::from_millis(1000);
+
+//看门狗复位后不会清除的寄存器：最后一次检查时没有报告的核(bit0为core0，bit1为core1)、看门狗复位的次数
+const SCRATCH_STALLED: usize = 0;
+const SCRATCH_RESETS: usize = 1;
+
+static CORE0_ALIVE: AtomicBool = AtomicBool::new(false);
+static CORE1_ALIVE: AtomicBool = AtomicBool::new(false);
+//core0正在处理包的接收器数量(Raw、串口、HID各一个)
+static CORE0_BUSY: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetReason{
+    //上电、RUN引脚复位或SYSRESETREQ(升级固件后复制完成时)
+    PowerOn,
+    //重启到新固件
+    Software,
+    //上次运行时panic
+    Panic,
+    //看门狗超时，参数为卡住的核
+    Watchdog(u8),
+}
+
+impl ResetReason{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            ResetReason::PowerOn => "power-on",
+            ResetReason::Software => "software",
+            ResetReason::Panic => "panic",
+            ResetReason::Watchdog(0) => "watchdog-core0",
+            ResetReason::Watchdog(_) => "watchdog-core1",
+        }
+    }
+}
+
+static RESET_REASON: Mutex<CriticalSectionRawMutex, Cell<(ResetReason, u32)>> = Mutex::new(Cell::new((ResetReason::PowerOn, 0)));
+
+//启动时调用(在复制新固件之前)：关闭上次运行时启动的看门狗，读取复位原因
+pub fn init(watchdog: &mut Watchdog, panicked: bool){
+    pac::WATCHDOG.ctrl().modify(|w| w.set_enable(false));
+    let reason = pac::WATCHDOG.reason().read();
+    let mut resets = watchdog.get_scratch(SCRATCH_RESETS);
+    let reset_reason = if panicked{
+        ResetReason::Panic
+    }else if reason.timer(){
+        resets = resets.wrapping_add(1);
+        //喂狗任务自己没有运行时，最后一次检查的结果是正常的，也算作core0卡住
+        let core = if watchdog.get_scratch(SCRATCH_STALLED) & 0b10 != 0{ 1 }else{ 0 };
+        ResetReason::Watchdog(core)
+    }else if reason.force(){
+        ResetReason::Software
+    }else{
+        //上电后寄存器的值是0，SYSRESETREQ不会清除寄存器
+        ResetReason::PowerOn
+    };
+    watchdog.set_scratch(SCRATCH_RESETS, resets);
+    watchdog.set_scratch(SCRATCH_STALLED, 0);
+    if let ResetReason::Watchdog(_) = reset_reason{
+        log::error!("reset by watchdog: {}", reset_reason.as_str());
+    }
+    RESET_REASON.lock(|r| r.set((reset_reason, resets)));
+}
+
+//复位原因和看门狗复位的次数(上电后清零)
+pub fn reset_reason() -> (ResetReason, u32){
+    RESET_REASON.lock(|r| r.get())
+}
+
+//通过看门狗立即重启，重启后的复位原因为software
+pub fn reboot() -> !{
+    pac::WATCHDOG.ctrl().write(|w| w.set_trigger(true));
+    loop{
+        cortex_m::asm::nop();
+    }
+}
+
+//panic时停止看门狗，显示panic信息期间不会被复位
+pub fn disable(){
+    pac::WATCHDOG.ctrl().modify(|w| w.set_enable(false));
+}
+
+//core1的绘制循环处理了一条消息或绘制了一帧空闲画面
+pub fn core1_alive(){
+    CORE1_ALIVE.store(true, Ordering::Relaxed);
+}
+
+async fn heartbeat() -> !{
+    loop {
+        core1_alive();
+        Timer::after(HEARTBEAT_INTERVAL).await;
+    }
+}
+
+//core1等待消息，等待期间定时报告(通道和USB断开信号上的等待不是卡死)
+pub async fn core1_waiting<F: Future>(fut: F) -> F::Output{
+    match select(fut, heartbeat()).await{
+        Either::First(output) => output,
+        Either::Second(never) => never,
+    }
+}
+
+//core0的接收器处理一个包，处理完成时报告；处理期间卡住(例如一直等待core1)不报告
+pub async fn core0_handle<F: Future>(fut: F) -> F::Output{
+    CORE0_BUSY.fetch_add(1, Ordering::Relaxed);
+    let output = fut.await;
+    CORE0_BUSY.fetch_sub(1, Ordering::Relaxed);
+    CORE0_ALIVE.store(true, Ordering::Relaxed);
+    output
+}
+
+//core0喂狗
+#[embassy_executor::task]
+pub async fn watchdog_task(mut watchdog: Watchdog){
+    watchdog.start(WATCHDOG_TIMEOUT);
+    let mut ticker = Ticker::every(FEED_INTERVAL);
+    loop {
+        ticker.next().await;
+        let core0 = CORE0_ALIVE.swap(false, Ordering::Relaxed) || CORE0_BUSY.load(Ordering::Relaxed) == 0;
+        let core1 = CORE1_ALIVE.swap(false, Ordering::Relaxed);
+        if core0 && core1{
+            watchdog.set_scratch(SCRATCH_STALLED, 0);
+            watchdog.feed();
+        }else{
+            watchdog.set_scratch(SCRATCH_STALLED, (!core0) as u32 | ((!core1) as u32) << 1);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! 协议会话的录制格式：主机发送的命令包、数据和读取到的回复按顺序保存，可以回放到设备或模拟器中重现问题。
+//!
+//! 格式: 魔数 + 若干记录，每条记录为 类型(u8) + 距录制开始的时间(微秒，u64 BE) + 长度(u32 BE) + 数据。
+
+use alloc::vec::Vec;
+
+use crate::protocol::{magic_number, MAGIC_NUM_LEN};
+
+pub const CAPTURE_MAGIC: u64 = u64::from_be_bytes(*b"ScrCapt1");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind{
+    //write_packet发送的命令包
+    Packet = 0,
+    //write_data发送的数据(压缩后的图像、精灵、开机画面、固件)
+    Data = 1,
+    //read_response读取到的回复
+    Response = 2,
+}
+
+impl RecordKind{
+    pub fn from_u8(value: u8) -> Option<Self>{
+        match value{
+            0 => Some(RecordKind::Packet),
+            1 => Some(RecordKind::Data),
+            2 => Some(RecordKind::Response),
+            _ => None,
+        }
+    }
+}
+
+/// 一条记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a>{
+    pub kind: RecordKind,
+    pub time_us: u64,
+    pub data: &'a [u8],
+}
+
+impl Record<'_>{
+    pub const HEADER_LEN: usize = 13;
+
+    pub fn encode(&self) -> Vec<u8>{
+        let mut buf = Vec::with_capacity(Self::HEADER_LEN + self.data.len());
+        buf.push(self.kind as u8);
+        buf.extend_from_slice(&self.time_us.to_be_bytes());
+        buf.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        buf.extend_from_slice(self.data);
+        buf
+    }
+}
+
+/// 录制文件的开头
+pub fn capture_header() -> [u8; MAGIC_NUM_LEN]{
+    CAPTURE_MAGIC.to_be_bytes()
+}
+
+/// 逐条读取录制文件中的记录，不是录制文件时返回None。
+/// 录制中断时最后一条记录可能不完整，遇到不完整或无效的记录时结束
+pub fn records(capture: &[u8]) -> Option<Records<'_>>{
+    if magic_number(capture)? != CAPTURE_MAGIC{
+        return None;
+    }
+    Some(Records { data: &capture[MAGIC_NUM_LEN..] })
+}
+
+pub struct Records<'a>{
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Records<'a>{
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        let data = self.data;
+        if data.len() < Record::HEADER_LEN{
+            return None;
+        }
+        let kind = RecordKind::from_u8(data[0])?;
+        let mut time = [0u8; 8];
+        time.copy_from_slice(&data[1..9]);
+        let len = u32::from_be_bytes([data[9], data[10], data[11], data[12]]) as usize;
+        if len > data.len() - Record::HEADER_LEN{
+            self.data = &[];
+            return None;
+        }
+        let end = Record::HEADER_LEN + len;
+        self.data = &data[end..];
+        Some(Record{ kind, time_us: u64::from_be_bytes(time), data: &data[Record::HEADER_LEN..end] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_round_trip() {
+        let written = [
+            Record { kind: RecordKind::Packet, time_us: 0, data: &[1, 2, 3] },
+            Record { kind: RecordKind::Data, time_us: 1500, data: &[] },
+            Record { kind: RecordKind::Response, time_us: 1 << 40, data: &[9; 70] },
+        ];
+        let mut capture = capture_header().to_vec();
+        for record in &written{
+            capture.extend_from_slice(&record.encode());
+        }
+        let read: Vec<_> = records(&capture).unwrap().collect();
+        assert_eq!(read, written);
+
+        // 录制中断，最后一条记录不完整
+        let read: Vec<_> = records(&capture[..capture.len() - 1]).unwrap().collect();
+        assert_eq!(read, written[..2]);
+
+        assert!(records(&[0u8; 32]).is_none());
+    }
+}
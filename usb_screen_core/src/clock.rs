@@ -0,0 +1,56 @@
+//! 设备端时钟画面使用的时间格式。
+
+use alloc::format;
+use alloc::string::String;
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// 日期时间，weekday: 0=星期日 ... 6=星期六
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockTime{
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl ClockTime{
+    /// 各字段是否在有效范围内(不检查每个月的天数)
+    pub fn is_valid(&self) -> bool{
+        self.year <= 4095
+            && (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.weekday < 7
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// 例如 12:34:56
+    pub fn time_text(&self) -> String{
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+
+    /// 例如 2024/05/01 WED
+    pub fn date_text(&self) -> String{
+        let weekday = WEEKDAYS.get(self.weekday as usize).copied().unwrap_or("");
+        format!("{:04}/{:02}/{:02} {}", self.year, self.month, self.day, weekday)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_clock() {
+        let time = ClockTime { year: 2024, month: 5, day: 1, weekday: 3, hour: 9, minute: 5, second: 0 };
+        assert!(time.is_valid());
+        assert_eq!(time.time_text(), "09:05:00");
+        assert_eq!(time.date_text(), "2024/05/01 WED");
+        assert!(!ClockTime { month: 13, ..time }.is_valid());
+    }
+}
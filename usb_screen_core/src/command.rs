@@ -0,0 +1,340 @@
+//! 精灵和屏幕设置命令的执行：固件在core1执行，模拟器收到后直接执行，两边共用同一份代码，通过display::Screen绘制。
+//!
+//! 命令中的位置和大小来自主机，绘制前裁剪到屏幕内；失败的命令不改变屏幕和精灵缓存，返回的CommandError记录到性能计数器。
+
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::display::Screen;
+use crate::frame::{decompressed_len, FrameError};
+use crate::imageproc::{warp, Interpolation, Projection};
+use crate::panel::PanelConfig;
+use crate::protocol::{DrawSprites, DrawWarp, ScrollArea, UploadSprite, INTERPOLATION_BILINEAR};
+use crate::rgb565::{rgb565_be_to_pixels, Rgb565Image, Rgb565Pixel};
+use crate::scroll::Scroll;
+use crate::sprite::{opaque_runs, Sprite, SpriteCache};
+use crate::stats::DeviceStats;
+
+/// 接收完成后交给Screen所在的一方执行的命令
+pub enum Command{
+    /// 上传精灵，data为lz4压缩的RGB565 BE数据
+    UploadSprite{ sprite: UploadSprite, data: Vec<u8> },
+    /// 在指定位置绘制精灵
+    DrawSprites(DrawSprites),
+    /// 变换绘制精灵
+    DrawWarp(DrawWarp),
+    /// 定义硬件滚动区域
+    ScrollArea(ScrollArea),
+    /// 设置滚动偏移
+    ScrollOffset(u16),
+    /// 设置反色和Gamma曲线
+    SetPanel(PanelConfig),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError{
+    /// 精灵数据lz4解压失败
+    Decode,
+    /// 精灵数据长度与宽高不符等，原因与被拒绝的帧相同
+    Rejected(FrameError),
+}
+
+impl CommandError{
+    /// 记录到性能计数器：解压失败计入decode_errors，其他计入rejected_frames
+    pub fn record(self, stats: &mut DeviceStats){
+        match self{
+            CommandError::Decode => stats.decode_errors += 1,
+            CommandError::Rejected(error) => {
+                stats.rejected_frames += 1;
+                stats.last_error = error as u32;
+            }
+        }
+    }
+}
+
+//变换绘制时每个横条的最大像素数(8K字节)
+const WARP_STRIPE: usize = 4096;
+
+pub struct CommandHandler{
+    screen_width: u16,
+    screen_height: u16,
+    sprites: SpriteCache,
+    scroll: Scroll,
+}
+
+impl CommandHandler{
+    /// sprite_cache_capacity: 精灵缓存容量(字节)
+    pub fn new(screen_width: u16, screen_height: u16, sprite_cache_capacity: usize) -> Self{
+        Self{
+            screen_width,
+            screen_height,
+            sprites: SpriteCache::new(sprite_cache_capacity),
+            scroll: Scroll::default(),
+        }
+    }
+
+    pub fn sprites(&self) -> &SpriteCache{
+        &self.sprites
+    }
+
+    /// 当前的硬件滚动区域和偏移
+    pub fn scroll(&self) -> Scroll{
+        self.scroll
+    }
+
+    pub async fn handle<S: Screen>(&mut self, screen: &mut S, command: Command) -> Result<(), CommandError>{
+        match command{
+            Command::UploadSprite { sprite, data } => self.upload_sprite(sprite, data)?,
+            Command::DrawSprites(cmd) => {
+                for draw in cmd.iter(){
+                    self.draw_sprite(screen, draw.id, draw.x, draw.y).await;
+                }
+            }
+            Command::DrawWarp(cmd) => self.draw_warp(screen, &cmd).await,
+            Command::ScrollArea(area) => {
+                self.scroll.set_area(area, self.screen_width);
+                screen.set_scroll(&self.scroll).await;
+            }
+            Command::ScrollOffset(offset) => {
+                if self.scroll.is_active(){
+                    self.scroll.set_offset(offset);
+                    screen.set_scroll(&self.scroll).await;
+                }
+            }
+            //显存偏移会改变滚动区域的位置，重新设置
+            Command::SetPanel(panel) => {
+                screen.set_panel(&panel).await;
+                if self.scroll.is_active(){
+                    screen.set_scroll(&self.scroll).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn upload_sprite(&mut self, cmd: UploadSprite, data: Vec<u8>) -> Result<(), CommandError>{
+        //宽x高x2在32位的设备上可能溢出
+        let bytes = (cmd.width as usize).checked_mul(cmd.height as usize).and_then(|pixels| pixels.checked_mul(2))
+            .ok_or(CommandError::Rejected(FrameError::TooLarge))?;
+        //解压之前先检查空间，防止内存不足；解压成功后insert才替换同一编号的旧图像，错误的数据不会破坏旧图像
+        if self.sprites.check_space(cmd.id, bytes).is_err(){
+            return Ok(());
+        }
+        //开头的原始长度来自主机，与宽高不符时不解压，解压到按宽高分配的缓冲区，不按数据中的长度分配内存
+        if decompressed_len(&data) != Some(bytes){
+            return Err(CommandError::Rejected(FrameError::SizeMismatch));
+        }
+        let mut image = vec![0u8; bytes];
+        match lz4_flex::block::decompress_into(&data[4..], &mut image){
+            Ok(len) if len == bytes => (),
+            Ok(_) => return Err(CommandError::Rejected(FrameError::SizeMismatch)),
+            Err(_) => return Err(CommandError::Decode),
+        }
+        //压缩数据先释放，同时在内存中的最多是解压后的图像和转换后的像素
+        drop(data);
+        let sprite = Sprite{
+            width: cmd.width,
+            height: cmd.height,
+            key: cmd.color_key(),
+            pixels: rgb565_be_to_pixels(&image).collect(),
+        };
+        let _ = self.sprites.insert(cmd.id, sprite);
+        Ok(())
+    }
+
+    async fn draw_sprite<S: Screen>(&self, screen: &mut S, id: u16, x: u16, y: u16){
+        let sprite = match self.sprites.get(id){
+            Some(sprite) => sprite,
+            None => return,
+        };
+        if x >= self.screen_width || y >= self.screen_height || sprite.width == 0 || sprite.height == 0{
+            return;
+        }
+        //超出屏幕的部分裁掉
+        let width = sprite.width.min(self.screen_width - x);
+        let height = sprite.height.min(self.screen_height - y);
+
+        //没有透明色又不需要裁剪时一次绘制整个精灵
+        if sprite.key.is_none() && width == sprite.width{
+            let len = sprite.width as usize * height as usize;
+            screen.draw_pixels(&sprite.pixels[..len], x, y, width, height).await;
+            return;
+        }
+
+        //逐行绘制不透明的区间
+        let rows = sprite.pixels.chunks(sprite.width as usize).take(height as usize);
+        for (row_y, row) in rows.enumerate(){
+            let row = &row[..width as usize];
+            for (start, len) in opaque_runs(row, sprite.key){
+                screen.draw_pixels(&row[start..start+len], x + start as u16, y + row_y as u16, len as u16, 1).await;
+            }
+        }
+    }
+
+    async fn draw_warp<S: Screen>(&mut self, screen: &mut S, cmd: &DrawWarp){
+        let sprite = match self.sprites.get_mut(cmd.id){
+            Some(sprite) => sprite,
+            None => return,
+        };
+        //目标区域必须在屏幕内
+        if cmd.width == 0 || cmd.height == 0
+            || cmd.x as u32 + cmd.width as u32 > self.screen_width as u32
+            || cmd.y as u32 + cmd.height as u32 > self.screen_height as u32{
+            return;
+        }
+        //矩阵不可逆时忽略
+        let projection = match Projection::from_matrix(cmd.matrix){
+            Some(projection) => projection,
+            None => return,
+        };
+        let interpolation = if cmd.interpolation == INTERPOLATION_BILINEAR{
+            Interpolation::Bilinear
+        }else{
+            Interpolation::Nearest
+        };
+        //按横条变换和绘制，每条最多WARP_STRIPE个像素，目标区域再大也只需要一条的内存
+        let rows = (WARP_STRIPE / cmd.width as usize).clamp(1, cmd.height as usize) as u16;
+        let mut pixels = vec![0u16; cmd.width as usize * rows as usize];
+        let source = Rgb565Image::new(&mut sprite.pixels, sprite.width, sprite.height);
+        for top in (0..cmd.height).step_by(rows as usize){
+            let height = rows.min(cmd.height - top);
+            let pixels = &mut pixels[..cmd.width as usize * height as usize];
+            //横条的第一行对应目标区域的第top行
+            let stripe = projection.and_then(Projection::translate(0.0, -(top as f32)));
+            warp(&source, &stripe, interpolation, Rgb565Pixel(cmd.background), &mut Rgb565Image::new(pixels, cmd.width, height));
+            screen.draw_pixels(pixels, cmd.x, cmd.y + top, cmd.width, height).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use crate::protocol::SpriteDraw;
+
+    //测试用的屏幕：记录绘制的区域和滚动设置
+    #[derive(Default)]
+    struct TestScreen{
+        draws: Vec<(u16, u16, u16, u16)>,
+        scrolls: Vec<Scroll>,
+    }
+
+    impl Screen for TestScreen{
+        async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+            assert_eq!(pixels.len(), width as usize * height as usize);
+            self.draws.push((x, y, width, height));
+        }
+
+        async fn set_scroll(&mut self, scroll: &Scroll){
+            self.scrolls.push(*scroll);
+        }
+
+        async fn set_panel(&mut self, _panel: &PanelConfig){}
+    }
+
+    //把绘制的像素保存到帧缓冲区
+    struct FrameScreen{
+        width: u16,
+        pixels: Vec<u16>,
+    }
+
+    impl Screen for FrameScreen{
+        async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16){
+            for (row_y, row) in pixels.chunks(width as usize).take(height as usize).enumerate(){
+                let start = (y as usize + row_y) * self.width as usize + x as usize;
+                self.pixels[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+
+        async fn set_scroll(&mut self, _scroll: &Scroll){}
+
+        async fn set_panel(&mut self, _panel: &PanelConfig){}
+    }
+
+    //TestScreen的方法不会挂起，poll一次就完成
+    fn block_on<F: Future>(future: F) -> F::Output{
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())){
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is pending"),
+        }
+    }
+
+    fn upload(id: u16, width: u16, height: u16, data: &[u8]) -> Command{
+        let sprite = UploadSprite{ id, width, height, format: 0, key: 0 };
+        Command::UploadSprite{ sprite, data: lz4_flex::compress_prepend_size(data) }
+    }
+
+    #[test]
+    fn sprites_are_clipped_to_screen() {
+        let mut screen = TestScreen::default();
+        let mut handler = CommandHandler::new(160, 128, 1024);
+        block_on(handler.handle(&mut screen, upload(1, 4, 2, &[0x12; 16]))).unwrap();
+        assert_eq!(handler.sprites().free(), 1024 - 16);
+        let draws = DrawSprites::pack(&[SpriteDraw{ id: 1, x: 158, y: 127 }, SpriteDraw{ id: 1, x: 160, y: 0 }]).next().unwrap();
+        block_on(handler.handle(&mut screen, Command::DrawSprites(draws))).unwrap();
+        assert_eq!(screen.draws, [(158, 127, 2, 1)]);
+    }
+
+    #[test]
+    fn failed_upload_keeps_old_sprite() {
+        let mut screen = TestScreen::default();
+        let mut handler = CommandHandler::new(160, 128, 1024);
+        block_on(handler.handle(&mut screen, upload(1, 2, 2, &[0x12; 8]))).unwrap();
+        let corrupt = Command::UploadSprite{ sprite: UploadSprite{ id: 1, width: 2, height: 2, format: 0, key: 0 }, data: vec![8, 0, 0, 0, 0xFF] };
+        assert_eq!(block_on(handler.handle(&mut screen, corrupt)), Err(CommandError::Decode));
+        assert_eq!(block_on(handler.handle(&mut screen, upload(1, 2, 2, &[0x34; 4]))), Err(CommandError::Rejected(FrameError::SizeMismatch)));
+        assert_eq!(handler.sprites().get(1).map(|s| s.pixels[0]), Some(0x1212));
+    }
+
+    #[test]
+    fn sprite_size_is_checked_before_decompressing() {
+        let mut screen = TestScreen::default();
+        let mut handler = CommandHandler::new(160, 128, 1024 * 1024);
+        //开头的长度声称解压后有4G
+        let mut data = lz4_flex::compress_prepend_size(&[0x12; 8]);
+        data[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let sprite = UploadSprite{ id: 1, width: 2, height: 2, format: 0, key: 0 };
+        assert_eq!(block_on(handler.handle(&mut screen, Command::UploadSprite{ sprite, data })), Err(CommandError::Rejected(FrameError::SizeMismatch)));
+        //长度与宽高相符但数据解压后更短
+        let mut data = lz4_flex::compress_prepend_size(&[0x12; 6]);
+        data[..4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(block_on(handler.handle(&mut screen, Command::UploadSprite{ sprite, data })).is_err());
+        assert!(handler.sprites().get(1).is_none());
+    }
+
+    #[test]
+    fn warp_is_drawn_in_stripes() {
+        let mut screen = FrameScreen{ width: 320, pixels: vec![0; 320 * 240] };
+        let mut handler = CommandHandler::new(320, 240, 1024 * 16);
+        let data: Vec<u8> = (0..64u16).flat_map(|i| (i * 997).to_be_bytes()).collect();
+        block_on(handler.handle(&mut screen, upload(1, 8, 8, &data))).unwrap();
+        //放大30倍铺满整个屏幕，分成多个横条绘制
+        let matrix = (Projection::translate(1.0, 0.0).and_then(Projection::scale(30.0, 30.0))).matrix();
+        let cmd = DrawWarp{ id: 1, x: 0, y: 0, width: 320, height: 240, background: 0xFFFF, interpolation: 0, matrix };
+        block_on(handler.handle(&mut screen, Command::DrawWarp(cmd))).unwrap();
+        let sprite = handler.sprites().get(1).unwrap();
+        for (x, y) in [(35, 5), (185, 215), (245, 95)]{
+            assert_eq!(screen.pixels[y * 320 + x], sprite.pixels[(y / 30) * 8 + x / 30 - 1], "({x}, {y})");
+        }
+        assert_eq!((screen.pixels[100 * 320 + 5], screen.pixels[100 * 320 + 300]), (0xFFFF, 0xFFFF));
+
+        //超出屏幕的区域不绘制
+        let mut screen = TestScreen::default();
+        block_on(handler.handle(&mut screen, Command::DrawWarp(DrawWarp{ height: 241, ..cmd }))).unwrap();
+        assert!(screen.draws.is_empty());
+    }
+
+    #[test]
+    fn scroll_offset_needs_area() {
+        let mut screen = TestScreen::default();
+        let mut handler = CommandHandler::new(320, 240, 1024);
+        block_on(handler.handle(&mut screen, Command::ScrollOffset(10))).unwrap();
+        assert!(screen.scrolls.is_empty());
+        block_on(handler.handle(&mut screen, Command::ScrollArea(ScrollArea{ start: 0, len: 100 }))).unwrap();
+        block_on(handler.handle(&mut screen, Command::ScrollOffset(110))).unwrap();
+        assert_eq!(handler.scroll(), Scroll{ start: 0, len: 100, offset: 10 });
+        assert_eq!(screen.scrolls.len(), 2);
+    }
+}
@@ -0,0 +1,143 @@
+//! panic信息的保存格式：固件把panic的位置和信息写入复位后不会清除的RAM，重启后读取并报告给主机。
+//! 上电时RAM中是随机数据，用魔数、长度和CRC32判断是否有效。
+
+use core::fmt;
+
+use alloc::string::String;
+
+use crate::update::Crc32;
+
+pub const CRASH_MAGIC: u32 = u32::from_be_bytes(*b"Pnc!");
+//最多保存的字节数，超出部分截断
+pub const CRASH_MESSAGE_LEN: usize = 240;
+
+/// 保存在RAM中的panic信息
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CrashRecord{
+    magic: u32,
+    len: u32,
+    crc: u32,
+    message: [u8; CRASH_MESSAGE_LEN],
+}
+
+impl Default for CrashRecord{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl CrashRecord{
+    pub const fn new() -> Self{
+        Self { magic: 0, len: 0, crc: 0, message: [0; CRASH_MESSAGE_LEN] }
+    }
+
+    /// 保存信息，超长时截断
+    pub fn store(&mut self, message: &str){
+        let len = floor_char_boundary(message, CRASH_MESSAGE_LEN);
+        self.message[..len].copy_from_slice(&message.as_bytes()[..len]);
+        self.len = len as u32;
+        self.crc = Crc32::checksum(&self.message[..len]);
+        self.magic = CRASH_MAGIC;
+    }
+
+    /// 读取并清除，没有保存过(或RAM中是随机数据)时返回None
+    pub fn take(&mut self) -> Option<String>{
+        let valid = self.magic == CRASH_MAGIC
+            && self.len as usize <= CRASH_MESSAGE_LEN
+            && Crc32::checksum(&self.message[..self.len as usize]) == self.crc;
+        self.magic = 0;
+        if !valid{
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.message[..self.len as usize]).into())
+    }
+}
+
+/// 格式化到固定大小的缓冲区，超出部分丢弃。panic时不能分配内存(可能就是内存不足导致的panic)
+pub struct FixedWriter<const N: usize>{
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for FixedWriter<N>{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl<const N: usize> FixedWriter<N>{
+    pub const fn new() -> Self{
+        Self { buf: [0; N], len: 0 }
+    }
+
+    pub fn as_str(&self) -> &str{
+        //只会在字符边界截断
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedWriter<N>{
+    fn write_str(&mut self, s: &str) -> fmt::Result{
+        let len = floor_char_boundary(s, N - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// 按屏幕宽度折行，每行最多columns个字符，遇到换行符也会换行
+pub fn wrap_lines(text: &str, columns: usize) -> impl Iterator<Item = &str>{
+    text.lines().flat_map(move |line| {
+        let mut rest = line;
+        core::iter::from_fn(move || {
+            if rest.is_empty(){
+                return None;
+            }
+            let end = rest.char_indices().nth(columns).map(|(i, _)| i).unwrap_or(rest.len());
+            let (line, remain) = rest.split_at(end);
+            rest = remain;
+            Some(line)
+        })
+    })
+}
+
+//不超过max的最大字符边界
+fn floor_char_boundary(s: &str, max: usize) -> usize{
+    if s.len() <= max{
+        return s.len();
+    }
+    (0..=max).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::fmt::Write;
+
+    #[test]
+    fn record_round_trip() {
+        let mut record = CrashRecord::new();
+        assert_eq!(record.take(), None);
+        record.store("panicked at src/main.rs:249:104");
+        assert_eq!(record.take().as_deref(), Some("panicked at src/main.rs:249:104"));
+        // 读取后清除
+        assert_eq!(record.take(), None);
+
+        // 数据被破坏
+        record.store("out of memory");
+        record.message[0] ^= 1;
+        assert_eq!(record.take(), None);
+    }
+
+    #[test]
+    fn writer_truncates_on_char_boundary() {
+        let mut w = FixedWriter::<8>::new();
+        let code = 12;
+        write!(w, "ab{code}解压失败").unwrap();
+        assert_eq!(w.as_str(), "ab12解");
+        let lines: Vec<_> = wrap_lines("abcdefg\nhi", 3).collect();
+        assert_eq!(lines, ["abc", "def", "g", "hi"]);
+    }
+}
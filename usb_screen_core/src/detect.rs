@@ -0,0 +1,131 @@
+//! 通过MISO读取屏幕控制器的ID，识别控制器型号。
+//!
+//! 启动时在初始化屏幕之前发送RDDID(04h)读取3字节ID：ST7735为 7C 89 F0，ST7789为 85 85 52(第一字节为模块厂商ID，不固定)。
+//! ILI9341的RDDID通常全为0，再用RDID4(D3h)读取，返回 无效字节 00 93 41。
+//! 串口方式多字节读取时控制器先输出一个无效位(dummy clock)，所以RDDID、RDDST多读1字节，去掉第一位后使用。
+//! MISO没有连接时读到全0或全1，固件使用编译时选择的屏幕。
+
+use alloc::format;
+use alloc::string::String;
+
+/// 读取ID和状态的指令
+pub const RDDID: u8 = 0x04;
+pub const RDDST: u8 = 0x09;
+pub const RDID4: u8 = 0xD3;
+
+/// 读取时SPI的最高频率，ST7789读取周期最短150ns
+pub const READ_FREQ: u32 = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Controller{
+    St7735,
+    St7789,
+    Ili9341,
+}
+
+impl Controller{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Controller::St7735 => "ST7735",
+            Controller::St7789 => "ST7789",
+            Controller::Ili9341 => "ILI9341",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection{
+    /// 没有识别出型号时为None
+    pub controller: Option<Controller>,
+    /// RDDID读到的24位ID
+    pub id: u32,
+    /// RDDST读到的32位状态
+    pub status: u32,
+}
+
+/// 去掉多字节读取开头的无效位，data比要读取的字节数多1字节(最多5字节)
+pub fn read_value(data: &[u8]) -> u32{
+    let bits = data.len() as u32 * 8;
+    let value = data.iter().fold(0u64, |value, byte| value << 8 | *byte as u64);
+    (((value << 1) & ((1u64 << bits) - 1)) >> 8) as u32
+}
+
+impl Detection{
+    /// rddid、rddst、rdid4为三个指令读到的原始数据
+    pub fn new(rddid: &[u8; 4], rddst: &[u8; 5], rdid4: &[u8; 4]) -> Self{
+        let id = read_value(rddid);
+        let status = read_value(rddst);
+        let controller = match id & 0xFFFF{
+            0x89F0 => Some(Controller::St7735),
+            0x8552 => Some(Controller::St7789),
+            _ if u32::from_be_bytes(*rdid4) & 0xFFFF == 0x9341 => Some(Controller::Ili9341),
+            _ => None,
+        };
+        Self{ controller, id, status }
+    }
+
+    /// 识别出的型号不在固件支持的型号中：分辨率、引脚和驱动在编译时决定，需要换用对应屏幕的固件
+    pub fn mismatch(&self, supported: &[Controller]) -> bool{
+        self.controller.is_some_and(|controller| !supported.contains(&controller))
+    }
+
+    /// 是否收到了回复，MISO没有连接时读到全0或全1
+    pub fn responded(&self) -> bool{
+        self.controller.is_some() || !matches!((self.id, self.status), (0, 0) | (0xFF_FFFF, 0xFFFF_FFFF))
+    }
+
+    /// 设备信息(ReadInfo)中的屏幕字段：;panel=型号;panel_id=ID;panel_status=状态，型号不在supported中时加上;panel_mismatch=1。
+    /// panel=none表示没有收到回复(MISO没有连接)，使用编译时选择的屏幕
+    pub fn info(&self, supported: &[Controller]) -> String{
+        if !self.responded(){
+            return String::from(";panel=none");
+        }
+        let mut info = format!(
+            ";panel={};panel_id={:06X};panel_status={:08X}",
+            self.controller.map(|c| c.name()).unwrap_or("unknown"),
+            self.id,
+            self.status
+        );
+        if self.mismatch(supported){
+            info.push_str(";panel_mismatch=1");
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //把数据右移1位，模拟开头的无效位
+    fn with_dummy_bit<const N: usize>(value: u64) -> [u8; N]{
+        let bytes = (value << 7).to_be_bytes();
+        core::array::from_fn(|i| bytes[8 - N + i])
+    }
+
+    #[test]
+    fn identifies_controllers() {
+        let st7789 = Detection::new(&with_dummy_bit(0x858552), &with_dummy_bit(0x5361_0000), &[0; 4]);
+        assert_eq!(st7789.controller, Some(Controller::St7789));
+        assert_eq!((st7789.id, st7789.status), (0x858552, 0x5361_0000));
+
+        let st7735 = Detection::new(&with_dummy_bit(0x7C89F0), &[0; 5], &[0; 4]);
+        assert_eq!(st7735.controller, Some(Controller::St7735));
+
+        let ili9341 = Detection::new(&[0; 4], &with_dummy_bit(0x0061_0000), &[0xFF, 0x00, 0x93, 0x41]);
+        assert_eq!(ili9341.controller, Some(Controller::Ili9341));
+
+        let floating = Detection::new(&[0xFF; 4], &[0xFF; 5], &[0xFF; 4]);
+        assert_eq!(floating.controller, None);
+        assert!(!floating.responded());
+        assert!(!Detection::new(&[0; 4], &[0; 5], &[0; 4]).responded());
+
+        // ST7735固件接了ST7789屏幕，没有识别出型号时不算不符
+        assert!(st7789.mismatch(&[Controller::St7735]));
+        assert!(!ili9341.mismatch(&[Controller::St7789, Controller::Ili9341]));
+        assert!(!floating.mismatch(&[Controller::St7735]));
+
+        assert_eq!(floating.info(&[Controller::St7735]), ";panel=none");
+        assert_eq!(st7789.info(&[Controller::St7735]), ";panel=ST7789;panel_id=858552;panel_status=53610000;panel_mismatch=1");
+    }
+}
@@ -0,0 +1,25 @@
+//! 屏幕驱动的绘制接口：固件的三种屏幕驱动和主机上的模拟器都实现它，命令处理(command模块)等通用代码通过它绘制，不需要区分屏幕型号。
+
+use alloc::vec;
+use crate::panel::PanelConfig;
+use crate::scroll::Scroll;
+
+#[allow(async_fn_in_trait)]
+pub trait Screen{
+    /// 在(x,y)位置绘制 width x height 的RGB565像素
+    async fn draw_pixels(&mut self, pixels: &[u16], x: u16, y: u16, width: u16, height: u16);
+
+    /// 设置控制器的硬件滚动区域和偏移(屏幕的x对应显存的行)
+    async fn set_scroll(&mut self, scroll: &Scroll);
+
+    /// 设置反色和Gamma曲线，启动时和收到SetPanel时调用
+    async fn set_panel(&mut self, panel: &PanelConfig);
+
+    /// 用单一颜色填充矩形，逐行绘制，只需要一行的内存
+    async fn fill_rect(&mut self, color: u16, x: u16, y: u16, width: u16, height: u16){
+        let row = vec![color; width as usize];
+        for row_y in y..y+height{
+            self.draw_pixels(&row, x, row_y, width, 1).await;
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! 图像帧(IMAGE_AA ~ IMAGE_BB)的检查和裁剪。
+//!
+//! 头部中的位置和大小来自主机，不能直接交给屏幕驱动：超出屏幕的部分会在屏幕上卷绕，数据长度不符时画出乱码。
+//! 设备把区域裁剪到屏幕内，只绘制可见部分；区域为空、完全在屏幕外或解压后的长度不等于宽x高x2时拒绝这一帧，
+//! 拒绝的次数和原因记录在性能计数器中(DeviceStats::rejected_frames、last_error)。
+//!
+//! 解压时压缩数据和解压后的图像同时在内存中，设备在信息中报告一帧最多占用的内存(frame=，见frame_buffer_size)，
+//! 主机发送压缩效果差的大图像时按stripes分成若干横条，每条单独作为一帧发送。
+//!
+//! ST7789固件还在信息中报告stripe=：解压后不超过这个长度的横条由core0解压，core1同时绘制上一条，
+//! 主机按decode_stripes把大图像分成这样的横条。一帧分成多条后，性能计数器中的帧数由StripeTracker按整帧统计。
+
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError{
+    //宽或高为0
+    Empty = 1,
+    //左上角在屏幕外
+    OffScreen = 2,
+    //解压后的长度不等于宽x高x2
+    SizeMismatch = 3,
+    //压缩数据和解压后的图像超过了设备的帧缓冲
+    TooLarge = 4,
+}
+
+impl FrameError{
+    pub fn from_code(code: u32) -> Option<Self>{
+        match code{
+            1 => Some(FrameError::Empty),
+            2 => Some(FrameError::OffScreen),
+            3 => Some(FrameError::SizeMismatch),
+            4 => Some(FrameError::TooLarge),
+            _ => None,
+        }
+    }
+}
+
+/// 屏幕上的矩形区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect{
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect{
+    pub fn pixels(&self) -> usize{
+        self.width as usize * self.height as usize
+    }
+
+    /// 裁剪到屏幕内，为空或左上角在屏幕外时返回错误
+    pub fn clip(&self, screen_width: u16, screen_height: u16) -> Result<Rect, FrameError>{
+        if self.width == 0 || self.height == 0{
+            return Err(FrameError::Empty);
+        }
+        if self.x >= screen_width || self.y >= screen_height{
+            return Err(FrameError::OffScreen);
+        }
+        Ok(Rect{
+            x: self.x,
+            y: self.y,
+            width: self.width.min(screen_width - self.x),
+            height: self.height.min(screen_height - self.y),
+        })
+    }
+}
+
+//len字节的图像lz4压缩后的最大长度，加上开头的4字节原始长度
+pub(crate) fn max_compressed_len(len: usize) -> usize{
+    len.saturating_add(4 + len / 255 + 16)
+}
+
+/// 一帧最多占用的内存(压缩数据加上解压后的图像)：屏幕大小的图像，加上它的1/8用于压缩数据。
+/// 纯色和界面类的图像压缩后远小于1/8，整屏发送；照片、噪点等压缩效果差的图像需要分成横条
+pub const fn frame_buffer_size(screen_width: u16, screen_height: u16) -> usize{
+    let len = screen_width as usize * screen_height as usize * 2;
+    len + len / 8
+}
+
+/// 开头的原始长度(lz4_flex的prepend size，u32 LE)表示的解压后的长度，数据不足4字节时返回None
+pub fn decompressed_len(compressed: &[u8]) -> Option<usize>{
+    Some(u32::from_le_bytes(compressed.get(..4)?.try_into().ok()?) as usize)
+}
+
+/// 一帧占用的内存：压缩数据和解压后的长度，数据不足4字节时返回None
+pub fn frame_memory(compressed: &[u8]) -> Option<usize>{
+    Some(compressed.len().saturating_add(decompressed_len(compressed)?))
+}
+
+//每条即使完全不能压缩也不超过frame_buffer的行数，最少1行
+fn stripe_rows(rect: &Rect, frame_buffer: usize) -> usize{
+    let row = rect.width as usize * 2;
+    let cost = |rows: usize| rows * row + max_compressed_len(rows * row);
+    let mut rows = (frame_buffer / (row * 2 + 1).max(1)).clamp(1, rect.height.max(1) as usize);
+    while rows > 1 && cost(rows) > frame_buffer{
+        rows -= 1;
+    }
+    rows
+}
+
+/// 把区域分成从上到下的横条，每条即使完全不能压缩也不超过frame_buffer，最少1行
+pub fn stripes(rect: Rect, frame_buffer: usize) -> impl Iterator<Item = Rect>{
+    split_rows(rect, stripe_rows(&rect, frame_buffer))
+}
+
+/// 把区域分成解压后不超过stripe字节(设备信息中的stripe=)的横条，同时不超过frame_buffer，最少1行
+pub fn decode_stripes(rect: Rect, frame_buffer: usize, stripe: usize) -> impl Iterator<Item = Rect>{
+    let rows = (stripe / (rect.width as usize * 2).max(1)).clamp(1, stripe_rows(&rect, frame_buffer));
+    split_rows(rect, rows)
+}
+
+fn split_rows(rect: Rect, rows: usize) -> impl Iterator<Item = Rect>{
+    (0..rect.height).step_by(rows).map(move |top| Rect{
+        x: rect.x,
+        y: rect.y.saturating_add(top),
+        width: rect.width,
+        height: (rect.height - top).min(rows as u16),
+    })
+}
+
+/// 检查解压后的RGB565图像并裁剪到屏幕内：可见部分的行移到开头，image截短为可见部分，返回可见的区域
+pub fn crop_image(image: &mut Vec<u8>, rect: &Rect, screen_width: u16, screen_height: u16) -> Result<Rect, FrameError>{
+    let visible = rect.clip(screen_width, screen_height)?;
+    if image.len() != rect.pixels() * 2{
+        return Err(FrameError::SizeMismatch);
+    }
+    if visible.width != rect.width{
+        let (stride, visible_stride) = (rect.width as usize * 2, visible.width as usize * 2);
+        for row in 1..visible.height as usize{
+            image.copy_within(row * stride..row * stride + visible_stride, row * visible_stride);
+        }
+    }
+    image.truncate(visible.pixels() * 2);
+    Ok(visible)
+}
+
+/// 按整帧统计绘制的帧数：紧接在上一条下面(x和宽度相同)的横条是同一帧的一部分，不算新的一帧。
+/// 正好画在上一次更新下面的局部更新也会被算作同一帧
+#[derive(Debug, Default)]
+pub struct StripeTracker{
+    //上一条下面紧接着的位置
+    next: Option<Rect>,
+}
+
+impl StripeTracker{
+    /// 记录绘制的区域，是新的一帧时返回true
+    pub fn new_frame(&mut self, rect: &Rect) -> bool{
+        let continued = self.next.is_some_and(|next| next.x == rect.x && next.y == rect.y && next.width == rect.width);
+        self.next = Some(Rect{ y: rect.y.saturating_add(rect.height), ..*rect });
+        !continued
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn clip_to_screen() {
+        let rect = |x, y, width, height| Rect { x, y, width, height };
+        assert_eq!(rect(0, 0, 160, 128).clip(160, 128), Ok(rect(0, 0, 160, 128)));
+        assert_eq!(rect(150, 120, 20, 10).clip(160, 128), Ok(rect(150, 120, 10, 8)));
+        assert_eq!(rect(0, 0, u16::MAX, u16::MAX).clip(320, 240), Ok(rect(0, 0, 320, 240)));
+        assert_eq!(rect(159, 127, 1, 1).clip(160, 128), Ok(rect(159, 127, 1, 1)));
+        assert_eq!(rect(160, 0, 1, 1).clip(160, 128), Err(FrameError::OffScreen));
+        assert_eq!(rect(0, 128, 1, 1).clip(160, 128), Err(FrameError::OffScreen));
+        assert_eq!(rect(10, 10, 0, 5).clip(160, 128), Err(FrameError::Empty));
+    }
+
+    #[test]
+    fn crop_keeps_visible_rows() {
+        // 3x2的图像，右边一列在屏幕外
+        let pixels: Vec<u8> = (0..12).collect();
+        let mut image = pixels.clone();
+        let rect = Rect { x: 8, y: 0, width: 3, height: 2 };
+        assert_eq!(crop_image(&mut image, &rect, 10, 10), Ok(Rect { width: 2, ..rect }));
+        assert_eq!(image, [0, 1, 2, 3, 6, 7, 8, 9]);
+
+        // 下面一行在屏幕外
+        let mut image = pixels.clone();
+        let rect = Rect { x: 0, y: 9, width: 3, height: 2 };
+        assert_eq!(crop_image(&mut image, &rect, 10, 10), Ok(Rect { height: 1, ..rect }));
+        assert_eq!(image, pixels[..6]);
+
+        // 长度不符
+        let mut image = vec![0; 10];
+        assert_eq!(crop_image(&mut image, &Rect { x: 0, y: 0, width: 3, height: 2 }, 10, 10), Err(FrameError::SizeMismatch));
+    }
+
+    #[test]
+    fn stripes_fit_frame_buffer() {
+        let rect = Rect { x: 0, y: 10, width: 320, height: 240 };
+        let budget = frame_buffer_size(320, 240);
+        let parts: Vec<Rect> = stripes(rect, budget).collect();
+        assert!(parts.len() > 1);
+        assert_eq!(parts.iter().map(|r| r.height).sum::<u16>(), 240);
+        assert_eq!(parts[1].y, 10 + parts[0].height);
+        for part in &parts{
+            let len = part.pixels() * 2;
+            assert!(len + max_compressed_len(len) <= budget);
+        }
+        // 帧缓冲太小时每行一条
+        assert_eq!(stripes(rect, 100).count(), 240);
+
+        let compressed = [0x58, 0x02, 0x00, 0x00, 1, 2];
+        assert_eq!(frame_memory(&compressed), Some(600 + 6));
+        assert_eq!(frame_memory(&[1, 2]), None);
+    }
+
+    #[test]
+    fn decode_stripes_count_as_one_frame() {
+        let rect = Rect { x: 0, y: 0, width: 320, height: 240 };
+        let parts: Vec<Rect> = decode_stripes(rect, frame_buffer_size(320, 240), 320 * 2 * 40).collect();
+        assert_eq!(parts.len(), 6);
+        assert!(parts.iter().all(|part| part.height == 40));
+        // 帧缓冲更小时按帧缓冲分割
+        assert!(decode_stripes(rect, 320 * 2 * 10, 320 * 2 * 40).all(|part| part.height < 10));
+
+        let mut tracker = StripeTracker::default();
+        let new_frames: Vec<bool> = parts.iter().chain(&parts).map(|part| tracker.new_frame(part)).collect();
+        assert_eq!(new_frames.iter().filter(|new| **new).count(), 2);
+        assert!(new_frames[0] && new_frames[6]);
+        // 不同位置的局部更新各算一帧
+        assert!(tracker.new_frame(&Rect { x: 10, y: 0, width: 20, height: 20 }));
+        assert!(tracker.new_frame(&Rect { x: 10, y: 0, width: 20, height: 20 }));
+    }
+}
@@ -0,0 +1,123 @@
+//! HID传输的分包格式。
+//!
+//! HID报告固定64字节，不足的部分会被补0，所以每个报告的第一个字节为长度，后面最多63字节数据。
+//! 长度的最高位为1时表示这个包还没有结束，接收方把后续报告的数据拼接起来，直到最高位为0的报告。
+//! 拼接后的包和USB Raw/串口收到的包相同：命令包(最多64字节)需要拆成两个报告，图像等数据每63字节一个包。
+
+use alloc::vec::Vec;
+
+use crate::protocol::PACKET_SIZE;
+
+pub const HID_REPORT_SIZE: usize = 64;
+//每个报告最多携带的数据
+pub const HID_PAYLOAD_SIZE: usize = HID_REPORT_SIZE - 1;
+//长度字节的最高位：后面还有数据
+pub const HID_CONTINUE: u8 = 0x80;
+
+/// 把一个包拆成若干个HID报告，空包也会生成一个报告
+pub fn encode_reports(packet: &[u8]) -> Vec<[u8; HID_REPORT_SIZE]>{
+    let mut reports = Vec::with_capacity(packet.len() / HID_PAYLOAD_SIZE + 1);
+    let mut chunks = packet.chunks(HID_PAYLOAD_SIZE).peekable();
+    if chunks.peek().is_none(){
+        reports.push([0; HID_REPORT_SIZE]);
+    }
+    while let Some(chunk) = chunks.next(){
+        let mut report = [0; HID_REPORT_SIZE];
+        report[0] = chunk.len() as u8;
+        if chunks.peek().is_some(){
+            report[0] |= HID_CONTINUE;
+        }
+        report[1..1 + chunk.len()].copy_from_slice(chunk);
+        reports.push(report);
+    }
+    reports
+}
+
+/// 把收到的HID报告拼接成包
+pub struct ReportAssembler{
+    buf: Vec<u8>,
+    //最大包长度，超过的包丢弃
+    max_len: usize,
+    //当前包超长，丢弃到包结束
+    overflow: bool,
+    //上一个包已经返回，收到新的报告时清空
+    complete: bool,
+}
+
+impl Default for ReportAssembler{
+    fn default() -> Self{
+        Self::new(PACKET_SIZE)
+    }
+}
+
+impl ReportAssembler{
+    pub fn new(max_len: usize) -> Self{
+        Self { buf: Vec::with_capacity(max_len), max_len, overflow: false, complete: false }
+    }
+
+    /// 放入一个报告，包结束时返回完整的包
+    pub fn push(&mut self, report: &[u8]) -> Option<&[u8]>{
+        if core::mem::take(&mut self.complete){
+            self.buf.clear();
+        }
+        let header = *report.first()?;
+        let len = (header & !HID_CONTINUE) as usize;
+        let data = report.get(1..1 + len)?;
+        if self.overflow || self.buf.len() + len > self.max_len{
+            self.buf.clear();
+            self.overflow = true;
+        }else{
+            self.buf.extend_from_slice(data);
+        }
+        if header & HID_CONTINUE != 0{
+            return None;
+        }
+        self.complete = true;
+        if core::mem::take(&mut self.overflow){
+            return None;
+        }
+        Some(&self.buf)
+    }
+
+    /// 放弃正在拼接的包(例如USB断开)
+    pub fn reset(&mut self){
+        self.buf.clear();
+        self.overflow = false;
+        self.complete = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_round_trip() {
+        let packet: Vec<u8> = (0..PACKET_SIZE as u8).collect();
+        let reports = encode_reports(&packet);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0][0], HID_CONTINUE | 63);
+        assert_eq!(reports[1][0], 1);
+
+        let mut assembler = ReportAssembler::default();
+        assert_eq!(assembler.push(&reports[0]), None);
+        assert_eq!(assembler.push(&reports[1]), Some(&packet[..]));
+
+        // 短包和空包
+        assert_eq!(assembler.push(&encode_reports(&[1, 2, 3])[0]), Some(&[1u8, 2, 3][..]));
+        assert_eq!(encode_reports(&[]).len(), 1);
+        assert_eq!(assembler.push(&encode_reports(&[])[0]), Some(&[][..]));
+    }
+
+    #[test]
+    fn oversized_packet_is_dropped() {
+        let mut assembler = ReportAssembler::new(PACKET_SIZE);
+        let reports = encode_reports(&[7; 200]);
+        assert!(reports.iter().all(|r| assembler.push(r).is_none()));
+        // 丢弃后可以继续接收
+        assert_eq!(assembler.push(&encode_reports(&[9])[0]), Some(&[9u8][..]));
+        // 长度字节超出报告
+        assembler.reset();
+        assert_eq!(assembler.push(&[63, 1, 2]), None);
+    }
+}
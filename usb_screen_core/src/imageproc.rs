@@ -0,0 +1,754 @@
+//! Geometric transformations of images. This includes rotations, translation, and general
+//! projective transformations.
+
+use core::ops::Mul;
+use fixed::types::I16F16;
+// 主机上运行测试时会使用std中f32的方法
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use crate::rgb565::{Rgb565Image, Rgb565Pixel};
+
+#[derive(Copy, Clone, Debug)]
+enum TransformationClass {
+    Translation,
+    Affine,
+    Projection,
+}
+
+/// A 2d projective transformation, stored as a row major 3x3 matrix.
+///
+/// Transformations combine by pre-multiplication, i.e. applying `P * Q` is equivalent to
+/// applying `Q` and then applying `P`. For example, the following defines a rotation
+/// about the point (320.0, 240.0).
+///
+/// ```
+/// use usb_screen_core::imageproc::*;
+/// use core::f32::consts::PI;
+///
+/// let (cx, cy) = (320.0, 240.0);
+///
+/// let c_rotation = Projection::translate(cx, cy)
+///     * Projection::rotate(PI / 6.0)
+///     * Projection::translate(-cx, -cy);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Projection {
+    transform: [f32; 9],
+    inverse: [f32; 9],
+    class: TransformationClass,
+}
+
+impl Projection {
+    /// Creates a 2d projective transform from a row-major 3x3 matrix in homogeneous coordinates.
+    ///
+    /// Returns `None` if the matrix is not invertible, if its bottom-right entry is zero
+    /// (it cannot be normalized) or if any entry of it or its inverse is not finite.
+    pub fn from_matrix(transform: [f32; 9]) -> Option<Projection> {
+        if transform[8] == 0.0 || !transform.iter().all(|t| t.is_finite()) {
+            return None;
+        }
+        let transform = normalize(transform);
+        if !transform.iter().all(|t| t.is_finite()) {
+            return None;
+        }
+        let class = class_from_matrix(transform);
+        try_inverse(&transform)
+            .filter(|inverse| inverse.iter().all(|t| t.is_finite()))
+            .map(|inverse| Projection {
+                transform,
+                inverse,
+                class,
+            })
+    }
+
+    /// Combine the transformation with another one. The resulting transformation is equivalent to
+    /// applying this transformation followed by the `other` transformation.
+    pub fn and_then(self, other: Projection) -> Projection {
+        other * self
+    }
+
+    /// A translation by (tx, ty).
+    #[rustfmt::skip]
+    pub fn translate(tx: f32, ty: f32) -> Projection {
+        Projection {
+            transform: [
+                1.0, 0.0, tx,
+                0.0, 1.0, ty,
+                0.0, 0.0, 1.0
+            ],
+            inverse: [
+                1.0, 0.0, -tx,
+                0.0, 1.0, -ty,
+                0.0, 0.0, 1.0
+            ],
+            class: TransformationClass::Translation,
+        }
+    }
+
+    /// A clockwise rotation around the top-left corner of the image by theta radians.
+    #[rustfmt::skip]
+    pub fn rotate(theta: f32) -> Projection {
+        let (s, c) = theta.sin_cos();
+        Projection {
+            transform: [
+                  c,  -s, 0.0,
+                  s,   c, 0.0,
+                0.0, 0.0, 1.0
+            ],
+            inverse: [
+                  c,   s, 0.0,
+                 -s,   c, 0.0,
+                0.0, 0.0, 1.0
+            ],
+            class: TransformationClass::Affine,
+        }
+    }
+
+    /// An anisotropic scaling (sx, sy).
+    ///
+    /// Note that the `warp` function does not change the size of the input image.
+    /// If you want to resize an image then use the `resize` module of the firmware.
+    #[rustfmt::skip]
+    pub fn scale(sx: f32, sy: f32) -> Projection {
+        Projection {
+            transform: [
+                 sx, 0.0, 0.0,
+                0.0,  sy, 0.0,
+                0.0, 0.0, 1.0
+            ],
+            inverse: [
+                1.0 / sx, 0.0,      0.0,
+                0.0,      1.0 / sy, 0.0,
+                0.0,      0.0,      1.0
+            ],
+            class: TransformationClass::Affine,
+        }
+    }
+
+    /// Returns the row-major 3x3 matrix of the transformation.
+    pub fn matrix(&self) -> [f32; 9] {
+        self.transform
+    }
+
+    /// Inverts the transformation.
+    pub fn invert(self) -> Projection {
+        Projection {
+            transform: self.inverse,
+            inverse: self.transform,
+            class: self.class,
+        }
+    }
+
+    // Helper functions used as optimization in warp.
+    #[inline(always)]
+    fn map_projective(&self, x: f32, y: f32) -> (f32, f32) {
+        let t = &self.transform;
+        let d = t[6] * x + t[7] * y + t[8];
+        (
+            (t[0] * x + t[1] * y + t[2]) / d,
+            (t[3] * x + t[4] * y + t[5]) / d,
+        )
+    }
+
+    #[inline(always)]
+    fn map_affine(&self, x: f32, y: f32) -> (f32, f32) {
+        let t = &self.transform;
+        ((t[0] * x + t[1] * y + t[2]), (t[3] * x + t[4] * y + t[5]))
+    }
+
+    #[inline(always)]
+    fn map_translation(&self, x: f32, y: f32) -> (f32, f32) {
+        let t = &self.transform;
+        let tx = t[2];
+        let ty = t[5];
+        (x + tx, y + ty)
+    }
+}
+
+impl Mul<Projection> for Projection {
+    type Output = Projection;
+
+    fn mul(self, rhs: Projection) -> Projection {
+        use TransformationClass as TC;
+        let t = mul3x3(self.transform, rhs.transform);
+        let i = mul3x3(rhs.inverse, self.inverse);
+
+        let class = match (self.class, rhs.class) {
+            (TC::Translation, TC::Translation) => TC::Translation,
+            (TC::Translation, TC::Affine) => TC::Affine,
+            (TC::Affine, TC::Translation) => TC::Affine,
+            (TC::Affine, TC::Affine) => TC::Affine,
+            (_, _) => TC::Projection,
+        };
+
+        Projection {
+            transform: t,
+            inverse: i,
+            class,
+        }
+    }
+}
+
+impl Mul<&Projection> for &Projection {
+    type Output = Projection;
+
+    fn mul(self, rhs: &Projection) -> Projection {
+        *self * *rhs
+    }
+}
+
+impl Mul<(f32, f32)> for Projection {
+    type Output = (f32, f32);
+
+    fn mul(self, rhs: (f32, f32)) -> (f32, f32) {
+        let (x, y) = rhs;
+        match self.class {
+            TransformationClass::Translation => self.map_translation(x, y),
+            TransformationClass::Affine => self.map_affine(x, y),
+            TransformationClass::Projection => self.map_projective(x, y),
+        }
+    }
+}
+
+impl Mul<&(f32, f32)> for &Projection {
+    type Output = (f32, f32);
+
+    fn mul(self, rhs: &(f32, f32)) -> (f32, f32) {
+        *self * *rhs
+    }
+}
+
+/// Rotates an image clockwise about its center.
+/// The output image has the same dimensions as the input. Output pixels
+/// whose pre-image lies outside the input image are set to `default`.
+pub fn rotate_about_center<'a>(
+    image: &Rgb565Image<'a>,
+    theta: f32,
+    interpolation: Interpolation,
+    output: &mut Rgb565Image,
+    default: Rgb565Pixel,
+)
+{
+    let (w, h) = (image.width, image.height);
+    rotate(
+        image,
+        (w as f32 / 2.0, h as f32 / 2.0),
+        theta,
+        interpolation,
+        output,
+        default,
+    )
+}
+
+/// Rotates an image clockwise about the provided center by theta radians.
+/// The output image has the same dimensions as the input. Output pixels
+/// whose pre-image lies outside the input image are set to `default`.
+pub fn rotate<'a>(
+    image: &Rgb565Image<'a>,
+    center: (f32, f32),
+    theta: f32,
+    interpolation: Interpolation,
+    output: &mut Rgb565Image,
+    default: Rgb565Pixel,
+)
+{
+    let (cx, cy) = center;
+    let projection =
+        Projection::translate(cx, cy) * Projection::rotate(theta) * Projection::translate(-cx, -cy);
+    warp(image, &projection, interpolation, default,  output)
+}
+
+/// Applies a projective transformation to an image.
+///
+/// The output image may have different dimensions than `image`. Output pixels
+/// whose pre-image lies outside the input image are set to `default`.
+///
+/// The provided projection defines a mapping from locations in the input image to their
+/// corresponding location in the output image.
+pub fn warp<'a>(
+    image: &Rgb565Image<'a>,
+    projection: &Projection,
+    interpolation: Interpolation,
+    default: Rgb565Pixel,
+    output: &mut Rgb565Image,
+)
+{
+    warp_into(image, projection, interpolation, default, output);
+}
+
+/// Applies a projective transformation to an image, writing to a provided output.
+///
+/// See the [`warp`](fn.warp.html) documentation for more information.
+pub fn warp_into<'a>(
+    image: &Rgb565Image<'a>,
+    projection: &Projection,
+    interpolation: Interpolation,
+    default: Rgb565Pixel,
+    out: &mut Rgb565Image
+)
+{
+    let projection = projection.invert();
+    let nn = |x, y| interpolate_nearest(image, x, y, default);
+    let nn_interior = |x, y| interpolate_nearest_interior(image, x, y);
+    let bl = |x, y| interpolate_bilinear(image, x, y, default);
+    let bl_interior = |x, y| interpolate_bilinear_interior(image, x, y);
+    let wp = |x, y| projection.map_projective(x, y);
+    let wa = |x, y| projection.map_affine(x, y);
+    let wt = |x, y| projection.map_translation(x, y);
+    use Interpolation as I;
+    use TransformationClass as TC;
+
+    // Pre-images below these bounds can be sampled without looking outside the input image.
+    let (width, height) = (image.width as f32, image.height as f32);
+    let nn_max = (width - 0.5, height - 0.5);
+    let bl_max = (width - 1.0, height - 1.0);
+    let t = &projection.transform;
+
+    match (interpolation, projection.class) {
+        (I::Nearest, TC::Translation) => warp_affine_inner(out, wt, t, nn_max, nn, nn_interior),
+        (I::Nearest, TC::Affine) => warp_affine_inner(out, wa, t, nn_max, nn, nn_interior),
+        (I::Nearest, TC::Projection) => warp_inner(out, wp, nn),
+        (I::Bilinear, TC::Translation) => warp_affine_inner(out, wt, t, bl_max, bl, bl_interior),
+        (I::Bilinear, TC::Affine) => warp_affine_inner(out, wa, t, bl_max, bl, bl_interior),
+        (I::Bilinear, TC::Projection) => warp_inner(out, wp, bl),
+    }
+}
+
+// Affine warps map every output row onto a straight line in the input image, so the run of
+// output pixels whose pre-image lies inside `[0, max)` is contiguous. It is computed once per
+// row and sampled with `interior`, which skips the boundary checks done by `border`.
+fn warp_affine_inner<Fc, Fb, Fi>(
+    out: &mut Rgb565Image,
+    mapping: Fc,
+    transform: &[f32; 9],
+    max: (f32, f32),
+    border: Fb,
+    interior: Fi,
+)
+where
+    Fc: Fn(f32, f32) -> (f32, f32),
+    Fb: Fn(f32, f32) -> u16,
+    Fi: Fn(f32, f32) -> u16,
+{
+    let width = out.width as usize;
+    let (max_x, max_y) = max;
+    let inside = |(px, py): (f32, f32)| px >= 0.0 && py >= 0.0 && px < max_x && py < max_y;
+
+    out.pixels.chunks_mut(width).enumerate().for_each(|(y, row)| {
+        let y = y as f32;
+        let (x0, y0) = mapping(0.0, y);
+        let (sx, ex) = interior_span(x0, transform[0], max_x, row.len());
+        let (sy, ey) = interior_span(y0, transform[3], max_y, row.len());
+        let (mut start, mut end) = (sx.max(sy), ex.min(ey).max(sx.max(sy)));
+        // The span is only an estimate because of rounding, shrink it until both ends are
+        // inside. The interior is convex, so every pixel in between is inside as well.
+        while start < end && !inside(mapping(start as f32, y)) {
+            start += 1;
+        }
+        while end > start && !inside(mapping((end - 1) as f32, y)) {
+            end -= 1;
+        }
+
+        let (head, rest) = row.split_at_mut(start);
+        let (body, tail) = rest.split_at_mut(end - start);
+        for (x, p) in head.iter_mut().enumerate() {
+            let (px, py) = mapping(x as f32, y);
+            *p = border(px, py);
+        }
+        for (x, p) in body.iter_mut().enumerate() {
+            let (px, py) = mapping((start + x) as f32, y);
+            *p = interior(px, py);
+        }
+        for (x, p) in tail.iter_mut().enumerate() {
+            let (px, py) = mapping((end + x) as f32, y);
+            *p = border(px, py);
+        }
+    });
+}
+
+// Range of x in `[0, len)` for which `v0 + dv * x` lies in `[0, max)`.
+fn interior_span(v0: f32, dv: f32, max: f32, len: usize) -> (usize, usize) {
+    if dv.abs() < 1e-10 {
+        return if v0 >= 0.0 && v0 < max { (0, len) } else { (0, 0) };
+    }
+    let (a, b) = ((0.0 - v0) / dv, (max - v0) / dv);
+    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+    let clamp = |v: f32| v.max(0.0).min(len as f32) as usize;
+    (clamp(lo.ceil()), clamp(hi.ceil()))
+}
+
+// Work horse of all warp functions
+fn warp_inner<Fc, Fi>(out: &mut Rgb565Image, mapping: Fc, get_pixel: Fi)
+where
+    Fc: Fn(f32, f32) -> (f32, f32) + Send + Sync,
+    Fi: Fn(f32, f32) -> u16,
+{
+    let width = out.width;
+
+    out.pixels.chunks_mut(width as usize).enumerate().for_each(|(y, row)| {
+        for (x, p) in row.iter_mut().enumerate() {
+            let (px, py) = mapping(x as f32, y as f32);
+            *p = get_pixel(px, py);
+        }
+    });
+}
+
+// Classifies transformation by looking up transformation matrix coefficients
+fn class_from_matrix(mx: [f32; 9]) -> TransformationClass {
+    if (mx[6] - 0.0).abs() < 1e-10 && (mx[7] - 0.0).abs() < 1e-10 && (mx[8] - 1.0).abs() < 1e-10 {
+        if (mx[0] - 1.0).abs() < 1e-10
+            && (mx[1] - 0.0).abs() < 1e-10
+            && (mx[3] - 0.0).abs() < 1e-10
+            && (mx[4] - 1.0).abs() < 1e-10
+        {
+            TransformationClass::Translation
+        } else {
+            TransformationClass::Affine
+        }
+    } else {
+        TransformationClass::Projection
+    }
+}
+
+fn normalize(mx: [f32; 9]) -> [f32; 9] {
+    [
+        mx[0] / mx[8],
+        mx[1] / mx[8],
+        mx[2] / mx[8],
+        mx[3] / mx[8],
+        mx[4] / mx[8],
+        mx[5] / mx[8],
+        mx[6] / mx[8],
+        mx[7] / mx[8],
+        1.0,
+    ]
+}
+
+// TODO: write me in f64
+fn try_inverse(t: &[f32; 9]) -> Option<[f32; 9]> {
+    let [t00, t01, t02, t10, t11, t12, t20, t21, t22] = t;
+
+    let m00 = t11 * t22 - t12 * t21;
+    let m01 = t10 * t22 - t12 * t20;
+    let m02 = t10 * t21 - t11 * t20;
+
+    let det = t00 * m00 - t01 * m01 + t02 * m02;
+
+    if det.abs() < 1e-10 {
+        return None;
+    }
+
+    let m10 = t01 * t22 - t02 * t21;
+    let m11 = t00 * t22 - t02 * t20;
+    let m12 = t00 * t21 - t01 * t20;
+    let m20 = t01 * t12 - t02 * t11;
+    let m21 = t00 * t12 - t02 * t10;
+    let m22 = t00 * t11 - t01 * t10;
+
+    #[rustfmt::skip]
+    let inv = [
+         m00 / det, -m10 / det,  m20 / det,
+        -m01 / det,  m11 / det, -m21 / det,
+         m02 / det, -m12 / det,  m22 / det,
+    ];
+
+    Some(normalize(inv))
+}
+
+fn mul3x3(a: [f32; 9], b: [f32; 9]) -> [f32; 9] {
+    let [a00, a01, a02, a10, a11, a12, a20, a21, a22] = a;
+    let [b00, b01, b02, b10, b11, b12, b20, b21, b22] = b;
+    [
+        a00 * b00 + a01 * b10 + a02 * b20,
+        a00 * b01 + a01 * b11 + a02 * b21,
+        a00 * b02 + a01 * b12 + a02 * b22,
+        a10 * b00 + a11 * b10 + a12 * b20,
+        a10 * b01 + a11 * b11 + a12 * b21,
+        a10 * b02 + a11 * b12 + a12 * b22,
+        a20 * b00 + a21 * b10 + a22 * b20,
+        a20 * b01 + a21 * b11 + a22 * b21,
+        a20 * b02 + a21 * b12 + a22 * b22,
+    ]
+}
+
+
+#[inline(always)]
+fn interpolate_nearest<'a>(image: &Rgb565Image<'a>, x: f32, y: f32, default: Rgb565Pixel) -> u16 {
+    if x < -0.5 || y < -0.5 {
+        return default.0;
+    }
+
+    let (width, height) = (image.width as u32, image.height as u32);
+
+    let rx = (x + 0.5) as u32;
+    let ry = (y + 0.5) as u32;
+
+    if rx >= width || ry >= height {
+        default.0
+    } else {
+        image.get_pixel(rx, ry).0
+    }
+}
+
+// Nearest neighbour for pre-images known to lie inside the image.
+#[inline(always)]
+fn interpolate_nearest_interior(image: &Rgb565Image, x: f32, y: f32) -> u16 {
+    image.pixels[(y + 0.5) as usize * image.width as usize + (x + 0.5) as usize]
+}
+
+// Splits a pixel into its red, green and blue channels.
+#[inline(always)]
+fn channels(p: u16) -> [I16F16; 3] {
+    [
+        I16F16::from_num((p >> 11) & 0x1F),
+        I16F16::from_num((p >> 5) & 0x3F),
+        I16F16::from_num(p & 0x1F),
+    ]
+}
+
+// Blends four neighbouring pixels channel by channel, `fx` and `fy` are the weights of the
+// right and bottom pixels.
+#[inline(always)]
+fn blend(top_left: u16, top_right: u16, bottom_left: u16, bottom_right: u16, fx: I16F16, fy: I16F16) -> u16 {
+    let (tl, tr) = (channels(top_left), channels(top_right));
+    let (bl, br) = (channels(bottom_left), channels(bottom_right));
+    let mut c = [0u16; 3];
+    for i in 0..3 {
+        let top = tl[i] + (tr[i] - tl[i]) * fx;
+        let bottom = bl[i] + (br[i] - bl[i]) * fx;
+        c[i] = (top + (bottom - top) * fy).round().to_num::<u16>();
+    }
+    (c[0] << 11) | (c[1] << 5) | c[2]
+}
+
+#[inline(always)]
+fn interpolate_bilinear(image: &Rgb565Image, x: f32, y: f32, default: Rgb565Pixel) -> u16 {
+    let (width, height) = (image.width as i32, image.height as i32);
+    if x <= -1.0 || y <= -1.0 || x >= width as f32 || y >= height as f32 {
+        return default.0;
+    }
+
+    let (left, top) = (x.floor(), y.floor());
+    let fx = I16F16::from_num(x - left);
+    let fy = I16F16::from_num(y - top);
+    let (left, top) = (left as i32, top as i32);
+
+    // Neighbours outside the image take the default color, which blends the edges smoothly.
+    let get = |px: i32, py: i32| {
+        if px >= 0 && py >= 0 && px < width && py < height {
+            image.get_pixel(px as u32, py as u32).0
+        } else {
+            default.0
+        }
+    };
+
+    blend(get(left, top), get(left + 1, top), get(left, top + 1), get(left + 1, top + 1), fx, fy)
+}
+
+// Bilinear interpolation for pre-images whose four neighbours are all inside the image.
+#[inline(always)]
+fn interpolate_bilinear_interior(image: &Rgb565Image, x: f32, y: f32) -> u16 {
+    let (left, top) = (x as usize, y as usize);
+    let fx = I16F16::from_num(x - left as f32);
+    let fy = I16F16::from_num(y - top as f32);
+    let width = image.width as usize;
+    let i = top * width + left;
+    let p = &image.pixels;
+    blend(p[i], p[i + 1], p[i + width], p[i + width + 1], fx, fy)
+}
+
+/// How to handle pixels whose pre-image lies between input pixels.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Interpolation {
+    /// Choose the nearest pixel to the pre-image of the
+    /// output pixel.
+    Nearest,
+    /// Bilinearly interpolate between the four pixels
+    /// closest to the pre-image of the output pixel.
+    /// Neighbours outside the input image are treated as
+    /// `default`, so the edges of the warped image are smooth.
+    Bilinear,
+}
+
+pub struct Canvas<'a>{
+    pub buf: &'a mut [u16],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl <'a> Canvas<'a>{
+    pub fn clear(&mut self, color: u16){
+        self.buf.fill(color);
+    }
+    
+    pub fn draw_image_at(&mut self, x: usize, y: usize, image:&[u16], image_width: usize){
+        let mut begin = y*self.width + x;
+        for row in image.chunks(image_width){
+            let mut end = begin+image_width;
+            if end > self.buf.len(){
+                end = self.buf.len();                
+            }
+            let len = end - begin;
+            self.buf[begin..end].copy_from_slice(&row[0..len]);
+            begin += self.width;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    const LIT: u16 = 0xFFFF;
+    const DEFAULT: Rgb565Pixel = Rgb565Pixel(0x0001);
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn projection_composition_and_inverse() {
+        let p = Projection::translate(5.0, -3.0) * Projection::rotate(PI / 2.0);
+        // 先旋转(顺时针90度)再平移
+        assert!(close(p * (1.0, 0.0), (5.0, -2.0)));
+        assert!(close(p.invert() * (p * (7.0, 2.5)), (7.0, 2.5)));
+        assert!(close(
+            Projection::rotate(PI / 2.0).and_then(Projection::translate(5.0, -3.0)) * (1.0, 0.0),
+            (5.0, -2.0)
+        ));
+
+        let m = Projection::from_matrix([2.0, 0.0, 1.0, 0.0, 3.0, 2.0, 0.0, 0.0, 1.0]).unwrap();
+        assert!(close(m * (1.0, 1.0), (3.0, 5.0)));
+        assert!(Projection::from_matrix([1.0, 2.0, 0.0, 2.0, 4.0, 0.0, 0.0, 0.0, 1.0]).is_none());
+        // m[8]为0时不能归一化，NaN和无穷大不能用于变换
+        assert!(Projection::from_matrix([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]).is_none());
+        assert!(Projection::from_matrix([f32::NAN, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]).is_none());
+        assert!(Projection::from_matrix([1.0, 0.0, f32::INFINITY, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]).is_none());
+        assert!(Projection::from_matrix([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1e-40]).is_none());
+    }
+
+    #[test]
+    fn warp_translation_moves_pixels() {
+        let mut src = [0u16; 16];
+        src[4 + 1] = LIT;
+        let image = Rgb565Image::new(&mut src, 4, 4);
+        let mut dst = [0u16; 16];
+        let mut out = Rgb565Image::new(&mut dst, 4, 4);
+
+        warp(&image, &Projection::translate(2.0, 1.0), Interpolation::Nearest, DEFAULT, &mut out);
+
+        assert_eq!(dst[2 * 4 + 3], LIT);
+        assert_eq!(dst.iter().filter(|p| **p == LIT).count(), 1);
+        // 左上角两列一行没有对应的源像素
+        assert_eq!(dst[0], DEFAULT.0);
+        assert_eq!(dst[4 + 1], DEFAULT.0);
+    }
+
+    // Deterministic pseudo random image.
+    fn noise(len: usize) -> alloc::vec::Vec<u16> {
+        let mut seed = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 16) as u16
+            })
+            .collect()
+    }
+
+    fn split(p: u16) -> [f32; 3] {
+        [((p >> 11) & 0x1F) as f32, ((p >> 5) & 0x3F) as f32, (p & 0x1F) as f32]
+    }
+
+    // Float reference of the bilinear sampler, with the same edge semantics.
+    fn bilinear_reference(pixels: &[u16], width: i32, height: i32, x: f32, y: f32, default: u16) -> [f32; 3] {
+        let get = |px: i32, py: i32| {
+            if px >= 0 && py >= 0 && px < width && py < height {
+                split(pixels[(py * width + px) as usize])
+            } else {
+                split(default)
+            }
+        };
+        if x <= -1.0 || y <= -1.0 || x >= width as f32 || y >= height as f32 {
+            return split(default);
+        }
+        let (left, top) = (x.floor(), y.floor());
+        let (fx, fy) = (x - left, y - top);
+        let (left, top) = (left as i32, top as i32);
+        let (tl, tr, bl, br) = (get(left, top), get(left + 1, top), get(left, top + 1), get(left + 1, top + 1));
+        let mut c = [0f32; 3];
+        for i in 0..3 {
+            c[i] = tl[i] * (1.0 - fx) * (1.0 - fy) + tr[i] * fx * (1.0 - fy) + bl[i] * (1.0 - fx) * fy + br[i] * fx * fy;
+        }
+        c
+    }
+
+    #[test]
+    fn bilinear_matches_float_reference() {
+        let (w, h) = (23u16, 17u16);
+        let mut src = noise(w as usize * h as usize);
+        let reference = src.clone();
+        let image = Rgb565Image::new(&mut src, w, h);
+        let (ow, oh) = (31u16, 29u16);
+        let mut dst = alloc::vec![0u16; ow as usize * oh as usize];
+        let mut out = Rgb565Image::new(&mut dst, ow, oh);
+        let projection = Projection::translate(15.0, 14.0)
+            * Projection::rotate(0.3)
+            * Projection::scale(1.3, 0.8)
+            * Projection::translate(-11.5, -8.5);
+
+        warp(&image, &projection, Interpolation::Bilinear, DEFAULT, &mut out);
+
+        let inverse = projection.invert();
+        for y in 0..oh as usize {
+            for x in 0..ow as usize {
+                let (px, py) = inverse * (x as f32, y as f32);
+                let expected = bilinear_reference(&reference, w as i32, h as i32, px, py, DEFAULT.0);
+                let actual = split(dst[y * ow as usize + x]);
+                for c in 0..3 {
+                    assert!((actual[c] - expected[c]).abs() <= 1.0, "({x}, {y}) channel {c}: {actual:?} vs {expected:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interior_fast_path_matches_checked_sampling() {
+        let (w, h) = (19u16, 13u16);
+        let mut src = noise(w as usize * h as usize);
+        let image = Rgb565Image::new(&mut src, w, h);
+        let projections = [
+            Projection::translate(3.25, -2.5),
+            Projection::translate(12.0, 10.0) * Projection::rotate(2.0) * Projection::translate(-9.5, -6.5),
+            Projection::scale(1.7, 1.7) * Projection::rotate(-0.7),
+        ];
+        for projection in projections {
+            let inverse = projection.invert();
+            for interpolation in [Interpolation::Nearest, Interpolation::Bilinear] {
+                let mut fast = [0u16; 24 * 20];
+                let mut checked = [0u16; 24 * 20];
+                warp(&image, &projection, interpolation, DEFAULT, &mut Rgb565Image::new(&mut fast, 24, 20));
+                let sample = |x, y| match interpolation {
+                    Interpolation::Nearest => interpolate_nearest(&image, x, y, DEFAULT),
+                    Interpolation::Bilinear => interpolate_bilinear(&image, x, y, DEFAULT),
+                };
+                warp_inner(&mut Rgb565Image::new(&mut checked, 24, 20), |x, y| inverse.map_affine(x, y), sample);
+                assert_eq!(fast, checked);
+            }
+        }
+    }
+
+    #[test]
+    fn rotate_about_center_quarter_turn() {
+        let mut src = [0u16; 16];
+        src[4 + 3] = LIT;
+        let image = Rgb565Image::new(&mut src, 4, 4);
+        let mut dst = [0u16; 16];
+        let mut out = Rgb565Image::new(&mut dst, 4, 4);
+
+        rotate_about_center(&image, PI / 2.0, Interpolation::Nearest, &mut out, DEFAULT);
+
+        assert_eq!(dst[3 * 4 + 3], LIT);
+        assert_eq!(dst.iter().filter(|p| **p == LIT).count(), 1);
+    }
+}
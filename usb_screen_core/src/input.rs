@@ -0,0 +1,268 @@
+//! 按键和旋转编码器。
+//!
+//! 引脚和按键码保存在设置中(SetConfg键9~15)，重启后生效:
+//!
+//! - 键9~12: 按键1~4，值为 引脚+1(低8位，0表示不使用) | HID用法码(8~23位) | 类型(24~31位)
+//! - 键13: 编码器，值为 A相引脚+1(低8位) | B相引脚+1(8~15位)
+//! - 键14、15: 编码器顺时针、逆时针转动一格时发送的按键，格式同按键的高24位
+//!
+//! 类型: 0 只发送事件 / 1 HID键盘(用法码为键盘码) / 2 HID多媒体键(用法码为Consumer Page用法，例如0xE9音量+)。
+//! 不管类型是什么，开启事件后都会通过USB Raw的IN端点发送InputEvent。
+
+use crate::protocol::{magic_number, INPUT_EVENT, MAGIC_NUM_LEN};
+
+pub const MAX_BUTTONS: usize = 4;
+//设置中占用的键数: 4个按键 + 编码器引脚 + 2个编码器按键
+pub const INPUT_CONFIG_KEYS: usize = MAX_BUTTONS + 3;
+
+//按键稳定多少次采样后才认为状态改变
+pub const DEBOUNCE_SAMPLES: u8 = 4;
+
+/// 按键发送的HID按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyUsage{
+    //只发送事件
+    None,
+    Keyboard(u8),
+    Consumer(u16),
+}
+
+impl KeyUsage{
+    /// 解析设置值的高24位
+    pub fn from_bits(value: u32) -> Self{
+        let usage = (value >> 8) as u16;
+        match value >> 24{
+            1 => KeyUsage::Keyboard(usage as u8),
+            2 => KeyUsage::Consumer(usage),
+            _ => KeyUsage::None,
+        }
+    }
+
+    pub fn to_bits(&self) -> u32{
+        match *self{
+            KeyUsage::None => 0,
+            KeyUsage::Keyboard(code) => 1 << 24 | (code as u32) << 8,
+            KeyUsage::Consumer(usage) => 2 << 24 | (usage as u32) << 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonConfig{
+    pub pin: u8,
+    pub usage: KeyUsage,
+}
+
+impl ButtonConfig{
+    pub fn from_bits(value: u32) -> Option<Self>{
+        let pin = (value & 0xFF) as u8;
+        if pin == 0{
+            return None;
+        }
+        Some(Self { pin: pin - 1, usage: KeyUsage::from_bits(value) })
+    }
+
+    pub fn to_bits(&self) -> u32{
+        self.usage.to_bits() | (self.pin as u32 + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderConfig{
+    pub pin_a: u8,
+    pub pin_b: u8,
+    //顺时针、逆时针转动一格发送的按键
+    pub clockwise: KeyUsage,
+    pub counter_clockwise: KeyUsage,
+}
+
+/// 从设置中读取的全部输入配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputConfig{
+    pub buttons: [Option<ButtonConfig>; MAX_BUTTONS],
+    pub encoder: Option<EncoderConfig>,
+}
+
+impl InputConfig{
+    pub fn from_bits(values: &[u32; INPUT_CONFIG_KEYS]) -> Self{
+        let mut buttons = [None; MAX_BUTTONS];
+        for (button, value) in buttons.iter_mut().zip(values){
+            *button = ButtonConfig::from_bits(*value);
+        }
+        let pins = values[MAX_BUTTONS];
+        let (pin_a, pin_b) = ((pins & 0xFF) as u8, (pins >> 8 & 0xFF) as u8);
+        let encoder = if pin_a == 0 || pin_b == 0{
+            None
+        }else{
+            Some(EncoderConfig{
+                pin_a: pin_a - 1,
+                pin_b: pin_b - 1,
+                clockwise: KeyUsage::from_bits(values[MAX_BUTTONS + 1]),
+                counter_clockwise: KeyUsage::from_bits(values[MAX_BUTTONS + 2]),
+            })
+        };
+        Self { buttons, encoder }
+    }
+
+    /// 按键或编码器是否使用了这个GPIO
+    pub fn uses_pin(&self, pin: u8) -> bool{
+        self.buttons.iter().flatten().any(|button| button.pin == pin)
+            || self.encoder.is_some_and(|encoder| encoder.pin_a == pin || encoder.pin_b == pin)
+    }
+
+    pub fn to_bits(&self) -> [u32; INPUT_CONFIG_KEYS]{
+        let mut values = [0; INPUT_CONFIG_KEYS];
+        for (value, button) in values.iter_mut().zip(&self.buttons){
+            *value = button.map(|b| b.to_bits()).unwrap_or(0);
+        }
+        if let Some(encoder) = self.encoder{
+            values[MAX_BUTTONS] = (encoder.pin_a as u32 + 1) | (encoder.pin_b as u32 + 1) << 8;
+            values[MAX_BUTTONS + 1] = encoder.clockwise.to_bits();
+            values[MAX_BUTTONS + 2] = encoder.counter_clockwise.to_bits();
+        }
+        values
+    }
+}
+
+/// 按键消抖：连续DEBOUNCE_SAMPLES次采样相同才改变状态
+#[derive(Debug, Default)]
+pub struct Debouncer{
+    pressed: bool,
+    count: u8,
+}
+
+impl Debouncer{
+    /// 放入一次采样，状态改变时返回新状态
+    pub fn update(&mut self, pressed: bool) -> Option<bool>{
+        if pressed == self.pressed{
+            self.count = 0;
+            return None;
+        }
+        self.count += 1;
+        if self.count < DEBOUNCE_SAMPLES{
+            return None;
+        }
+        self.count = 0;
+        self.pressed = pressed;
+        Some(pressed)
+    }
+}
+
+/// 旋转编码器解码，每格4次状态变化
+#[derive(Debug, Default)]
+pub struct QuadratureDecoder{
+    state: u8,
+    steps: i8,
+}
+
+impl QuadratureDecoder{
+    /// 放入A、B相的电平，转过一格时返回1(顺时针)或-1(逆时针)
+    pub fn update(&mut self, a: bool, b: bool) -> i8{
+        //索引为 上次状态(2位) << 2 | 本次状态，非法跳变(同时变化两位)记为0
+        const TABLE: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+        let state = (a as u8) << 1 | b as u8;
+        self.steps += TABLE[(self.state << 2 | state) as usize];
+        self.state = state;
+        //回到停止位(两相都为高电平)时才输出，抖动产生的来回跳变会互相抵消
+        if state == 0b11 && self.steps.abs() >= 4{
+            let step = self.steps.signum();
+            self.steps = 0;
+            return step;
+        }
+        if state == 0b11{
+            self.steps = 0;
+        }
+        0
+    }
+}
+
+//InputEvent的类型
+pub const INPUT_BUTTON: u16 = 0;
+pub const INPUT_ENCODER: u16 = 1;
+
+/// 按键或编码器事件: 魔数 + 类型 + 编号 + 值
+/// 按键的值为1按下/0松开，编码器的值为转动的格数(顺时针为正)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent{
+    pub kind: u16,
+    pub id: u16,
+    pub value: i16,
+}
+
+impl InputEvent{
+    pub const LEN: usize = MAGIC_NUM_LEN + 6;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&INPUT_EVENT.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.kind.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.id.to_be_bytes());
+        buf[12..14].copy_from_slice(&self.value.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != INPUT_EVENT{
+            return None;
+        }
+        Some(Self{
+            kind: u16::from_be_bytes([data[8], data[9]]),
+            id: u16::from_be_bytes([data[10], data[11]]),
+            value: i16::from_be_bytes([data[12], data[13]]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trip() {
+        let mut config = InputConfig::from_bits(&[0; INPUT_CONFIG_KEYS]);
+        assert_eq!(config.buttons, [None; MAX_BUTTONS]);
+        assert_eq!(config.encoder, None);
+
+        config.buttons[0] = Some(ButtonConfig { pin: 0, usage: KeyUsage::Keyboard(0x4F) });
+        config.buttons[2] = Some(ButtonConfig { pin: 21, usage: KeyUsage::None });
+        config.encoder = Some(EncoderConfig {
+            pin_a: 2,
+            pin_b: 3,
+            clockwise: KeyUsage::Consumer(0xE9),
+            counter_clockwise: KeyUsage::Consumer(0xEA),
+        });
+        assert_eq!(InputConfig::from_bits(&config.to_bits()), config);
+    }
+
+    #[test]
+    fn debounce_ignores_glitches() {
+        let mut button = Debouncer::default();
+        assert_eq!(button.update(true), None);
+        assert_eq!(button.update(false), None);
+        for _ in 0..DEBOUNCE_SAMPLES - 1 {
+            assert_eq!(button.update(true), None);
+        }
+        assert_eq!(button.update(true), Some(true));
+        assert_eq!(button.update(true), None);
+    }
+
+    #[test]
+    fn quadrature_counts_detents() {
+        let mut encoder = QuadratureDecoder::default();
+        encoder.update(true, true);
+        // 顺时针一格: 11 -> 01 -> 00 -> 10 -> 11
+        let cw = [(false, true), (false, false), (true, false), (true, true)];
+        let steps: i8 = cw.iter().map(|&(a, b)| encoder.update(a, b)).sum();
+        assert_eq!(steps, 1);
+        let steps: i8 = cw.iter().rev().skip(1).chain([(true, true)].iter()).map(|&(a, b)| encoder.update(a, b)).sum();
+        assert_eq!(steps, -1);
+        // 抖动后回到停止位不计数
+        assert_eq!(encoder.update(false, true) + encoder.update(true, true), 0);
+    }
+
+    #[test]
+    fn input_event_round_trip() {
+        let event = InputEvent { kind: INPUT_ENCODER, id: 0, value: -2 };
+        assert_eq!(InputEvent::decode(&event.encode()), Some(event));
+    }
+}
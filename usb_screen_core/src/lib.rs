@@ -0,0 +1,29 @@
+//! 固件(usb_screen)和主机程序(examples)共用的代码：传输协议、图像处理等。
+//! 这里的代码不依赖任何硬件，可以直接在主机上运行测试。
+#![no_std]
+
+extern crate alloc;
+
+pub mod capture;
+pub mod clock;
+pub mod command;
+pub mod crash;
+pub mod detect;
+pub mod display;
+pub mod font;
+pub mod frame;
+pub mod hid;
+pub mod imageproc;
+pub mod input;
+pub mod panel;
+pub mod protocol;
+pub mod receiver;
+pub mod rgb565;
+pub mod scroll;
+pub mod serial;
+pub mod settings;
+pub mod sprite;
+pub mod stats;
+pub mod storage;
+pub mod touch;
+pub mod update;
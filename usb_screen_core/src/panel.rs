@@ -0,0 +1,115 @@
+//! 屏幕面板的显示参数：反色、Gamma曲线、显存偏移和颜色顺序。
+//!
+//! 不同批次的屏幕有的需要反色，有的颜色发白，主机通过SetPanel指令预览和保存这些参数(保存在设置中，见settings模块)，
+//! 固件启动时在屏幕初始化之后设置。Gamma曲线直接写入控制器：ST7789为PVGAMCTRL/NVGAMCTRL(各14字节)，
+//! ST7735为GMCTRP1/GMCTRN1(各16字节)，全为0表示使用控制器的默认曲线(已经写入过其他曲线时重启后恢复)。
+//!
+//! 很多128x128的ST7735模块只用了控制器显存的一部分，画面会偏移几个像素(边上出现花屏)，红蓝颠倒时需要切换为BGR。
+//! 偏移以横屏后的屏幕坐标表示，绘制时加到CASET/RASET的地址上。
+
+/// Gamma曲线的最大长度
+pub const GAMMA_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Inversion{
+    //固件默认(ST7735按型号，ST7789不反色)
+    #[default]
+    Default = 0,
+    Off = 1,
+    On = 2,
+}
+
+impl Inversion{
+    pub fn from_u32(value: u32) -> Option<Self>{
+        match value{
+            0 => Some(Inversion::Default),
+            1 => Some(Inversion::Off),
+            2 => Some(Inversion::On),
+            _ => None,
+        }
+    }
+
+    /// 是否反色，default为固件默认值
+    pub fn inverted(&self, default: bool) -> bool{
+        match self{
+            Inversion::Default => default,
+            Inversion::Off => false,
+            Inversion::On => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorOrder{
+    //固件默认(RGB)
+    #[default]
+    Default = 0,
+    Rgb = 1,
+    Bgr = 2,
+}
+
+impl ColorOrder{
+    pub fn from_u32(value: u32) -> Option<Self>{
+        match value{
+            0 => Some(ColorOrder::Default),
+            1 => Some(ColorOrder::Rgb),
+            2 => Some(ColorOrder::Bgr),
+            _ => None,
+        }
+    }
+
+    /// 是否为BGR顺序(MADCTL的BGR位)，default为固件默认值
+    pub fn bgr(&self, default: bool) -> bool{
+        match self{
+            ColorOrder::Default => default,
+            ColorOrder::Rgb => false,
+            ColorOrder::Bgr => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PanelConfig{
+    pub inversion: Inversion,
+    //正极性和负极性的Gamma曲线
+    pub positive: [u8; GAMMA_LEN],
+    pub negative: [u8; GAMMA_LEN],
+    //屏幕左上角在显存中的位置(横屏坐标)
+    pub offset_x: u16,
+    pub offset_y: u16,
+    pub color_order: ColorOrder,
+}
+
+impl PanelConfig{
+    /// 要写入控制器的Gamma曲线(正极性, 负极性)，每条取前len字节，全为0时返回None
+    pub fn gamma(&self, len: usize) -> Option<(&[u8], &[u8])>{
+        let len = len.min(GAMMA_LEN);
+        if self.positive.iter().chain(self.negative.iter()).all(|b| *b == 0){
+            return None;
+        }
+        Some((&self.positive[..len], &self.negative[..len]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_uses_controller_length() {
+        let mut panel = PanelConfig::default();
+        assert_eq!(panel.gamma(14), None);
+        assert!(Inversion::Default.inverted(true));
+        assert!(!Inversion::Off.inverted(true));
+        assert!(ColorOrder::Default.bgr(true));
+        assert!(ColorOrder::Bgr.bgr(false));
+        assert_eq!(ColorOrder::from_u32(3), None);
+
+        panel.positive = core::array::from_fn(|i| i as u8 + 1);
+        let (positive, negative) = panel.gamma(14).unwrap();
+        assert_eq!(positive.len(), 14);
+        assert_eq!(positive[13], 14);
+        assert_eq!(negative, [0; 14]);
+        assert_eq!(panel.gamma(32).unwrap().0.len(), GAMMA_LEN);
+    }
+}
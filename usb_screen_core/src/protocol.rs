@@ -0,0 +1,522 @@
+//! USB Raw和USB串口共用的传输协议。
+//! 每条指令以8字节魔数开头(u64 BE)，参数紧跟其后，所有数值都使用大端字节顺序。
+
+use crate::clock::ClockTime;
+use crate::panel::{ColorOrder, Inversion, PanelConfig, GAMMA_LEN};
+use crate::update::UpdateError;
+
+//图像传输开始标记(8字节)
+pub const IMAGE_AA:u64 = 7596835243154170209;
+//图像传输结束标记(8字节)
+pub const IMAGE_BB:u64 = 7596835243154170466;
+//重启到U盘模式命令(8字节)
+pub const BOOT_USB:u64 = 7093010483740242786;
+//读取设备信息(8字节) 串口读取信息使用
+pub const READ_INF:u64 = u64::from_be_bytes(*b"ReadInfo");
+//上传精灵(8字节)，参数为 编号、宽、高、格式、透明色，之后与IMAGE_AA一样发送lz4压缩数据和IMAGE_BB
+pub const UPLOAD_SPRITE:u64 = u64::from_be_bytes(*b"UpSprite");
+//绘制精灵(8字节)，之后是最多7个8字节的绘制项
+pub const DRAW_SPRITES:u64 = u64::from_be_bytes(*b"DrSprite");
+//使用3x3变换矩阵绘制精灵(8字节)
+pub const DRAW_WRP:u64 = u64::from_be_bytes(*b"DrawWarp");
+//修改设置并保存到Flash(8字节)，参数为 键(u16)、值(u32)
+pub const SET_CONFIG:u64 = u64::from_be_bytes(*b"SetConfg");
+//写入自定义开机画面(8字节)，之后发送开机画面数据(见storage模块)和IMAGE_BB
+pub const WRITE_SPLASH:u64 = u64::from_be_bytes(*b"WrSplash");
+//设置设备RTC时间(8字节)，参数为 年(u16)、月、日、星期、时、分、秒(u8)
+pub const SET_TIME:u64 = u64::from_be_bytes(*b"SetTime_");
+//开始固件升级(8字节)，参数为 固件长度(u32)、CRC32(u32)，之后发送固件数据和IMAGE_BB
+pub const FW_UPDATE:u64 = u64::from_be_bytes(*b"FwUpdate");
+//固件升级结果(8字节)，设备收到IMAGE_BB后返回，参数为 错误码(u16，0表示成功)
+pub const FW_RESULT:u64 = u64::from_be_bytes(*b"FwResult");
+//开启/关闭事件(8字节)，参数为 是否开启(u16)，开启后设备通过USB Raw的IN端点发送触摸事件和按键事件(见touch、input模块)
+pub const EVENT_SUB:u64 = u64::from_be_bytes(*b"EventSub");
+//触摸事件(8字节)，设备发送
+pub const TOUCH_EVENT:u64 = u64::from_be_bytes(*b"TouchEvt");
+//按键、编码器事件(8字节)，设备发送
+pub const INPUT_EVENT:u64 = u64::from_be_bytes(*b"InputEvt");
+//读取性能计数器(8字节)，参数为 是否在读取后清零(u16，可省略)，设备返回DevStats(见stats模块)
+pub const READ_STATS:u64 = u64::from_be_bytes(*b"ReadStat");
+//性能计数器(8字节)，设备发送
+pub const DEVICE_STATS:u64 = u64::from_be_bytes(*b"DevStats");
+//定义硬件滚动区域(8字节)，参数为 起始位置、长度(u16，沿滚动方向，长度为0时取消滚动)，见scroll模块
+pub const SCROLL_AREA:u64 = u64::from_be_bytes(*b"ScrlArea");
+//设置滚动偏移(8字节)，参数为 偏移(u16)
+pub const SCROLL_OFFSET:u64 = u64::from_be_bytes(*b"ScrlOffs");
+//设置反色和Gamma曲线(8字节)，参数为 是否保存(u16)、反色(u16)、正极性和负极性的Gamma曲线(各16字节)，见panel模块
+pub const SET_PANEL:u64 = u64::from_be_bytes(*b"SetPanel");
+pub const MAGIC_NUM_LEN: usize = 8;
+//USB包大小
+pub const PACKET_SIZE: usize = 64;
+
+//精灵格式: RGB565 / 带透明色的RGB565
+pub const SPRITE_FORMAT_RGB565: u16 = 0;
+pub const SPRITE_FORMAT_RGB565_KEY: u16 = 1;
+
+//DrawWarp的插值方式: 最近邻 / 双线性
+pub const INTERPOLATION_NEAREST: u16 = 0;
+pub const INTERPOLATION_BILINEAR: u16 = 1;
+
+fn read_u16(data: &[u8], offset: usize) -> u16{
+    u16::from_be_bytes([data[offset], data[offset+1]])
+}
+
+fn read_f32(data: &[u8], offset: usize) -> f32{
+    f32::from_be_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]])
+}
+
+/// 读取指令开头的魔数，数据不足8字节时返回None
+pub fn magic_number(data: &[u8]) -> Option<u64>{
+    if data.len() < MAGIC_NUM_LEN{
+        return None;
+    }
+    let mut magic_num_buf = [0u8; MAGIC_NUM_LEN];
+    magic_num_buf.copy_from_slice(&data[0..MAGIC_NUM_LEN]);
+    Some(u64::from_be_bytes(magic_num_buf))
+}
+
+/// 上传精灵指令: 魔数 + 编号 + 宽 + 高 + 格式 + 透明色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadSprite{
+    pub id: u16,
+    pub width: u16,
+    pub height: u16,
+    pub format: u16,
+    //格式为SPRITE_FORMAT_RGB565_KEY时，等于该颜色的像素不绘制
+    pub key: u16,
+}
+
+impl UploadSprite{
+    pub const LEN: usize = MAGIC_NUM_LEN + 10;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&UPLOAD_SPRITE.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.id.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.width.to_be_bytes());
+        buf[12..14].copy_from_slice(&self.height.to_be_bytes());
+        buf[14..16].copy_from_slice(&self.format.to_be_bytes());
+        buf[16..18].copy_from_slice(&self.key.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != UPLOAD_SPRITE{
+            return None;
+        }
+        Some(Self{
+            id: read_u16(data, 8),
+            width: read_u16(data, 10),
+            height: read_u16(data, 12),
+            format: read_u16(data, 14),
+            key: read_u16(data, 16),
+        })
+    }
+
+    /// 透明色
+    pub fn color_key(&self) -> Option<u16>{
+        if self.format == SPRITE_FORMAT_RGB565_KEY{
+            Some(self.key)
+        }else{
+            None
+        }
+    }
+}
+
+/// 一个精灵绘制项: 编号 + x + y + 保留(0)，共8字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpriteDraw{
+    pub id: u16,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// 绘制精灵指令: 魔数 + 最多7个绘制项，一个USB包可以绘制7个精灵
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawSprites{
+    pub count: usize,
+    pub draws: [SpriteDraw; Self::MAX_DRAWS],
+}
+
+impl DrawSprites{
+    pub const ENTRY_LEN: usize = 8;
+    pub const MAX_DRAWS: usize = (PACKET_SIZE - MAGIC_NUM_LEN) / Self::ENTRY_LEN;
+
+    /// 把绘制项分成多条指令，每条指令放在一个USB包中
+    pub fn pack(draws: &[SpriteDraw]) -> impl Iterator<Item = DrawSprites> + '_{
+        draws.chunks(Self::MAX_DRAWS).map(|chunk| {
+            let mut cmd = DrawSprites{ count: chunk.len(), draws: [SpriteDraw::default(); Self::MAX_DRAWS] };
+            cmd.draws[..chunk.len()].copy_from_slice(chunk);
+            cmd
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SpriteDraw>{
+        self.draws[..self.count].iter()
+    }
+
+    pub fn encode(&self) -> ([u8; PACKET_SIZE], usize){
+        let mut buf = [0u8; PACKET_SIZE];
+        buf[0..8].copy_from_slice(&DRAW_SPRITES.to_be_bytes());
+        for (i, draw) in self.iter().enumerate(){
+            let offset = MAGIC_NUM_LEN + i * Self::ENTRY_LEN;
+            buf[offset..offset+2].copy_from_slice(&draw.id.to_be_bytes());
+            buf[offset+2..offset+4].copy_from_slice(&draw.x.to_be_bytes());
+            buf[offset+4..offset+6].copy_from_slice(&draw.y.to_be_bytes());
+        }
+        (buf, MAGIC_NUM_LEN + self.count * Self::ENTRY_LEN)
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if magic_number(data)? != DRAW_SPRITES{
+            return None;
+        }
+        let count = ((data.len() - MAGIC_NUM_LEN) / Self::ENTRY_LEN).min(Self::MAX_DRAWS);
+        let mut cmd = DrawSprites{ count, draws: [SpriteDraw::default(); Self::MAX_DRAWS] };
+        for (i, draw) in cmd.draws[..count].iter_mut().enumerate(){
+            let offset = MAGIC_NUM_LEN + i * Self::ENTRY_LEN;
+            *draw = SpriteDraw{
+                id: read_u16(data, offset),
+                x: read_u16(data, offset+2),
+                y: read_u16(data, offset+4),
+            };
+        }
+        Some(cmd)
+    }
+}
+
+/// 修改设置指令: 魔数 + 键 + 值，键的定义见settings模块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetConfig{
+    pub key: u16,
+    pub value: u32,
+}
+
+impl SetConfig{
+    pub const LEN: usize = MAGIC_NUM_LEN + 6;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SET_CONFIG.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.key.to_be_bytes());
+        buf[10..14].copy_from_slice(&self.value.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SET_CONFIG{
+            return None;
+        }
+        Some(Self{
+            key: read_u16(data, 8),
+            value: u32::from_be_bytes([data[10], data[11], data[12], data[13]]),
+        })
+    }
+}
+
+/// 设置时间指令: 魔数 + 年 + 月 + 日 + 星期(0=星期日) + 时 + 分 + 秒
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetTime(pub ClockTime);
+
+impl SetTime{
+    pub const LEN: usize = MAGIC_NUM_LEN + 8;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let t = &self.0;
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SET_TIME.to_be_bytes());
+        buf[8..10].copy_from_slice(&t.year.to_be_bytes());
+        buf[10..16].copy_from_slice(&[t.month, t.day, t.weekday, t.hour, t.minute, t.second]);
+        buf
+    }
+
+    /// 时间无效时返回None
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SET_TIME{
+            return None;
+        }
+        let time = ClockTime{
+            year: read_u16(data, 8),
+            month: data[10],
+            day: data[11],
+            weekday: data[12],
+            hour: data[13],
+            minute: data[14],
+            second: data[15],
+        };
+        if !time.is_valid(){
+            return None;
+        }
+        Some(SetTime(time))
+    }
+}
+
+/// 开始固件升级指令: 魔数 + 固件长度 + CRC32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirmwareUpdate{
+    pub len: u32,
+    pub crc: u32,
+}
+
+impl FirmwareUpdate{
+    pub const LEN: usize = MAGIC_NUM_LEN + 8;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&FW_UPDATE.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.len.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.crc.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != FW_UPDATE{
+            return None;
+        }
+        Some(Self{
+            len: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            crc: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
+        })
+    }
+}
+
+/// 固件升级结果: 魔数 + 错误码
+pub fn encode_update_result(result: Result<(), UpdateError>) -> [u8; MAGIC_NUM_LEN + 2]{
+    let code = match result{
+        Ok(()) => 0,
+        Err(err) => err as u16,
+    };
+    let mut buf = [0u8; MAGIC_NUM_LEN + 2];
+    buf[0..8].copy_from_slice(&FW_RESULT.to_be_bytes());
+    buf[8..10].copy_from_slice(&code.to_be_bytes());
+    buf
+}
+
+/// 解析固件升级结果，不是升级结果时返回None
+pub fn decode_update_result(data: &[u8]) -> Option<Result<(), UpdateError>>{
+    if data.len() < MAGIC_NUM_LEN + 2 || magic_number(data)? != FW_RESULT{
+        return None;
+    }
+    match read_u16(data, 8){
+        0 => Some(Ok(())),
+        //未知的错误码按Flash错误处理
+        code => Some(Err(UpdateError::from_code(code).unwrap_or(UpdateError::Flash))),
+    }
+}
+
+/// 变换绘制指令: 魔数 + 精灵编号 + 目标区域(x,y,宽,高) + 背景色 + 插值方式 + 3x3变换矩阵(行优先, f32)
+///
+/// 变换矩阵把精灵中的坐标映射到目标区域中的坐标，目标区域中没有对应源像素的位置填充背景色。
+/// 双线性插值时图像边缘和背景色混合，旋转后的边缘更平滑。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawWarp{
+    pub id: u16,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub background: u16,
+    pub interpolation: u16,
+    pub matrix: [f32; 9],
+}
+
+impl DrawWarp{
+    pub const LEN: usize = MAGIC_NUM_LEN + 14 + 36;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&DRAW_WRP.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.id.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.x.to_be_bytes());
+        buf[12..14].copy_from_slice(&self.y.to_be_bytes());
+        buf[14..16].copy_from_slice(&self.width.to_be_bytes());
+        buf[16..18].copy_from_slice(&self.height.to_be_bytes());
+        buf[18..20].copy_from_slice(&self.background.to_be_bytes());
+        buf[20..22].copy_from_slice(&self.interpolation.to_be_bytes());
+        for (i, v) in self.matrix.iter().enumerate(){
+            buf[22+i*4..26+i*4].copy_from_slice(&v.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != DRAW_WRP{
+            return None;
+        }
+        let mut matrix = [0f32; 9];
+        for (i, v) in matrix.iter_mut().enumerate(){
+            *v = read_f32(data, 22+i*4);
+        }
+        Some(Self{
+            id: read_u16(data, 8),
+            x: read_u16(data, 10),
+            y: read_u16(data, 12),
+            width: read_u16(data, 14),
+            height: read_u16(data, 16),
+            background: read_u16(data, 18),
+            interpolation: read_u16(data, 20),
+            matrix,
+        })
+    }
+}
+
+/// 定义滚动区域指令: 魔数 + 起始位置 + 长度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollArea{
+    pub start: u16,
+    pub len: u16,
+}
+
+impl ScrollArea{
+    pub const LEN: usize = MAGIC_NUM_LEN + 4;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SCROLL_AREA.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.start.to_be_bytes());
+        buf[10..12].copy_from_slice(&self.len.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SCROLL_AREA{
+            return None;
+        }
+        Some(Self{ start: read_u16(data, 8), len: read_u16(data, 10) })
+    }
+}
+
+/// 设置滚动偏移指令: 魔数 + 偏移
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollOffset(pub u16);
+
+impl ScrollOffset{
+    pub const LEN: usize = MAGIC_NUM_LEN + 2;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SCROLL_OFFSET.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.0.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SCROLL_OFFSET{
+            return None;
+        }
+        Some(Self(read_u16(data, 8)))
+    }
+}
+
+/// 设置面板参数指令: 魔数 + 是否保存到Flash + 反色 + 正极性Gamma + 负极性Gamma + 显存偏移x、y + 颜色顺序。
+/// 不保存时只在本次运行中生效，用于校准时预览
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetPanel{
+    pub save: bool,
+    pub panel: PanelConfig,
+}
+
+impl SetPanel{
+    pub const LEN: usize = MAGIC_NUM_LEN + 4 + GAMMA_LEN * 2 + 6;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SET_PANEL.to_be_bytes());
+        buf[8..10].copy_from_slice(&(self.save as u16).to_be_bytes());
+        buf[10..12].copy_from_slice(&(self.panel.inversion as u16).to_be_bytes());
+        buf[12..12 + GAMMA_LEN].copy_from_slice(&self.panel.positive);
+        buf[12 + GAMMA_LEN..12 + GAMMA_LEN * 2].copy_from_slice(&self.panel.negative);
+        let offset = 12 + GAMMA_LEN * 2;
+        buf[offset..offset + 2].copy_from_slice(&self.panel.offset_x.to_be_bytes());
+        buf[offset + 2..offset + 4].copy_from_slice(&self.panel.offset_y.to_be_bytes());
+        buf[offset + 4..offset + 6].copy_from_slice(&(self.panel.color_order as u16).to_be_bytes());
+        buf
+    }
+
+    /// 反色或颜色顺序参数无效时返回None
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SET_PANEL{
+            return None;
+        }
+        let offset = 12 + GAMMA_LEN * 2;
+        let mut panel = PanelConfig{
+            inversion: Inversion::from_u32(read_u16(data, 10) as u32)?,
+            offset_x: read_u16(data, offset),
+            offset_y: read_u16(data, offset + 2),
+            color_order: ColorOrder::from_u32(read_u16(data, offset + 4) as u32)?,
+            ..Default::default()
+        };
+        panel.positive.copy_from_slice(&data[12..12 + GAMMA_LEN]);
+        panel.negative.copy_from_slice(&data[12 + GAMMA_LEN..offset]);
+        Some(Self{ save: read_u16(data, 8) != 0, panel })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_warp_round_trip() {
+        let cmd = DrawWarp {
+            id: 0,
+            x: 10,
+            y: 20,
+            width: 64,
+            height: 48,
+            background: 0xF800,
+            interpolation: INTERPOLATION_BILINEAR,
+            matrix: [0.5, -0.866, 12.0, 0.866, 0.5, -3.5, 0.0, 0.0, 1.0],
+        };
+        let packet = cmd.encode();
+        assert!(packet.len() <= 64);
+        assert_eq!(DrawWarp::decode(&packet), Some(cmd));
+        assert_eq!(UploadSprite::decode(&packet), None);
+    }
+
+    #[test]
+    fn set_panel_round_trip() {
+        let mut panel = PanelConfig { inversion: Inversion::On, offset_x: 2, offset_y: 3, color_order: ColorOrder::Bgr, ..Default::default() };
+        panel.positive[0] = 0xD0;
+        panel.negative[15] = 0x1D;
+        let cmd = SetPanel { save: true, panel };
+        let mut packet = cmd.encode();
+        assert!(packet.len() <= PACKET_SIZE);
+        assert_eq!(SetPanel::decode(&packet), Some(cmd));
+        packet[SetPanel::LEN - 1] = 3;
+        assert_eq!(SetPanel::decode(&packet), None);
+        packet[SetPanel::LEN - 1] = 2;
+        packet[11] = 3;
+        assert_eq!(SetPanel::decode(&packet), None);
+    }
+
+    #[test]
+    fn set_time_rejects_invalid_time() {
+        let time = ClockTime { year: 2024, month: 2, day: 29, weekday: 4, hour: 23, minute: 59, second: 59 };
+        let mut packet = SetTime(time).encode();
+        assert_eq!(SetTime::decode(&packet), Some(SetTime(time)));
+        packet[13] = 24;
+        assert_eq!(SetTime::decode(&packet), None);
+    }
+
+    #[test]
+    fn update_result_round_trip() {
+        for result in [Ok(()), Err(UpdateError::Crc), Err(UpdateError::TooLarge)] {
+            assert_eq!(decode_update_result(&encode_update_result(result)), Some(result));
+        }
+        assert_eq!(decode_update_result(b"ReadInfo00"), None);
+    }
+
+    #[test]
+    fn draw_sprites_fill_packets() {
+        let draws: alloc::vec::Vec<_> = (0..9).map(|i| SpriteDraw { id: i, x: i * 10, y: 300 - i }).collect();
+        let packets: alloc::vec::Vec<_> = DrawSprites::pack(&draws).collect();
+        assert_eq!(packets.len(), 2);
+        let (buf, len) = packets[0].encode();
+        assert_eq!(len, PACKET_SIZE);
+        let decoded = DrawSprites::decode(&buf[..len]).unwrap();
+        assert_eq!(decoded.iter().copied().collect::<alloc::vec::Vec<_>>(), draws[..7]);
+        let (buf, len) = packets[1].encode();
+        assert_eq!(DrawSprites::decode(&buf[..len]).unwrap().iter().count(), 2);
+    }
+}
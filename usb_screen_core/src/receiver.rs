@@ -0,0 +1,265 @@
+//! 协议解析：把主机发来的包(USB Raw的64字节包、串口解码后的帧、HID报告的数据)解析为图像帧、命令和Flash写入。
+//!
+//! 固件的USB Raw、串口和HID接口各使用一个Receiver，模拟器使用一个，两边对同样的数据得到同样的结果。
+//! IMAGE_AA(或UpSprite、WrSplash、FwUpdate)之后、IMAGE_BB之前不以魔数开头的包都是数据：
+//! 图像和精灵数据缓存到IMAGE_BB，最多frame_buffer字节，超出时整帧丢弃；开机画面和固件数据直接交给调用者写入Flash。
+//! 解析只改变Receiver的状态，执行(绘制、写入Flash、回复主机)由调用者根据返回的Packet完成。
+
+use alloc::vec::Vec;
+use crate::clock::ClockTime;
+use crate::command::Command;
+use crate::frame::{frame_memory, max_compressed_len, FrameError, Rect};
+use crate::protocol::{magic_number, DrawSprites, DrawWarp, FirmwareUpdate, ScrollArea, ScrollOffset, SetConfig, SetPanel, SetTime, UploadSprite, BOOT_USB, DRAW_SPRITES, DRAW_WRP, FW_UPDATE, IMAGE_AA, IMAGE_BB, MAGIC_NUM_LEN, READ_INF, READ_STATS, SCROLL_AREA, SCROLL_OFFSET, SET_CONFIG, SET_PANEL, SET_TIME, UPLOAD_SPRITE, WRITE_SPLASH};
+
+/// 存储当前正在写入的内容，两个接口共用一个存储，只有开始写入的接口的数据写入Flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashState{
+    Idle,
+    Splash,
+    Update,
+}
+
+/// 接收完成的一帧图像，位置和大小已经检查过，解压后用frame::crop_image裁剪到屏幕内
+pub struct Frame{
+    /// 头部中的区域(裁剪之前)
+    pub rect: Rect,
+    /// lz4压缩的RGB565 BE数据，开头4字节为解压后的长度
+    pub data: Vec<u8>,
+    /// 从IMAGE_AA到IMAGE_BB的时间
+    pub receive_us: u32,
+}
+
+/// 解析一个包的结果
+pub enum Packet<'a>{
+    /// 图像接收完成
+    Frame(Frame),
+    /// 图像被拒绝，记录到性能计数器
+    Rejected(FrameError),
+    /// 交给CommandHandler执行的命令
+    Command(Command),
+    SetConfig(SetConfig),
+    /// save为true时先保存到Flash，再作为Command::SetPanel执行
+    SetPanel(SetPanel),
+    SetTime(ClockTime),
+    /// 开始写入开机画面
+    BeginSplash,
+    /// 开始固件升级
+    BeginUpdate(FirmwareUpdate),
+    /// 开机画面数据，直接写入Flash
+    SplashData(&'a [u8]),
+    /// 固件数据，直接写入暂存区
+    UpdateData(&'a [u8]),
+    FinishSplash,
+    /// 校验固件并回复主机升级结果
+    FinishUpdate,
+    /// 写入开机画面或固件的过程中开始了新的图像，放弃写入
+    CancelWrites,
+    BootUsb,
+    /// 回复设备信息
+    ReadInfo,
+    /// 回复性能计数器，reset为true时读取后清零
+    ReadStats{ reset: bool },
+}
+
+pub struct Receiver{
+    screen_width: u16,
+    screen_height: u16,
+    frame_buffer: usize,
+    //IMAGE_AA中的区域和收到的时间
+    image: Rect,
+    image_start_us: u64,
+    //正在接收的是精灵
+    upload_sprite: Option<UploadSprite>,
+    //开机画面或固件是通过这个接口开始写入的，另一个接口的数据不会写入Flash
+    writing_flash: bool,
+    //接收到的数据
+    buf: Vec<u8>,
+    //数据超过了一帧的最大长度，在IMAGE_BB时丢弃
+    overflow: bool,
+}
+
+impl Receiver{
+    /// frame_buffer: 一帧最多占用的内存(见frame::frame_buffer_size)，压缩数据最多缓存这么多
+    pub fn new(screen_width: u16, screen_height: u16, frame_buffer: usize) -> Self{
+        Self{
+            screen_width,
+            screen_height,
+            frame_buffer,
+            image: Rect{ x: 0, y: 0, width: 0, height: 0 },
+            image_start_us: 0,
+            upload_sprite: None,
+            writing_flash: false,
+            buf: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// USB断开，放弃正在接收的数据，返回true时调用者取消Flash写入
+    pub fn disconnected(&mut self) -> bool{
+        self.upload_sprite = None;
+        self.buf.clear();
+        self.overflow = false;
+        core::mem::take(&mut self.writing_flash)
+    }
+
+    /// 解析收到的一个包。flash为存储当前正在写入的内容，now_us为当前时间(微秒)，用于统计接收耗时
+    pub fn handle<'a>(&mut self, data: &'a [u8], flash: FlashState, now_us: u64) -> Option<Packet<'a>>{
+        //短包只可能是数据
+        let magic_num = magic_number(data).unwrap_or(0);
+        let updating = self.writing_flash && flash == FlashState::Update;
+        let writing_splash = self.writing_flash && flash == FlashState::Splash;
+
+        if magic_num == IMAGE_AA && data.len() >= MAGIC_NUM_LEN + 8{
+            //图像开始
+            let read = |offset: usize| u16::from_be_bytes([data[MAGIC_NUM_LEN+offset], data[MAGIC_NUM_LEN+offset+1]]);
+            self.image = Rect{ width: read(0), height: read(2), x: read(4), y: read(6) };
+            self.image_start_us = now_us;
+            self.upload_sprite = None;
+            self.buf.clear();
+            self.overflow = false;
+            if core::mem::take(&mut self.writing_flash){
+                return Some(Packet::CancelWrites);
+            }
+        }else if magic_num == UPLOAD_SPRITE{
+            self.upload_sprite = UploadSprite::decode(data);
+            self.buf.clear();
+            self.overflow = false;
+        }else if magic_num == DRAW_SPRITES{
+            return DrawSprites::decode(data).map(|draws| Packet::Command(Command::DrawSprites(draws)));
+        }else if magic_num == DRAW_WRP{
+            return DrawWarp::decode(data).map(|warp| Packet::Command(Command::DrawWarp(warp)));
+        }else if magic_num == SCROLL_AREA{
+            return ScrollArea::decode(data).map(|area| Packet::Command(Command::ScrollArea(area)));
+        }else if magic_num == SCROLL_OFFSET{
+            return ScrollOffset::decode(data).map(|ScrollOffset(offset)| Packet::Command(Command::ScrollOffset(offset)));
+        }else if magic_num == SET_CONFIG{
+            return SetConfig::decode(data).map(Packet::SetConfig);
+        }else if magic_num == SET_PANEL{
+            return SetPanel::decode(data).map(Packet::SetPanel);
+        }else if magic_num == SET_TIME{
+            return SetTime::decode(data).map(|SetTime(time)| Packet::SetTime(time));
+        }else if magic_num == WRITE_SPLASH{
+            self.writing_flash = true;
+            return Some(Packet::BeginSplash);
+        }else if magic_num == FW_UPDATE{
+            let update = FirmwareUpdate::decode(data)?;
+            self.writing_flash = true;
+            return Some(Packet::BeginUpdate(update));
+        }else if magic_num == IMAGE_BB && updating{
+            //返回升级结果，成功时等待主机读取后重启
+            self.writing_flash = false;
+            return Some(Packet::FinishUpdate);
+        }else if magic_num == IMAGE_BB && writing_splash{
+            self.writing_flash = false;
+            return Some(Packet::FinishSplash);
+        }else if magic_num == IMAGE_BB && self.overflow{
+            //超长的数据已经被截断，不能解压
+            self.upload_sprite = None;
+            self.overflow = false;
+            self.buf.clear();
+            return Some(Packet::Rejected(FrameError::TooLarge));
+        }else if magic_num == IMAGE_BB && self.upload_sprite.is_some(){
+            //精灵接收完成，交给CommandHandler解压保存
+            let sprite = self.upload_sprite.take().unwrap();
+            return Some(Packet::Command(Command::UploadSprite{ sprite, data: core::mem::take(&mut self.buf) }));
+        }else if magic_num == IMAGE_BB{
+            return Some(self.finish_frame(now_us));
+        }else if magic_num == BOOT_USB{
+            return Some(Packet::BootUsb);
+        }else if magic_num == READ_INF{
+            return Some(Packet::ReadInfo);
+        }else if magic_num == READ_STATS{
+            let reset = data.len() >= MAGIC_NUM_LEN + 2 && u16::from_be_bytes([data[8], data[9]]) != 0;
+            return Some(Packet::ReadStats{ reset });
+        }else if updating{
+            return Some(Packet::UpdateData(data));
+        }else if writing_splash{
+            return Some(Packet::SplashData(data));
+        }else if self.buf.len() + data.len() <= self.buffer_limit(){
+            //图像传输中
+            self.buf.extend_from_slice(data);
+        }else{
+            self.overflow = true;
+        }
+        None
+    }
+
+    //图像的压缩数据最多为一帧的内存，精灵的压缩数据不超过它的大小压缩后的最大长度
+    fn buffer_limit(&self) -> usize{
+        match &self.upload_sprite{
+            Some(sprite) => max_compressed_len((sprite.width as usize * sprite.height as usize).saturating_mul(2)).min(self.frame_buffer),
+            None => self.frame_buffer,
+        }
+    }
+
+    //解压之前先检查位置和大小：完全在屏幕外的帧不需要解压，解压后内存不够用的帧直接丢弃，主机应该分成横条发送
+    fn finish_frame(&mut self, now_us: u64) -> Packet<'static>{
+        let data = core::mem::take(&mut self.buf);
+        if let Err(err) = self.image.clip(self.screen_width, self.screen_height){
+            return Packet::Rejected(err);
+        }
+        if frame_memory(&data).is_some_and(|len| len > self.frame_buffer){
+            return Packet::Rejected(FrameError::TooLarge);
+        }
+        let receive_us = now_us.saturating_sub(self.image_start_us).min(u32::MAX as u64) as u32;
+        Packet::Frame(Frame{ rect: self.image, data, receive_us })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::frame_buffer_size;
+
+    fn image_header(rect: Rect) -> Vec<u8>{
+        let mut header = IMAGE_AA.to_be_bytes().to_vec();
+        for value in [rect.width, rect.height, rect.x, rect.y]{
+            header.extend_from_slice(&value.to_be_bytes());
+        }
+        header
+    }
+
+    #[test]
+    fn receives_frames() {
+        let mut receiver = Receiver::new(160, 128, frame_buffer_size(160, 128));
+        let rect = Rect{ x: 10, y: 20, width: 2, height: 2 };
+        let data = lz4_flex::compress_prepend_size(&[0xFF; 8]);
+        assert!(receiver.handle(&image_header(rect), FlashState::Idle, 100).is_none());
+        assert!(receiver.handle(&data, FlashState::Idle, 150).is_none());
+        match receiver.handle(&IMAGE_BB.to_be_bytes(), FlashState::Idle, 300){
+            Some(Packet::Frame(frame)) => assert_eq!((frame.rect, frame.data, frame.receive_us), (rect, data, 200)),
+            _ => panic!("expected a frame"),
+        }
+        //左上角在屏幕外
+        receiver.handle(&image_header(Rect{ x: 160, ..rect }), FlashState::Idle, 0);
+        assert!(matches!(receiver.handle(&IMAGE_BB.to_be_bytes(), FlashState::Idle, 0), Some(Packet::Rejected(FrameError::OffScreen))));
+    }
+
+    #[test]
+    fn oversized_frames_are_dropped() {
+        let mut receiver = Receiver::new(160, 128, 100);
+        receiver.handle(&image_header(Rect{ x: 0, y: 0, width: 160, height: 128 }), FlashState::Idle, 0);
+        receiver.handle(&[1; 64], FlashState::Idle, 0);
+        receiver.handle(&[1; 64], FlashState::Idle, 0);
+        assert!(matches!(receiver.handle(&IMAGE_BB.to_be_bytes(), FlashState::Idle, 0), Some(Packet::Rejected(FrameError::TooLarge))));
+
+        //1x1的精灵压缩后不会超过25字节
+        let sprite = UploadSprite{ id: 1, width: 1, height: 1, format: 0, key: 0 };
+        receiver.handle(&sprite.encode(), FlashState::Idle, 0);
+        receiver.handle(&[1; 64], FlashState::Idle, 0);
+        assert!(matches!(receiver.handle(&IMAGE_BB.to_be_bytes(), FlashState::Idle, 0), Some(Packet::Rejected(FrameError::TooLarge))));
+    }
+
+    #[test]
+    fn flash_data_goes_to_the_interface_that_started_it() {
+        let mut receiver = Receiver::new(160, 128, 100);
+        assert!(matches!(receiver.handle(&crate::protocol::WRITE_SPLASH.to_be_bytes(), FlashState::Idle, 0), Some(Packet::BeginSplash)));
+        assert!(matches!(receiver.handle(&[1, 2, 3], FlashState::Splash, 0), Some(Packet::SplashData(&[1, 2, 3]))));
+        //另一个接口开始的写入
+        let mut other = Receiver::new(160, 128, 100);
+        assert!(other.handle(&[1, 2, 3], FlashState::Splash, 0).is_none());
+        //写入过程中开始新的图像
+        assert!(matches!(receiver.handle(&image_header(Rect{ x: 0, y: 0, width: 1, height: 1 }), FlashState::Splash, 0), Some(Packet::CancelWrites)));
+        assert!(!receiver.disconnected());
+    }
+}
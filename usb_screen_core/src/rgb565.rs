@@ -0,0 +1,26 @@
+/// RGB565像素值
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Rgb565Pixel(pub u16);
+
+/// RGB565图像，像素按行存储
+pub struct Rgb565Image<'a>{
+    pub pixels: &'a mut [u16],
+    pub width: u16,
+    pub height: u16,
+}
+
+impl <'a> Rgb565Image<'a>{
+    pub fn new(pixels: &'a mut [u16], width: u16, height: u16) -> Self{
+        Self { pixels, width, height }
+    }
+
+    #[inline(always)]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Rgb565Pixel{
+        Rgb565Pixel(self.pixels[y as usize * self.width as usize + x as usize])
+    }
+}
+
+/// RGB565 BE字节数组转换为u16像素
+pub fn rgb565_be_to_pixels(image: &[u8]) -> impl Iterator<Item = u16> + '_{
+    image.chunks_exact(2).map(|p| u16::from_be_bytes([p[0], p[1]]))
+}
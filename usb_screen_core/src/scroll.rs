@@ -0,0 +1,87 @@
+//! 硬件滚动(ST7789/ST7735的垂直滚动，VSCRDEF + VSCSAD)。
+//!
+//! 控制器沿显存的行方向滚动，固件使用横屏方式(MADCTL的MV位)，显存的行对应屏幕的x，所以在屏幕上是水平滚动：
+//! 滚动区域为屏幕上 start..start+len 列(整列)，区域内屏幕第x列显示显存中第 start + (x - start + offset) % len 列，区域外不变。
+//! 滚动只改变显示的位置，不移动显存中的数据，主机每次滚动后只需要把新露出的列写入显存(见memory_column)，
+//! 适合跑马灯、滚动日志等每次只有少量新内容的画面(主机端见examples/src/scroll.rs中的ScrollingText)。
+
+use crate::protocol::ScrollArea;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scroll{
+    //滚动区域的起始列和列数，len为0表示没有滚动
+    pub start: u16,
+    pub len: u16,
+    pub offset: u16,
+}
+
+impl Scroll{
+    /// 设置滚动区域并把偏移归零，超出屏幕的部分裁掉，长度为0或起点在屏幕外时取消滚动
+    pub fn set_area(&mut self, area: ScrollArea, screen_len: u16){
+        *self = if area.len == 0 || area.start >= screen_len{
+            Scroll::default()
+        }else{
+            Scroll{ start: area.start, len: area.len.min(screen_len - area.start), offset: 0 }
+        };
+    }
+
+    /// 设置偏移，超过区域长度时取余，没有滚动区域时忽略
+    pub fn set_offset(&mut self, offset: u16){
+        if self.len > 0{
+            self.offset = offset % self.len;
+        }
+    }
+
+    pub fn is_active(&self) -> bool{
+        self.len > 0
+    }
+
+    /// 滚动区域第一列显示的显存列(VSCSAD的参数)
+    pub fn start_line(&self) -> u16{
+        self.start + self.offset
+    }
+
+    /// 屏幕上第x列显示的显存列
+    pub fn memory_column(&self, x: u16) -> u16{
+        if x < self.start || x - self.start >= self.len{
+            return x;
+        }
+        self.start + (((x - self.start) as u32 + self.offset as u32) % self.len as u32) as u16
+    }
+
+    /// VSCRDEF的参数：上方固定区、滚动区、下方固定区的行数，lines为控制器显存的总行数
+    pub fn definition(&self, lines: u16) -> [u16; 3]{
+        if !self.is_active(){
+            return [0, lines, 0];
+        }
+        [self.start, self.len, lines.saturating_sub(self.start + self.len)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_visible_columns_to_memory() {
+        let mut scroll = Scroll::default();
+        scroll.set_area(ScrollArea { start: 10, len: 100 }, 160);
+        scroll.set_offset(230);
+        assert_eq!(scroll.offset, 30);
+        assert_eq!(scroll.start_line(), 40);
+        assert_eq!(scroll.memory_column(5), 5);
+        assert_eq!(scroll.memory_column(10), 40);
+        assert_eq!(scroll.memory_column(79), 109);
+        assert_eq!(scroll.memory_column(80), 10);
+        assert_eq!(scroll.memory_column(110), 110);
+        assert_eq!(scroll.definition(162), [10, 100, 52]);
+
+        // 超出屏幕的部分裁掉，长度为0时取消
+        scroll.set_area(ScrollArea { start: 100, len: 100 }, 160);
+        assert_eq!((scroll.len, scroll.offset), (60, 0));
+        scroll.set_area(ScrollArea { start: 0, len: 0 }, 160);
+        assert!(!scroll.is_active());
+        assert_eq!(scroll.memory_column(50), 50);
+        assert_eq!(scroll.definition(320), [0, 320, 0]);
+    }
+}
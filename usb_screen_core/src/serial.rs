@@ -0,0 +1,198 @@
+//! 串口传输的分帧格式。
+//!
+//! 串口是字节流，丢失或多出一个字节后就无法判断包的边界，所以每个包用COBS编码成一帧：
+//! 编码后的数据中没有0，帧的前后各加一个0作为分隔符。接收方在任意位置遇到0都可以重新同步，
+//! 损坏的帧解码失败后丢弃，最多影响一个包。
+//! 解码后的包和USB Raw收到的包相同：命令包(最多64字节)一帧，图像等数据每64字节一帧。
+//! 设备的回复(ReadInfo、ReadStat、FwUpdate)整个编码为一帧，日志是不含0的文本，只会出现在帧之间。
+
+use alloc::vec::Vec;
+
+use crate::protocol::PACKET_SIZE;
+
+pub const FRAME_DELIMITER: u8 = 0;
+
+//COBS编码后最多增加的字节数(每254字节一个编码字节)
+fn max_encoded_len(len: usize) -> usize{
+    len + len / 254 + 1
+}
+
+/// 把一个包编码成一帧(包括前后的分隔符)
+pub fn encode_frame(packet: &[u8]) -> Vec<u8>{
+    let mut frame = Vec::with_capacity(max_encoded_len(packet.len()) + 2);
+    frame.push(FRAME_DELIMITER);
+    let mut code_index = frame.len();
+    frame.push(0);
+    let mut code = 1u8;
+    for &byte in packet{
+        if byte != 0{
+            frame.push(byte);
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF{
+            frame[code_index] = code;
+            code_index = frame.len();
+            frame.push(0);
+            code = 1;
+        }
+    }
+    frame[code_index] = code;
+    frame.push(FRAME_DELIMITER);
+    frame
+}
+
+//原地解码COBS数据(不包括分隔符)，返回解码后的长度
+fn decode_in_place(buf: &mut [u8]) -> Option<usize>{
+    let (mut read, mut write) = (0, 0);
+    while read < buf.len(){
+        let code = buf[read] as usize;
+        if code == 0 || read + code > buf.len(){
+            return None;
+        }
+        read += 1;
+        buf.copy_within(read..read + code - 1, write);
+        write += code - 1;
+        read += code - 1;
+        if code != 0xFF && read < buf.len(){
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    Some(write)
+}
+
+/// 从收到的字节流中取出包
+pub struct FrameDecoder{
+    buf: Vec<u8>,
+    //最大包长度，超过的帧丢弃
+    max_len: usize,
+    //当前帧超长，丢弃到下一个分隔符
+    overflow: bool,
+    //上一个包已经返回，收到新的字节时清空
+    complete: bool,
+}
+
+impl Default for FrameDecoder{
+    fn default() -> Self{
+        Self::new(PACKET_SIZE)
+    }
+}
+
+impl FrameDecoder{
+    pub fn new(max_len: usize) -> Self{
+        Self { buf: Vec::with_capacity(max_encoded_len(max_len)), max_len, overflow: false, complete: false }
+    }
+
+    /// 放入一个字节，帧结束时返回解码后的包。空帧和损坏的帧不返回
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]>{
+        if core::mem::take(&mut self.complete){
+            self.buf.clear();
+        }
+        if byte != FRAME_DELIMITER{
+            if self.buf.len() < max_encoded_len(self.max_len){
+                self.buf.push(byte);
+            }else{
+                self.overflow = true;
+            }
+            return None;
+        }
+        self.complete = true;
+        if core::mem::take(&mut self.overflow) || self.buf.is_empty(){
+            return None;
+        }
+        let len = decode_in_place(&mut self.buf)?;
+        if len > self.max_len{
+            return None;
+        }
+        Some(&self.buf[..len])
+    }
+
+    /// 放弃正在接收的帧(例如串口断开)
+    pub fn reset(&mut self){
+        self.buf.clear();
+        self.overflow = false;
+        self.complete = false;
+    }
+}
+
+/// 主机从串口读取设备的回复：丢弃第一个分隔符之前的字节(回复之前的日志或上一次没有读完的数据)，返回之后的第一帧
+pub struct ResponseDecoder{
+    decoder: FrameDecoder,
+    synced: bool,
+}
+
+impl ResponseDecoder{
+    pub fn new(max_len: usize) -> Self{
+        Self { decoder: FrameDecoder::new(max_len), synced: false }
+    }
+
+    /// 放入一个字节，回复结束时返回
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]>{
+        if !self.synced{
+            self.synced = byte == FRAME_DELIMITER;
+            return None;
+        }
+        self.decoder.push(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn decode_all(decoder: &mut FrameDecoder, data: &[u8]) -> Vec<Vec<u8>>{
+        data.iter().filter_map(|&b| decoder.push(b).map(|p| p.to_vec())).collect()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut decoder = FrameDecoder::new(600);
+        let packets: [Vec<u8>; 5] = [
+            vec![1, 2, 3],
+            vec![0, 0, 5, 0],
+            (0..=255).cycle().take(600).collect(),
+            vec![7; 254],
+            vec![0],
+        ];
+        for packet in &packets{
+            let frame = encode_frame(packet);
+            assert!(!frame[1..frame.len() - 1].contains(&FRAME_DELIMITER));
+            assert_eq!(decode_all(&mut decoder, &frame), core::slice::from_ref(packet));
+        }
+    }
+
+    #[test]
+    fn resynchronizes_after_corruption() {
+        let mut decoder = FrameDecoder::default();
+        let mut stream = encode_frame(&[1, 0, 2, 3, 4]);
+        // 丢失一个字节，第一帧解码失败或内容错误，第二帧不受影响
+        stream.remove(3);
+        stream.extend_from_slice(&encode_frame(&[9, 8, 0, 7]));
+        let packets = decode_all(&mut decoder, &stream);
+        assert_eq!(packets.last().unwrap(), &[9, 8, 0, 7]);
+
+        // 超长的帧丢弃到下一个分隔符
+        let mut stream = encode_frame(&[5; 200]);
+        stream.extend_from_slice(&encode_frame(&[6]));
+        assert_eq!(decode_all(&mut decoder, &stream), [vec![6u8]]);
+    }
+
+    #[test]
+    fn responses_skip_log_text() {
+        let info = b"USBSCR320x240;01;sprite=16384/16384;frame=172800;stripe=38400".repeat(3);
+        let mut stream = b"[INFO] config 3 = 1\r\n".to_vec();
+        stream.extend_from_slice(&encode_frame(&info));
+        let mut decoder = ResponseDecoder::new(1024);
+        // 串口每次只读到一部分数据
+        let mut response = None;
+        for chunk in stream.chunks(7){
+            for &byte in chunk{
+                if let Some(packet) = decoder.push(byte){
+                    response = Some(packet.to_vec());
+                }
+            }
+        }
+        assert_eq!(response.unwrap(), info);
+    }
+}
@@ -0,0 +1,257 @@
+//! 保存在Flash中的设备设置。
+//! 存储格式: 魔数(8字节) + 项数(u16 BE) + 若干个(键 u16 BE, 值 u32 BE)，
+//! 不认识的键直接忽略，缺少的键使用默认值，新旧固件之间可以互相读取。
+
+use alloc::vec::Vec;
+
+use crate::input::{InputConfig, INPUT_CONFIG_KEYS};
+use crate::panel::{ColorOrder, Inversion, PanelConfig, GAMMA_LEN};
+use crate::protocol::{magic_number, MAGIC_NUM_LEN};
+
+pub const SETTINGS_MAGIC: u64 = u64::from_be_bytes(*b"ScrConf1");
+
+//设置项的键，SetConfg命令使用
+pub const CONFIG_SPLASH_MODE: u16 = 1;
+//没有新图像多少秒后回到空闲画面，0表示不超时
+pub const CONFIG_IDLE_TIMEOUT: u16 = 2;
+//触摸屏校准参数(见touch::Calibration)，6个键依次保存a~f的f32二进制，全为0表示未校准
+pub const CONFIG_TOUCH_CALIBRATION: u16 = 3;
+pub const TOUCH_CALIBRATION_KEYS: usize = 6;
+//按键和编码器(见input模块)，共INPUT_CONFIG_KEYS个键，重启后生效
+pub const CONFIG_INPUT: u16 = 9;
+//屏幕反色(见panel::Inversion)
+pub const CONFIG_PANEL_INVERSION: u16 = 32;
+//Gamma曲线，8个键依次保存正极性和负极性曲线的字节(每个键4字节，BE)，通过SetPanel指令修改
+pub const CONFIG_PANEL_GAMMA: u16 = 33;
+pub const PANEL_GAMMA_KEYS: usize = GAMMA_LEN * 2 / 4;
+//显存偏移，低16位为x，高16位为y
+pub const CONFIG_PANEL_OFFSET: u16 = 41;
+//颜色顺序(见panel::ColorOrder)
+pub const CONFIG_PANEL_COLOR_ORDER: u16 = 42;
+//屏幕TE(撕裂效应)输出接的引脚+1，0表示没有连接，重启后生效
+pub const CONFIG_TE_PIN: u16 = 43;
+//绘制图像前是否等待TE信号(0 关/1 开)，立即生效
+pub const CONFIG_TE_SYNC: u16 = 44;
+
+//每个设置项占用的字节数
+const ENTRY_LEN: usize = 6;
+
+/// 没有图像传输时显示的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplashMode{
+    //固件内置的动画
+    BuiltIn,
+    //Flash中保存的自定义图像(或动画)
+    Custom,
+    //黑屏
+    Blank,
+    //RTC时钟
+    Clock,
+}
+
+impl SplashMode{
+    pub fn from_u32(value: u32) -> Option<Self>{
+        match value{
+            0 => Some(SplashMode::BuiltIn),
+            1 => Some(SplashMode::Custom),
+            2 => Some(SplashMode::Blank),
+            3 => Some(SplashMode::Clock),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings{
+    pub splash_mode: SplashMode,
+    pub idle_timeout: u32,
+    pub touch_calibration: [u32; TOUCH_CALIBRATION_KEYS],
+    pub inputs: [u32; INPUT_CONFIG_KEYS],
+    pub panel: PanelConfig,
+    pub te_pin: Option<u8>,
+    pub te_sync: bool,
+}
+
+impl Default for Settings{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl Settings{
+    /// 默认设置，可以用于static初始化
+    pub const fn new() -> Self{
+        Self{
+            splash_mode: SplashMode::BuiltIn,
+            idle_timeout: 0,
+            touch_calibration: [0; TOUCH_CALIBRATION_KEYS],
+            inputs: [0; INPUT_CONFIG_KEYS],
+            panel: PanelConfig{
+                inversion: Inversion::Default,
+                positive: [0; GAMMA_LEN],
+                negative: [0; GAMMA_LEN],
+                offset_x: 0,
+                offset_y: 0,
+                color_order: ColorOrder::Default,
+            },
+            te_pin: None,
+            te_sync: false,
+        }
+    }
+
+    /// 修改设置项，键不存在或值无效时返回false
+    pub fn set(&mut self, key: u16, value: u32) -> bool{
+        match key{
+            CONFIG_SPLASH_MODE => match SplashMode::from_u32(value){
+                Some(mode) => self.splash_mode = mode,
+                None => return false,
+            },
+            CONFIG_IDLE_TIMEOUT => self.idle_timeout = value,
+            key if (CONFIG_TOUCH_CALIBRATION..CONFIG_TOUCH_CALIBRATION + TOUCH_CALIBRATION_KEYS as u16).contains(&key) => {
+                self.touch_calibration[(key - CONFIG_TOUCH_CALIBRATION) as usize] = value;
+            }
+            //TE引脚和按键、编码器不能使用同一个GPIO(TE下拉，按键上拉)
+            key if (CONFIG_INPUT..CONFIG_INPUT + INPUT_CONFIG_KEYS as u16).contains(&key) => {
+                let mut inputs = self.inputs;
+                inputs[(key - CONFIG_INPUT) as usize] = value;
+                if self.te_pin.is_some_and(|pin| InputConfig::from_bits(&inputs).uses_pin(pin)){
+                    return false;
+                }
+                self.inputs = inputs;
+            }
+            CONFIG_PANEL_INVERSION => match Inversion::from_u32(value){
+                Some(inversion) => self.panel.inversion = inversion,
+                None => return false,
+            },
+            key if (CONFIG_PANEL_GAMMA..CONFIG_PANEL_GAMMA + PANEL_GAMMA_KEYS as u16).contains(&key) => {
+                let offset = (key - CONFIG_PANEL_GAMMA) as usize * 4;
+                let (curve, offset) = if offset < GAMMA_LEN{
+                    (&mut self.panel.positive, offset)
+                }else{
+                    (&mut self.panel.negative, offset - GAMMA_LEN)
+                };
+                curve[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+            }
+            CONFIG_PANEL_OFFSET => {
+                self.panel.offset_x = value as u16;
+                self.panel.offset_y = (value >> 16) as u16;
+            }
+            CONFIG_PANEL_COLOR_ORDER => match ColorOrder::from_u32(value){
+                Some(order) => self.panel.color_order = order,
+                None => return false,
+            },
+            //RP2040只有GPIO0~29
+            CONFIG_TE_PIN => match value{
+                0 => self.te_pin = None,
+                1..=30 if InputConfig::from_bits(&self.inputs).uses_pin(value as u8 - 1) => return false,
+                1..=30 => self.te_pin = Some(value as u8 - 1),
+                _ => return false,
+            },
+            CONFIG_TE_SYNC => match value{
+                0 | 1 => self.te_sync = value == 1,
+                _ => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn entries(&self) -> Vec<(u16, u32)>{
+        let mut entries = alloc::vec![
+            (CONFIG_SPLASH_MODE, self.splash_mode as u32),
+            (CONFIG_IDLE_TIMEOUT, self.idle_timeout),
+        ];
+        for (i, value) in self.touch_calibration.iter().enumerate(){
+            entries.push((CONFIG_TOUCH_CALIBRATION + i as u16, *value));
+        }
+        for (i, value) in self.inputs.iter().enumerate(){
+            entries.push((CONFIG_INPUT + i as u16, *value));
+        }
+        entries.push((CONFIG_PANEL_INVERSION, self.panel.inversion as u32));
+        let gamma = self.panel.positive.iter().chain(self.panel.negative.iter()).copied().collect::<Vec<u8>>();
+        for (i, bytes) in gamma.chunks_exact(4).enumerate(){
+            entries.push((CONFIG_PANEL_GAMMA + i as u16, u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])));
+        }
+        entries.push((CONFIG_PANEL_OFFSET, self.panel.offset_x as u32 | (self.panel.offset_y as u32) << 16));
+        entries.push((CONFIG_PANEL_COLOR_ORDER, self.panel.color_order as u32));
+        entries.push((CONFIG_TE_PIN, self.te_pin.map(|pin| pin as u32 + 1).unwrap_or(0)));
+        entries.push((CONFIG_TE_SYNC, self.te_sync as u32));
+        entries
+    }
+
+    pub fn encode(&self) -> Vec<u8>{
+        let entries = self.entries();
+        let mut buf = Vec::with_capacity(MAGIC_NUM_LEN + 2 + entries.len() * ENTRY_LEN);
+        buf.extend_from_slice(&SETTINGS_MAGIC.to_be_bytes());
+        buf.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+        for (key, value) in entries{
+            buf.extend_from_slice(&key.to_be_bytes());
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+        buf
+    }
+
+    /// 读取设置，数据无效(例如Flash被擦除)时返回默认设置
+    pub fn decode(data: &[u8]) -> Self{
+        let mut settings = Settings::default();
+        if magic_number(data) != Some(SETTINGS_MAGIC) || data.len() < MAGIC_NUM_LEN + 2{
+            return settings;
+        }
+        let count = u16::from_be_bytes([data[8], data[9]]) as usize;
+        let entries = data[MAGIC_NUM_LEN + 2..].chunks_exact(ENTRY_LEN).take(count);
+        for entry in entries{
+            let key = u16::from_be_bytes([entry[0], entry[1]]);
+            let value = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+            settings.set(key, value);
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip() {
+        let mut settings = Settings::default();
+        assert!(settings.set(CONFIG_SPLASH_MODE, 2));
+        assert!(settings.set(CONFIG_IDLE_TIMEOUT, 30));
+        assert!(settings.set(CONFIG_TOUCH_CALIBRATION + 5, 1.5f32.to_bits()));
+        assert_eq!(settings.touch_calibration[5], 1.5f32.to_bits());
+        assert!(settings.set(CONFIG_INPUT + 4, 0x0403));
+        assert_eq!(settings.inputs[4], 0x0403);
+        assert!(settings.set(CONFIG_PANEL_INVERSION, 2));
+        assert!(settings.set(CONFIG_PANEL_GAMMA + 4, 0xD0000207));
+        assert_eq!(settings.panel.negative[..4], [0xD0, 0x00, 0x02, 0x07]);
+        assert!(!settings.set(CONFIG_PANEL_INVERSION, 3));
+        assert!(settings.set(CONFIG_PANEL_OFFSET, 2 | 1 << 16));
+        assert_eq!((settings.panel.offset_x, settings.panel.offset_y), (2, 1));
+        assert!(settings.set(CONFIG_PANEL_COLOR_ORDER, 2));
+        assert!(settings.set(CONFIG_TE_PIN, 12));
+        assert_eq!(settings.te_pin, Some(11));
+        assert!(!settings.set(CONFIG_TE_PIN, 31));
+        assert!(settings.set(CONFIG_TE_SYNC, 1));
+        assert!(!settings.set(CONFIG_SPLASH_MODE, 9));
+        assert!(!settings.set(0xFFFF, 0));
+        assert_eq!(Settings::decode(&settings.encode()), settings);
+        // 擦除后的Flash全是0xFF
+        assert_eq!(Settings::decode(&[0xFF; 64]), Settings::default());
+    }
+
+    #[test]
+    fn te_pin_conflicts_with_inputs() {
+        let mut settings = Settings::default();
+        // 按键0使用GPIO5，编码器使用GPIO2、GPIO3
+        assert!(settings.set(CONFIG_INPUT, 0x0100 | 6));
+        assert!(settings.set(CONFIG_INPUT + 4, 0x0403));
+        assert!(!settings.set(CONFIG_TE_PIN, 6));
+        assert!(!settings.set(CONFIG_TE_PIN, 4));
+        assert_eq!(settings.te_pin, None);
+        assert!(settings.set(CONFIG_TE_PIN, 12));
+        // 已经设置了TE引脚时，按键不能再使用它
+        assert!(!settings.set(CONFIG_INPUT + 1, 12));
+        assert_eq!(settings.inputs[1], 0);
+        assert!(settings.set(CONFIG_INPUT + 1, 13));
+    }
+}
@@ -0,0 +1,148 @@
+//! 设备端精灵(图标)缓存：按编号保存RGB565图像，所有图像的总大小不超过设定的容量。
+
+use alloc::vec::Vec;
+
+//精灵编号范围 0..MAX_SPRITES
+pub const MAX_SPRITES: usize = 64;
+
+pub struct Sprite{
+    pub width: u16,
+    pub height: u16,
+    //透明色，等于该颜色的像素不绘制
+    pub key: Option<u16>,
+    pub pixels: Vec<u16>,
+}
+
+impl Sprite{
+    pub fn size_bytes(&self) -> usize{
+        self.pixels.len() * 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteError{
+    //编号超出范围
+    InvalidId,
+    //缓存剩余空间不足
+    NoSpace,
+    //像素数量与宽高不符
+    SizeMismatch,
+}
+
+pub struct SpriteCache{
+    slots: Vec<Option<Sprite>>,
+    capacity: usize,
+    used: usize,
+}
+
+impl SpriteCache{
+    /// capacity: 缓存容量(字节)
+    pub fn new(capacity: usize) -> Self{
+        let mut slots = Vec::with_capacity(MAX_SPRITES);
+        slots.resize_with(MAX_SPRITES, || None);
+        Self { slots, capacity, used: 0 }
+    }
+
+    pub fn capacity(&self) -> usize{
+        self.capacity
+    }
+
+    pub fn free(&self) -> usize{
+        self.capacity - self.used
+    }
+
+    pub fn get(&self, id: u16) -> Option<&Sprite>{
+        self.slots.get(id as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: u16) -> Option<&mut Sprite>{
+        self.slots.get_mut(id as usize)?.as_mut()
+    }
+
+    pub fn remove(&mut self, id: u16) -> Option<Sprite>{
+        let sprite = self.slots.get_mut(id as usize)?.take()?;
+        self.used -= sprite.size_bytes();
+        Some(sprite)
+    }
+
+    /// 检查编号为id的精灵替换为bytes字节的图像后是否能放下，在解压图像之前调用，避免内存不足
+    pub fn check_space(&self, id: u16, bytes: usize) -> Result<(), SpriteError>{
+        if id as usize >= MAX_SPRITES{
+            return Err(SpriteError::InvalidId);
+        }
+        let replaced = self.get(id).map(|s| s.size_bytes()).unwrap_or(0);
+        if bytes > self.free() + replaced{
+            return Err(SpriteError::NoSpace);
+        }
+        Ok(())
+    }
+
+    /// 保存精灵，替换相同编号的旧图像
+    pub fn insert(&mut self, id: u16, sprite: Sprite) -> Result<(), SpriteError>{
+        if sprite.pixels.len() != sprite.width as usize * sprite.height as usize{
+            return Err(SpriteError::SizeMismatch);
+        }
+        self.check_space(id, sprite.size_bytes())?;
+        self.remove(id);
+        self.used += sprite.size_bytes();
+        self.slots[id as usize] = Some(sprite);
+        Ok(())
+    }
+}
+
+/// 一行像素中需要绘制的连续区间(起点, 长度)，跳过等于透明色的像素
+pub fn opaque_runs(row: &[u16], key: Option<u16>) -> impl Iterator<Item = (usize, usize)> + '_{
+    let mut x = 0;
+    core::iter::from_fn(move || {
+        if let Some(key) = key{
+            while x < row.len() && row[x] == key{
+                x += 1;
+            }
+        }
+        if x >= row.len(){
+            return None;
+        }
+        let start = x;
+        while x < row.len() && Some(row[x]) != key{
+            x += 1;
+        }
+        Some((start, x - start))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn sprite(width: u16, height: u16) -> Sprite {
+        Sprite { width, height, key: None, pixels: vec![0; width as usize * height as usize] }
+    }
+
+    #[test]
+    fn cache_tracks_free_space() {
+        let mut cache = SpriteCache::new(100);
+        cache.insert(1, sprite(5, 5)).unwrap();
+        assert_eq!(cache.free(), 50);
+        assert_eq!(cache.insert(2, sprite(6, 5)), Err(SpriteError::NoSpace));
+        // 替换同一编号时，旧图像占用的空间可以重复使用
+        cache.insert(1, sprite(10, 5)).unwrap();
+        assert_eq!(cache.free(), 0);
+        assert_eq!(cache.insert(MAX_SPRITES as u16, sprite(1, 1)), Err(SpriteError::InvalidId));
+        let bad = Sprite { width: 2, height: 2, key: None, pixels: vec![0; 3] };
+        assert_eq!(cache.insert(3, bad), Err(SpriteError::SizeMismatch));
+        assert!(cache.remove(1).is_some());
+        assert_eq!(cache.free(), 100);
+        assert!(cache.get(1).is_none());
+    }
+
+    #[test]
+    fn runs_skip_key_color() {
+        let row = [7, 7, 1, 2, 7, 3, 7];
+        let runs: Vec<_> = opaque_runs(&row, Some(7)).collect();
+        assert_eq!(runs, [(2, 2), (5, 1)]);
+        let runs: Vec<_> = opaque_runs(&row, None).collect();
+        assert_eq!(runs, [(0, 7)]);
+        assert_eq!(opaque_runs(&[7, 7], Some(7)).count(), 0);
+    }
+}
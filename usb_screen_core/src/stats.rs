@@ -0,0 +1,156 @@
+//! 设备的性能计数器：每帧的接收、解压、绘制耗时，丢帧数、解码错误数和堆内存使用量。
+//! 主机发送ReadStat后设备返回DeviceStats，主机可以根据两次读取之间frames和uptime_ms的差计算帧率。
+
+use crate::protocol::{magic_number, DEVICE_STATS, MAGIC_NUM_LEN};
+
+/// 一项耗时的统计(微秒)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing{
+    //指数移动平均，最近的8帧左右
+    pub avg_us: u32,
+    pub max_us: u32,
+}
+
+impl Timing{
+    pub const fn new() -> Self{
+        Self { avg_us: 0, max_us: 0 }
+    }
+
+    pub fn record(&mut self, us: u32){
+        self.max_us = self.max_us.max(us);
+        self.avg_us = if self.avg_us == 0{
+            us
+        }else{
+            self.avg_us - self.avg_us / 8 + us / 8
+        };
+    }
+}
+
+/// 性能计数器: 魔数 + 15个u32 BE
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceStats{
+    //开机后的时间
+    pub uptime_ms: u32,
+    //绘制完成的帧数
+    pub frames: u32,
+    //上一帧还没有绘制完成，新的帧被丢弃的次数
+    pub dropped_frames: u32,
+    //lz4解压失败的次数(串口传输可能出现错误帧)
+    pub decode_errors: u32,
+    //从IMAGE_AA到IMAGE_BB的时间
+    pub receive: Timing,
+    pub decompress: Timing,
+    pub draw: Timing,
+    pub heap_used: u32,
+    //堆内存使用的最大值
+    pub heap_peak: u32,
+    pub heap_size: u32,
+    //位置、大小无效或数据长度不符被拒绝的帧数(见frame模块)
+    pub rejected_frames: u32,
+    //最后一次拒绝的原因(FrameError)，0表示没有
+    pub last_error: u32,
+}
+
+impl DeviceStats{
+    pub const LEN: usize = MAGIC_NUM_LEN + 15 * 4;
+    //旧固件没有最后两项
+    const MIN_LEN: usize = MAGIC_NUM_LEN + 13 * 4;
+
+    /// 计数器清零，可以用于static初始化
+    pub const fn new() -> Self{
+        Self{
+            uptime_ms: 0,
+            frames: 0,
+            dropped_frames: 0,
+            decode_errors: 0,
+            receive: Timing::new(),
+            decompress: Timing::new(),
+            draw: Timing::new(),
+            heap_used: 0,
+            heap_peak: 0,
+            heap_size: 0,
+            rejected_frames: 0,
+            last_error: 0,
+        }
+    }
+
+    fn values(&self) -> [u32; 15]{
+        let timings = [self.receive, self.decompress, self.draw];
+        let mut values = [0; 15];
+        values[0..4].copy_from_slice(&[self.uptime_ms, self.frames, self.dropped_frames, self.decode_errors]);
+        for (i, t) in timings.iter().enumerate(){
+            values[4+i*2..6+i*2].copy_from_slice(&[t.avg_us, t.max_us]);
+        }
+        values[10..].copy_from_slice(&[self.heap_used, self.heap_peak, self.heap_size, self.rejected_frames, self.last_error]);
+        values
+    }
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&DEVICE_STATS.to_be_bytes());
+        for (i, v) in self.values().iter().enumerate(){
+            buf[8+i*4..12+i*4].copy_from_slice(&v.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::MIN_LEN || magic_number(data)? != DEVICE_STATS{
+            return None;
+        }
+        let read = |i: usize| match data.get(8+i*4..12+i*4){
+            Some(v) => u32::from_be_bytes([v[0], v[1], v[2], v[3]]),
+            None => 0,
+        };
+        let timing = |i: usize| Timing { avg_us: read(i), max_us: read(i+1) };
+        Some(Self{
+            uptime_ms: read(0),
+            frames: read(1),
+            dropped_frames: read(2),
+            decode_errors: read(3),
+            receive: timing(4),
+            decompress: timing(6),
+            draw: timing(8),
+            heap_used: read(10),
+            heap_peak: read(11),
+            heap_size: read(12),
+            rejected_frames: read(13),
+            last_error: read(14),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_average() {
+        let mut timing = Timing::new();
+        timing.record(8000);
+        assert_eq!(timing.avg_us, 8000);
+        timing.record(16000);
+        assert_eq!((timing.avg_us, timing.max_us), (9000, 16000));
+        timing.record(0);
+        assert_eq!(timing.max_us, 16000);
+    }
+
+    #[test]
+    fn stats_round_trip() {
+        let mut stats = DeviceStats::new();
+        stats.frames = 120;
+        stats.dropped_frames = 3;
+        stats.decompress.record(18000);
+        stats.draw.record(26000);
+        stats.heap_peak = 150 * 1024;
+        stats.rejected_frames = 2;
+        stats.last_error = 3;
+        assert_eq!(DeviceStats::decode(&stats.encode()), Some(stats));
+        assert_eq!(DeviceStats::decode(&stats.encode()[..20]), None);
+
+        // 旧固件的回复没有最后两项
+        stats.rejected_frames = 0;
+        stats.last_error = 0;
+        assert_eq!(DeviceStats::decode(&stats.encode()[..DeviceStats::MIN_LEN]), Some(stats));
+    }
+}
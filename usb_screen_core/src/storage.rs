@@ -0,0 +1,130 @@
+//! Flash分区和自定义开机画面的存储格式。
+//!
+//! 2M Flash的分区:
+//!
+//! | 偏移        | 大小  | 内容                       |
+//! |-------------|-------|----------------------------|
+//! | 0           | 768K  | 固件                       |
+//! | 768K        | 768K  | 固件升级暂存区(见update模块) |
+//! | 1536K       | 504K  | 自定义开机画面               |
+//! | 2040K       | 4K    | 固件升级状态                 |
+//! | 2044K       | 4K    | 设置                       |
+//!
+//! 开机画面格式: 头部(20字节) + 若干帧，每帧为 长度(u32 BE) + lz4压缩的RGB565 BE数据(compress_prepend_size)。
+
+use alloc::vec::Vec;
+
+use crate::protocol::{magic_number, MAGIC_NUM_LEN};
+
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+//擦除的最小单位
+pub const SECTOR_SIZE: usize = 4096;
+pub const FIRMWARE_CAPACITY: usize = 768 * 1024;
+pub const STAGING_OFFSET: usize = FIRMWARE_CAPACITY;
+pub const STAGING_CAPACITY: usize = FIRMWARE_CAPACITY;
+pub const SPLASH_OFFSET: usize = STAGING_OFFSET + STAGING_CAPACITY;
+pub const UPDATE_STATE_OFFSET: usize = FLASH_SIZE - 2 * SECTOR_SIZE;
+pub const SETTINGS_OFFSET: usize = FLASH_SIZE - SECTOR_SIZE;
+pub const SPLASH_CAPACITY: usize = UPDATE_STATE_OFFSET - SPLASH_OFFSET;
+
+pub const SPLASH_MAGIC: u64 = u64::from_be_bytes(*b"ScrSplsh");
+
+/// 开机画面头部: 魔数 + x + y + 宽 + 高 + 帧数 + 帧间隔(毫秒)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplashHeader{
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub frames: u16,
+    pub delay_ms: u16,
+}
+
+impl SplashHeader{
+    pub const LEN: usize = MAGIC_NUM_LEN + 12;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&SPLASH_MAGIC.to_be_bytes());
+        for (i, v) in [self.x, self.y, self.width, self.height, self.frames, self.delay_ms].iter().enumerate(){
+            buf[8+i*2..10+i*2].copy_from_slice(&v.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != SPLASH_MAGIC{
+            return None;
+        }
+        let read = |i: usize| u16::from_be_bytes([data[8+i*2], data[9+i*2]]);
+        let header = Self{
+            x: read(0),
+            y: read(1),
+            width: read(2),
+            height: read(3),
+            frames: read(4),
+            delay_ms: read(5),
+        };
+        if header.width == 0 || header.height == 0 || header.frames == 0{
+            return None;
+        }
+        Some(header)
+    }
+
+    /// 一帧解压后的字节数
+    pub fn frame_bytes(&self) -> usize{
+        self.width as usize * self.height as usize * 2
+    }
+}
+
+/// 把头部和压缩后的各帧拼接成写入Flash的数据
+pub fn encode_splash(header: &SplashHeader, frames: &[&[u8]]) -> Vec<u8>{
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&header.encode());
+    for frame in frames{
+        buf.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        buf.extend_from_slice(frame);
+    }
+    buf
+}
+
+/// 解析开机画面，数据无效时返回None。帧数据超出范围时只返回完整的帧
+pub fn decode_splash(data: &[u8]) -> Option<(SplashHeader, Vec<&[u8]>)>{
+    let header = SplashHeader::decode(data)?;
+    let mut frames = Vec::new();
+    let mut offset = SplashHeader::LEN;
+    while frames.len() < header.frames as usize && offset + 4 <= data.len(){
+        let len = u32::from_be_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]]) as usize;
+        offset += 4;
+        if len > data.len() - offset{
+            break;
+        }
+        frames.push(&data[offset..offset+len]);
+        offset += len;
+    }
+    if frames.is_empty(){
+        return None;
+    }
+    Some((header, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splash_round_trip() {
+        let header = SplashHeader { x: 0, y: 10, width: 16, height: 8, frames: 2, delay_ms: 100 };
+        let data = encode_splash(&header, &[&[1, 2, 3], &[4, 5]]);
+        let (decoded, frames) = decode_splash(&data).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(frames, [&[1u8, 2, 3][..], &[4, 5][..]]);
+
+        // 最后一帧被截断时只保留完整的帧
+        let (_, frames) = decode_splash(&data[..data.len() - 1]).unwrap();
+        assert_eq!(frames.len(), 1);
+
+        // 擦除后的Flash
+        assert!(decode_splash(&[0xFF; 64]).is_none());
+    }
+}
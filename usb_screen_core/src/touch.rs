@@ -0,0 +1,136 @@
+//! 电阻触摸屏(XPT2046)的校准和触摸事件。
+//!
+//! 校准使用仿射变换: x = a*rx + b*ry + c, y = d*rx + e*ry + f，其中rx、ry为XPT2046的12位原始值。
+//! 主机在屏幕上显示3个十字，读取触摸事件中的原始值，用 Calibration::from_points 计算后通过SetConfg写入设备。
+
+// 主机上运行测试时会使用std中f32的方法
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use crate::protocol::{magic_number, MAGIC_NUM_LEN, TOUCH_EVENT};
+
+/// 原始值到屏幕坐标的仿射变换
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration{
+    pub matrix: [f32; 6],
+}
+
+impl Calibration{
+    /// 未校准时使用的默认值：原始值200~3900线性映射到整个屏幕
+    pub fn default_for(width: u16, height: u16) -> Self{
+        let sx = width as f32 / 3700.;
+        let sy = height as f32 / 3700.;
+        Self { matrix: [sx, 0., -200. * sx, 0., sy, -200. * sy] }
+    }
+
+    /// 用3个点计算校准参数，3个原始点共线时返回None
+    pub fn from_points(raw: [(f32, f32); 3], screen: [(f32, f32); 3]) -> Option<Self>{
+        let [(x0, y0), (x1, y1), (x2, y2)] = raw;
+        let det = x0 * (y1 - y2) - x1 * (y0 - y2) + x2 * (y0 - y1);
+        if det.abs() < 1e-3{
+            return None;
+        }
+        //克莱姆法则分别求解x和y的3个系数
+        let solve = |s0: f32, s1: f32, s2: f32| [
+            (s0 * (y1 - y2) - s1 * (y0 - y2) + s2 * (y0 - y1)) / det,
+            (x0 * (s1 - s2) - x1 * (s0 - s2) + x2 * (s0 - s1)) / det,
+            (x0 * (y1 * s2 - y2 * s1) - x1 * (y0 * s2 - y2 * s0) + x2 * (y0 * s1 - y1 * s0)) / det,
+        ];
+        let [a, b, c] = solve(screen[0].0, screen[1].0, screen[2].0);
+        let [d, e, f] = solve(screen[0].1, screen[1].1, screen[2].1);
+        Some(Self { matrix: [a, b, c, d, e, f] })
+    }
+
+    /// 保存在设置中的6个值(f32的二进制)，全为0表示未校准
+    pub fn from_bits(bits: [u32; 6]) -> Option<Self>{
+        if bits.iter().all(|&b| b == 0){
+            return None;
+        }
+        let matrix = bits.map(f32::from_bits);
+        if matrix.iter().any(|v| !v.is_finite()){
+            return None;
+        }
+        Some(Self { matrix })
+    }
+
+    pub fn to_bits(&self) -> [u32; 6]{
+        self.matrix.map(f32::to_bits)
+    }
+
+    /// 原始值转换为屏幕坐标，超出屏幕的部分限制在边缘
+    pub fn apply(&self, raw_x: u16, raw_y: u16, width: u16, height: u16) -> (u16, u16){
+        let [a, b, c, d, e, f] = self.matrix;
+        let (rx, ry) = (raw_x as f32, raw_y as f32);
+        let x = (a * rx + b * ry + c).round().clamp(0., (width - 1) as f32);
+        let y = (d * rx + e * ry + f).round().clamp(0., (height - 1) as f32);
+        (x as u16, y as u16)
+    }
+}
+
+/// 触摸事件: 魔数 + 是否按下 + 屏幕坐标(x,y) + 原始值(x,y) + 压力，设备通过USB Raw的IN端点发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchEvent{
+    pub pressed: bool,
+    pub x: u16,
+    pub y: u16,
+    pub raw_x: u16,
+    pub raw_y: u16,
+    pub pressure: u16,
+}
+
+impl TouchEvent{
+    pub const LEN: usize = MAGIC_NUM_LEN + 12;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&TOUCH_EVENT.to_be_bytes());
+        for (i, v) in [self.pressed as u16, self.x, self.y, self.raw_x, self.raw_y, self.pressure].iter().enumerate(){
+            buf[8+i*2..10+i*2].copy_from_slice(&v.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != TOUCH_EVENT{
+            return None;
+        }
+        let read = |i: usize| u16::from_be_bytes([data[8+i*2], data[9+i*2]]);
+        Some(Self{
+            pressed: read(0) != 0,
+            x: read(1),
+            y: read(2),
+            raw_x: read(3),
+            raw_y: read(4),
+            pressure: read(5),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_from_points() {
+        // 原始值的x、y交换且y方向相反
+        let raw = [(400., 3600.), (3600., 3600.), (2000., 400.)];
+        let screen = [(20., 20.), (20., 220.), (300., 120.)];
+        let cal = Calibration::from_points(raw, screen).unwrap();
+        assert_eq!(cal.apply(400, 3600, 320, 240), (20, 20));
+        assert_eq!(cal.apply(3600, 3600, 320, 240), (20, 220));
+        assert_eq!(cal.apply(2000, 400, 320, 240), (300, 120));
+        // 超出屏幕
+        assert_eq!(cal.apply(2000, 0, 320, 240).0, 319);
+
+        assert_eq!(Calibration::from_bits(cal.to_bits()), Some(cal));
+        assert_eq!(Calibration::from_bits([0; 6]), None);
+        assert!(Calibration::from_points([(0., 0.), (1., 1.), (2., 2.)], screen).is_none());
+    }
+
+    #[test]
+    fn touch_event_round_trip() {
+        let event = TouchEvent { pressed: true, x: 10, y: 200, raw_x: 1234, raw_y: 3000, pressure: 80 };
+        assert_eq!(TouchEvent::decode(&event.encode()), Some(event));
+        assert_eq!(TouchEvent::decode(&event.encode()[..10]), None);
+    }
+}
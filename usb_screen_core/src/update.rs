@@ -0,0 +1,326 @@
+//! 通过USB升级固件。
+//!
+//! 1. 主机发送FwUpdate(长度、CRC32)，设备清除旧的升级状态
+//! 2. 主机发送固件数据(从0x10000000开始的bin文件)，设备按扇区擦除暂存区并写入
+//! 3. 主机发送IMAGE_BB，设备读回暂存区校验CRC32，校验通过后写入升级状态并重启
+//! 4. 重启后固件在RAM中运行的代码把暂存区复制到固件区，清除升级状态后再次重启
+//!
+//! 这里的代码只依赖embedded-storage的NorFlash，可以在主机上使用内存模拟的Flash测试。
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::protocol::{magic_number, MAGIC_NUM_LEN};
+use crate::storage::{SECTOR_SIZE, STAGING_CAPACITY, STAGING_OFFSET, UPDATE_STATE_OFFSET};
+
+pub const UPDATE_MAGIC: u64 = u64::from_be_bytes(*b"ScrUpdt1");
+
+//每次写入Flash的大小
+pub const PAGE_SIZE: usize = 256;
+
+/// CRC-32(IEEE)，与zlib的crc32相同
+#[derive(Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Default for Crc32{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl Crc32{
+    pub const fn new() -> Self{
+        Self(0xFFFF_FFFF)
+    }
+
+    pub fn update(&mut self, data: &[u8]){
+        for &b in data{
+            self.0 ^= b as u32;
+            for _ in 0..8{
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32{
+        !self.0
+    }
+
+    pub fn checksum(data: &[u8]) -> u32{
+        let mut crc = Self::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateError{
+    //固件超过暂存区大小
+    TooLarge = 1,
+    //擦除或写入Flash失败
+    Flash = 2,
+    //收到的数据长度与FwUpdate中的长度不一致
+    Length = 3,
+    //读回的数据CRC32不一致
+    Crc = 4,
+    //没有收到FwUpdate
+    NotStarted = 5,
+}
+
+impl UpdateError{
+    pub fn from_code(code: u16) -> Option<Self>{
+        match code{
+            1 => Some(UpdateError::TooLarge),
+            2 => Some(UpdateError::Flash),
+            3 => Some(UpdateError::Length),
+            4 => Some(UpdateError::Crc),
+            5 => Some(UpdateError::NotStarted),
+            _ => None,
+        }
+    }
+}
+
+/// 升级状态，保存在UPDATE_STATE_OFFSET: 魔数 + 长度(u32 BE) + CRC32(u32 BE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateState{
+    pub len: u32,
+    pub crc: u32,
+}
+
+impl UpdateState{
+    pub const LEN: usize = MAGIC_NUM_LEN + 8;
+
+    pub fn encode(&self) -> [u8; Self::LEN]{
+        let mut buf = [0u8; Self::LEN];
+        buf[0..8].copy_from_slice(&UPDATE_MAGIC.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.len.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.crc.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self>{
+        if data.len() < Self::LEN || magic_number(data)? != UPDATE_MAGIC{
+            return None;
+        }
+        let len = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        if len == 0 || len as usize > STAGING_CAPACITY{
+            return None;
+        }
+        Some(Self{
+            len,
+            crc: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
+        })
+    }
+}
+
+/// 读取等待应用的升级状态
+pub fn pending_update<F: NorFlash>(flash: &mut F) -> Option<UpdateState>{
+    let mut buf = [0u8; PAGE_SIZE];
+    flash.read(UPDATE_STATE_OFFSET as u32, &mut buf).ok()?;
+    UpdateState::decode(&buf)
+}
+
+/// 清除升级状态
+pub fn clear_pending<F: NorFlash>(flash: &mut F) -> Result<(), UpdateError>{
+    let offset = UPDATE_STATE_OFFSET as u32;
+    flash.erase(offset, offset + SECTOR_SIZE as u32).map_err(|_| UpdateError::Flash)
+}
+
+/// 计算暂存区中前len字节的CRC32
+pub fn staging_crc<F: NorFlash>(flash: &mut F, len: u32) -> Result<u32, UpdateError>{
+    let mut crc = Crc32::new();
+    let mut buf = [0u8; PAGE_SIZE];
+    let mut offset = 0;
+    while offset < len as usize{
+        flash.read((STAGING_OFFSET + offset) as u32, &mut buf).map_err(|_| UpdateError::Flash)?;
+        let n = PAGE_SIZE.min(len as usize - offset);
+        crc.update(&buf[..n]);
+        offset += n;
+    }
+    Ok(crc.finish())
+}
+
+/// 把接收到的固件写入暂存区
+pub struct FirmwareUpdater{
+    state: UpdateState,
+    //已接收的字节数
+    received: usize,
+    page: [u8; PAGE_SIZE],
+    page_len: usize,
+    //已写入暂存区的字节数(整页)
+    flushed: usize,
+    //暂存区中已擦除的字节数
+    erased: usize,
+}
+
+impl FirmwareUpdater{
+    pub fn begin<F: NorFlash>(flash: &mut F, len: u32, crc: u32) -> Result<Self, UpdateError>{
+        if len == 0 || len as usize > STAGING_CAPACITY{
+            return Err(UpdateError::TooLarge);
+        }
+        clear_pending(flash)?;
+        Ok(Self{
+            state: UpdateState { len, crc },
+            received: 0,
+            page: [0xFF; PAGE_SIZE],
+            page_len: 0,
+            flushed: 0,
+            erased: 0,
+        })
+    }
+
+    pub fn write<F: NorFlash>(&mut self, flash: &mut F, mut data: &[u8]) -> Result<(), UpdateError>{
+        if self.received + data.len() > self.state.len as usize{
+            return Err(UpdateError::Length);
+        }
+        self.received += data.len();
+        while !data.is_empty(){
+            let n = data.len().min(PAGE_SIZE - self.page_len);
+            self.page[self.page_len..self.page_len + n].copy_from_slice(&data[..n]);
+            self.page_len += n;
+            data = &data[n..];
+            if self.page_len == PAGE_SIZE{
+                self.flush_page(flash)?;
+            }
+        }
+        Ok(())
+    }
+
+    //写入一页，剩余部分填充0xFF
+    fn flush_page<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), UpdateError>{
+        let offset = self.flushed;
+        if offset + PAGE_SIZE > self.erased{
+            let from = (STAGING_OFFSET + self.erased) as u32;
+            flash.erase(from, from + SECTOR_SIZE as u32).map_err(|_| UpdateError::Flash)?;
+            self.erased += SECTOR_SIZE;
+        }
+        self.page[self.page_len..].fill(0xFF);
+        flash.write((STAGING_OFFSET + offset) as u32, &self.page).map_err(|_| UpdateError::Flash)?;
+        self.page_len = 0;
+        self.flushed += PAGE_SIZE;
+        Ok(())
+    }
+
+    /// 写入剩余数据，校验CRC32后写入升级状态，返回Ok后重启即可应用升级
+    pub fn finish<F: NorFlash>(mut self, flash: &mut F) -> Result<UpdateState, UpdateError>{
+        if self.received != self.state.len as usize{
+            return Err(UpdateError::Length);
+        }
+        if self.page_len > 0{
+            self.flush_page(flash)?;
+        }
+        if staging_crc(flash, self.state.len)? != self.state.crc{
+            return Err(UpdateError::Crc);
+        }
+        let mut page = [0xFF; PAGE_SIZE];
+        page[..UpdateState::LEN].copy_from_slice(&self.state.encode());
+        flash.write(UPDATE_STATE_OFFSET as u32, &page).map_err(|_| UpdateError::Flash)?;
+        Ok(self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FLASH_SIZE;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use embedded_storage::nor_flash::{check_erase, check_write, ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    /// 内存模拟的NorFlash：擦除后为0xFF，写入只能把1变成0
+    struct MemFlash {
+        data: Vec<u8>,
+        erases: usize,
+    }
+
+    impl MemFlash {
+        fn new() -> Self {
+            Self { data: vec![0xFF; FLASH_SIZE], erases: 0 }
+        }
+    }
+
+    impl ErrorType for MemFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MemFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            let src = self.data.get(offset..offset + bytes.len()).ok_or(NorFlashErrorKind::OutOfBounds)?;
+            bytes.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MemFlash {
+        const WRITE_SIZE: usize = PAGE_SIZE;
+        const ERASE_SIZE: usize = SECTOR_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.data[from as usize..to as usize].fill(0xFF);
+            self.erases += 1;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            for (dst, src) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                *dst &= src;
+            }
+            Ok(())
+        }
+    }
+
+    fn firmware(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(Crc32::checksum(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn update_writes_staging_and_state() {
+        let mut flash = MemFlash::new();
+        // 旧的暂存区数据必须被擦除
+        flash.data[STAGING_OFFSET..STAGING_OFFSET + 10000].fill(0);
+        let image = firmware(SECTOR_SIZE * 2 + 1000);
+        let mut updater = FirmwareUpdater::begin(&mut flash, image.len() as u32, Crc32::checksum(&image)).unwrap();
+        // 不对齐的包大小
+        for chunk in image.chunks(61) {
+            updater.write(&mut flash, chunk).unwrap();
+        }
+        let state = updater.finish(&mut flash).unwrap();
+        assert_eq!(&flash.data[STAGING_OFFSET..STAGING_OFFSET + image.len()], &image[..]);
+        assert_eq!(flash.erases, 1 + 3);
+        assert_eq!(pending_update(&mut flash), Some(state));
+        clear_pending(&mut flash).unwrap();
+        assert_eq!(pending_update(&mut flash), None);
+    }
+
+    #[test]
+    fn update_rejects_bad_crc_and_length() {
+        let mut flash = MemFlash::new();
+        let image = firmware(1000);
+        let mut updater = FirmwareUpdater::begin(&mut flash, image.len() as u32, 0x1234).unwrap();
+        updater.write(&mut flash, &image).unwrap();
+        assert_eq!(updater.finish(&mut flash).err(), Some(UpdateError::Crc));
+        assert_eq!(pending_update(&mut flash), None);
+
+        let mut updater = FirmwareUpdater::begin(&mut flash, 10, 0).unwrap();
+        assert_eq!(updater.write(&mut flash, &image[..11]), Err(UpdateError::Length));
+        updater.write(&mut flash, &image[..9]).unwrap();
+        assert_eq!(updater.finish(&mut flash).err(), Some(UpdateError::Length));
+
+        let too_large = STAGING_CAPACITY as u32 + 1;
+        assert_eq!(FirmwareUpdater::begin(&mut flash, too_large, 0).err(), Some(UpdateError::TooLarge));
+    }
+}